@@ -1,26 +1,248 @@
 #include <stdint.h>
 
-#define EPERM  1
-#define ENOENT 2
+/* Linux errno values, returned negated by the operations */
+#define EPERM           1
+#define ENOENT          2
+#define ESRCH           3
+#define EINTR           4
+#define EIO             5
+#define ENXIO           6
+#define E2BIG           7
+#define ENOEXEC         8
+#define EBADF           9
+#define ECHILD          10
+#define EAGAIN          11
+#define ENOMEM          12
+#define EACCES          13
+#define EFAULT          14
+#define ENOTBLK         15
+#define EBUSY           16
+#define EEXIST          17
+#define EXDEV           18
+#define ENODEV          19
+#define ENOTDIR         20
+#define EISDIR          21
+#define EINVAL          22
+#define ENFILE          23
+#define EMFILE          24
+#define ENOTTY          25
+#define ETXTBSY         26
+#define EFBIG           27
+#define ENOSPC          28
+#define ESPIPE          29
+#define EROFS           30
+#define EMLINK          31
+#define EPIPE           32
+#define EDOM            33
+#define ERANGE          34
+#define EDEADLK         35
+#define ENAMETOOLONG    36
+#define ENOLCK          37
+#define ENOSYS          38
+#define ENOTEMPTY       39
+#define ELOOP           40
+#define ENOMSG          42
+#define EIDRM           43
+#define ECHRNG          44
+#define EL2NSYNC        45
+#define EL3HLT          46
+#define EL3RST          47
+#define ELNRNG          48
+#define EUNATCH         49
+#define ENOCSI          50
+#define EL2HLT          51
+#define EBADE           52
+#define EBADR           53
+#define EXFULL          54
+#define ENOANO          55
+#define EBADRQC         56
+#define EBADSLT         57
+#define EBFONT          59
+#define ENOSTR          60
+#define ENODATA         61
+#define ETIME           62
+#define ENOSR           63
+#define ENONET          64
+#define ENOPKG          65
+#define EREMOTE         66
+#define ENOLINK         67
+#define EADV            68
+#define ESRMNT          69
+#define ECOMM           70
+#define EPROTO          71
+#define EMULTIHOP       72
+#define EDOTDOT         73
+#define EBADMSG         74
+#define EOVERFLOW       75
+#define ENOTUNIQ        76
+#define EBADFD          77
+#define EREMCHG         78
+#define ELIBACC         79
+#define ELIBBAD         80
+#define ELIBSCN         81
+#define ELIBMAX         82
+#define ELIBEXEC        83
+#define EILSEQ          84
+#define ERESTART        85
+#define ESTRPIPE        86
+#define EUSERS          87
+#define ENOTSOCK        88
+#define EDESTADDRREQ    89
+#define EMSGSIZE        90
+#define EPROTOTYPE      91
+#define ENOPROTOOPT     92
+#define EPROTONOSUPPORT 93
+#define ESOCKTNOSUPPORT 94
+#define EOPNOTSUPP      95
+#define EPFNOSUPPORT    96
+#define EAFNOSUPPORT    97
+#define EADDRINUSE      98
+#define EADDRNOTAVAIL   99
+#define ENETDOWN        100
+#define ENETUNREACH     101
+#define ENETRESET       102
+#define ECONNABORTED    103
+#define ECONNRESET      104
+#define ENOBUFS         105
+#define EISCONN         106
+#define ENOTCONN        107
+#define ESHUTDOWN       108
+#define ETOOMANYREFS    109
+#define ETIMEDOUT       110
+#define ECONNREFUSED    111
+#define EHOSTDOWN       112
+#define EHOSTUNREACH    113
+#define EALREADY        114
+#define EINPROGRESS     115
+#define ESTALE          116
+#define EUCLEAN         117
+#define ENOTNAM         118
+#define ENAVAIL         119
+#define EISNAM          120
+#define EREMOTEIO       121
+#define EDQUOT          122
+#define ENOMEDIUM       123
+#define EMEDIUMTYPE     124
+#define ECANCELED       125
+#define ENOKEY          126
+#define EKEYEXPIRED     127
+#define EKEYREVOKED     128
+#define EKEYREJECTED    129
+#define EOWNERDEAD      130
+#define ENOTRECOVERABLE 131
+#define ERFKILL         132
+#define EHWPOISON       133
+#define EWOULDBLOCK     EAGAIN
+#define EDEADLOCK       EDEADLK
+#define ENOTSUP         EOPNOTSUPP
+
+#define F_OK 0
+#define X_OK 1
+#define W_OK 2
+#define R_OK 4
+
+#define RENAME_NOREPLACE (1 << 0)
+#define RENAME_EXCHANGE  (1 << 1)
 
 const uint16_t S_IFMT  = 0170000;
 const uint16_t S_IFDIR = 0040000;
 const uint16_t S_IFREG = 0100000;
 const uint16_t S_IFLNK = 0120000;
 
+#define MOUNTBOX_STAT_VERSION 1
+
+/* Bits of stat.mask. Those up to MOUNTBOX_STAT_BLOCKS match the STATX_* values, the others
+   have no STATX_* counterpart */
+#define MOUNTBOX_STAT_TYPE    0x0001
+#define MOUNTBOX_STAT_MODE    0x0002
+#define MOUNTBOX_STAT_NLINK   0x0004
+#define MOUNTBOX_STAT_UID     0x0008
+#define MOUNTBOX_STAT_GID     0x0010
+#define MOUNTBOX_STAT_ATIME   0x0020
+#define MOUNTBOX_STAT_MTIME   0x0040
+#define MOUNTBOX_STAT_CTIME   0x0080
+#define MOUNTBOX_STAT_INO     0x0100
+#define MOUNTBOX_STAT_SIZE    0x0200
+#define MOUNTBOX_STAT_BLOCKS  0x0400
+#define MOUNTBOX_STAT_BLKSIZE 0x10000000
+#define MOUNTBOX_STAT_RDEV    0x20000000
+/* What a plugin leaving mask at 0 is assumed to provide */
+#define MOUNTBOX_STAT_LEGACY  (MOUNTBOX_STAT_TYPE | MOUNTBOX_STAT_SIZE | MOUNTBOX_STAT_ATIME | MOUNTBOX_STAT_MTIME | MOUNTBOX_STAT_CTIME)
+
+struct mountbox_timespec {
+  int64_t sec;
+  int64_t nsec;
+};
+
+/* Zeroed with version set to MOUNTBOX_STAT_VERSION before being handed to getattr.
+   Fields missing from mask are made up by mountbox. */
 struct stat {
+  uint32_t version;
+  uint32_t mask;
   uint64_t size;
   uint16_t mode;
-  int64_t  atime;
-  int64_t  mtime;
-  int64_t  ctime;
+  uint32_t uid;
+  uint32_t gid;
+  uint64_t ino;
+  uint64_t nlink;
+  uint64_t blocks;
+  uint32_t blksize;
+  uint64_t rdev;
+  struct mountbox_timespec atime;
+  struct mountbox_timespec mtime;
+  struct mountbox_timespec ctime;
 };
 
+#define MOUNTBOX_ABI_VERSION 1
+
+/* Bits of mountbox_plugin_info.capabilities, for behaviours plugins opt into */
+/* rename honours RENAME_NOREPLACE and RENAME_EXCHANGE. Renames with flags fail with EINVAL otherwise */
+#define MOUNTBOX_CAP_RENAME_FLAGS   (1 << 0)
+/* open truncates the files opened with O_TRUNC. truncate is called after it otherwise */
+#define MOUNTBOX_CAP_ATOMIC_O_TRUNC (1 << 1)
+
+typedef int (*mountbox_fill_dir_t)(void * buf, const char * name, const struct stat * stat);
+
+/* Operations left NULL fail with ENOSYS */
 struct mountbox_operations {
-  int (*open)(const char * path);
+  int (*open)(const char * path, int32_t flags, uint64_t * fh);
   int (*close)(const char * path, uint64_t fh);
   int (*read)(const char * path, char * buf, uint64_t size, int64_t offset, uint64_t fh);
   int (*getattr)(const char * path, struct stat * stat);
+  int (*opendir)(const char * path, uint64_t * fh);
+  int (*readdir)(const char * path, void * buf, mountbox_fill_dir_t filler, int64_t offset, uint64_t fh);
+  int (*releasedir)(const char * path, uint64_t fh);
+  int (*write)(const char * path, const char * buf, uint64_t size, int64_t offset, uint64_t fh);
+  int (*create)(const char * path, uint32_t mode);
+  int (*truncate)(const char * path, int64_t size);
+  int (*mkdir)(const char * path, uint32_t mode);
+  int (*rmdir)(const char * path);
+  int (*unlink)(const char * path);
+  int (*rename)(const char * from, const char * to, uint32_t flags);
+  int (*symlink)(const char * target, const char * path);
+  int (*link)(const char * from, const char * to);
+  int (*readlink)(const char * path, char * buf, uint64_t size);
+  int (*access)(const char * path, int32_t mask);
+  /* Called for each mount before any other operation, with its key=value options as a
+     NULL-terminated array. What is stored in private_data is what mountbox_private_data
+     returns during the operations of that mount. */
+  int (*init)(const char *const * opts, void ** private_data);
+  /* Called with the private data of a mount once it is no longer used */
+  void (*destroy)(void * private_data);
+};
+
+/* The private data init stored for the mount the running operation is made on */
+void * mountbox_private_data(void);
+
+/* What a plugin exports, as mountbox_plugin_info unless another symbol is given */
+struct mountbox_plugin_info {
+  /* MOUNTBOX_ABI_VERSION of the header the plugin was built with */
+  uint32_t abi_version;
+  /* sizeof(struct mountbox_operations) of that header, operations past it being missing */
+  uint32_t operations_size;
+  uint64_t capabilities;
+  const struct mountbox_operations * operations;
 };
 
-static struct mountbox_operations operations;
\ No newline at end of file
+#define MOUNTBOX_PLUGIN(name, ops, caps) \
+  const struct mountbox_plugin_info name = { MOUNTBOX_ABI_VERSION, sizeof(struct mountbox_operations), caps, &(ops) }
\ No newline at end of file
@@ -1,11 +1,63 @@
-use nix::{fcntl::readlink, libc::AT_FDCWD, unistd::Pid};
-use typed_path::NativePathBuf;
+use nix::{errno::Errno, fcntl::readlink, libc::{self, AT_FDCWD}, unistd::Pid};
+use typed_path::{NativePath, NativePathBuf, UnixComponent};
+use crate::{mounts::Mounts, process::FdTable};
 
-pub fn resolve(pid: Pid, dirfd: i32, path: &str) -> NativePathBuf {
-  if dirfd == AT_FDCWD {
-    NativePathBuf::from(path)
+fn dirpath(fds: &FdTable, pid: Pid, dirfd: i32) -> Result<NativePathBuf, Errno> {
+  if dirfd < 0 {
+    return Err(Errno::EBADF);
+  }
+  if let Some(file) = fds.get(dirfd as u16) {
+    let fd_info = file.read().unwrap();
+    return Ok(fd_info.mountpath.join(fd_info.path.as_str().trim_start_matches('/')));
+  }
+  let dirpath = readlink(format!("/proc/{}/fd/{}", pid.as_raw(), dirfd).as_str())
+    .map_err(|e| if matches!(e, Errno::ENOENT) { Errno::EBADF } else { e })?;
+  Ok(NativePathBuf::from(dirpath.as_encoded_bytes()))
+}
+
+pub fn resolve(fds: &FdTable, pid: Pid, dirfd: i32, path: &str) -> Result<NativePathBuf, Errno> {
+  if dirfd == AT_FDCWD || NativePath::new(path).is_absolute() {
+    Ok(NativePathBuf::from(path))
   } else {
-    let dirpath = NativePathBuf::from(readlink(format!("/proc/{}/fd/{}", pid.as_raw(), dirfd).as_str()).unwrap().as_encoded_bytes());
-    dirpath.join(path)
+    Ok(dirpath(fds, pid, dirfd)?.join(path))
+  }
+}
+
+/// Resolves `path` the way openat2 does for the `RESOLVE_BENEATH`, `RESOLVE_IN_ROOT` and
+/// `RESOLVE_NO_XDEV` flags. Symlinks are not followed inside mounts, so the remaining flags
+/// need no handling here.
+pub fn resolve_openat2(mounts: &Mounts, fds: &FdTable, pid: Pid, cwd: &NativePath, dirfd: i32, path: &str, resolve: u64) -> Result<NativePathBuf, Errno> {
+  let path = NativePath::new(path);
+  let base = if dirfd == AT_FDCWD { cwd.to_path_buf() } else { dirpath(fds, pid, dirfd)? };
+  let scoped = resolve & (libc::RESOLVE_BENEATH | libc::RESOLVE_IN_ROOT) != 0;
+  if resolve & libc::RESOLVE_BENEATH != 0 && path.is_absolute() {
+    return Err(Errno::EXDEV);
+  }
+  let base_mount = mounts.get_mount_of_path(&base).map(|m| m.path.clone());
+  let crosses_mount = |path: &NativePath| {
+    resolve & libc::RESOLVE_NO_XDEV != 0 && mounts.get_mount_of_path(path).map(|m| m.path.clone()) != base_mount
+  };
+  let mut resolved = if path.is_absolute() && !scoped { NativePathBuf::from("/") } else { base.clone() };
+  if crosses_mount(&resolved) {
+    return Err(Errno::EXDEV);
+  }
+  for component in path.components() {
+    match component {
+      UnixComponent::RootDir | UnixComponent::CurDir => continue,
+      UnixComponent::ParentDir => {
+        if scoped && resolved == base {
+          if resolve & libc::RESOLVE_BENEATH != 0 {
+            return Err(Errno::EXDEV);
+          }
+          continue;
+        }
+        resolved.pop();
+      },
+      UnixComponent::Normal(name) => resolved.push(name)
+    }
+    if crosses_mount(&resolved) {
+      return Err(Errno::EXDEV);
+    }
   }
-}
\ No newline at end of file
+  Ok(resolved)
+}
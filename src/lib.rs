@@ -1,5 +1,6 @@
 pub mod tracer;
 pub mod state;
 pub mod mounts;
+pub mod process;
 pub mod dirfd_resolver;
 pub mod plugin;
\ No newline at end of file
@@ -1,33 +1,186 @@
-use std::{os::unix::process::CommandExt, process::{exit, Command, ExitCode}, sync::{Arc, OnceLock, RwLock}};
+use std::{os::unix::process::CommandExt, path::Path, process::{exit, Command, ExitCode}, sync::{atomic::{AtomicI32, Ordering}, Arc, RwLock}};
 use anyhow::{anyhow, Result};
-use dlopen::symbor::Library;
-use mountbox::{mounts::Mounts, plugin::Plugin, tracer, state::State};
-use nix::{libc, unistd::{fork, ForkResult, Pid}};
-use clap::Parser;
+use mountbox::{mounts::Mounts, plugin::{remote::{self, Remote}, Libraries, Plugin}, tracer, state::State};
+use nix::{libc, sys::signal::{raise, sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal}, unistd::{fork, ForkResult}};
+use clap::{Parser, ValueEnum};
 use typed_path::NativePathBuf;
 
-fn multipath_parser<const N: usize>(value: &str) -> Result<[String; N]> {
-  value.splitn(N, ':').map(|p| {
-    p.to_string()
-  }).collect::<Vec<String>>().try_into().map_err(|_| anyhow!("Missing dir or socket path"))
+/// A plugin mounted on a dir, as given by `DIR:PLUGIN[:SYMBOL][,key=value...]`.
+#[derive(Clone)]
+struct Bind {
+  dir: String,
+  plugin: String,
+  symbol: Option<String>,
+  opts: Vec<String>
+}
+
+/// Splits the `,key=value...` options off `value`.
+fn opts_parser(value: &str) -> Result<(&str, Vec<String>)> {
+  let mut opts = value.split(',');
+  let paths = opts.next().unwrap_or_default();
+  let opts: Vec<String> = opts.map(str::to_string).collect();
+  if let Some(opt) = opts.iter().find(|opt| !opt.contains('=')) {
+    return Err(anyhow!("Option {opt} is not key=value"));
+  }
+  Ok((paths, opts))
+}
+
+fn bind_parser(value: &str) -> Result<Bind> {
+  let (paths, opts) = opts_parser(value)?;
+  let mut paths = paths.splitn(3, ':').map(str::to_string);
+  let (Some(dir), Some(plugin)) = (paths.next(), paths.next()) else {
+    return Err(anyhow!("Missing dir or plugin path"));
+  };
+  Ok(Bind { dir, plugin, symbol: paths.next().filter(|symbol| !symbol.is_empty()), opts })
+}
+
+/// A daemon mounted on a dir, as given by `DIR:SOCKET[,key=value...]`.
+#[derive(Clone)]
+struct Connect {
+  dir: String,
+  socket: String,
+  opts: Vec<String>
+}
+
+fn connect_parser(value: &str) -> Result<Connect> {
+  let (paths, opts) = opts_parser(value)?;
+  let Some((dir, socket)) = paths.split_once(':') else {
+    return Err(anyhow!("Missing dir or socket path"));
+  };
+  Ok(Connect { dir: dir.to_string(), socket: socket.to_string(), opts })
 }
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-  #[arg(short='u', long, value_name="DIR:PLUGIN_PATH", num_args=1.., value_parser=multipath_parser::<2>)]
-  bind: Option<Vec<[String; 2]>>,
+  /// Mounts the plugin exported as SYMBOL (mountbox_plugin_info by default) by the library at PLUGIN on
+  /// DIR, setting it up with the options following it
+  #[arg(short='u', long, value_name="DIR:PLUGIN[:SYMBOL][,key=value...]", num_args=1.., value_parser=bind_parser)]
+  bind: Option<Vec<Bind>>,
+
+  /// Mounts the plugin served by the daemon listening at SOCKET on DIR, setting it up with the
+  /// options following it. Operations fail with EIO while the daemon does not answer
+  #[arg(short, long, value_name="DIR:SOCKET[,key=value...]", num_args=1.., value_parser=connect_parser)]
+  connect: Option<Vec<Connect>>,
+
+  /// How syscalls on the mounts are intercepted
+  #[arg(short, long, value_enum, default_value_t = Backend::Ptrace)]
+  backend: Backend,
 
+  /// Command to run. Without CAP_SYS_ADMIN, mountbox runs it with no_new_privs set, so that
+  /// setuid binaries and file capabilities grant it no privileges
   #[arg(last = true, required = true)]
   command: Vec<String>
 }
 
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Backend {
+  /// Trace the command with ptrace
+  Ptrace,
+  /// Serve seccomp user notifications, leaving the command free to be traced by debuggers.
+  /// Files on the mounts cannot be executed, and symlinks on the mounts cannot lead out of them
+  Notify
+}
+
+/// Signals ending a process that mountbox passes on to the traced command.
+const FORWARDED_SIGNALS: [Signal; 4] = [Signal::SIGHUP, Signal::SIGINT, Signal::SIGQUIT, Signal::SIGTERM];
+
+/// Process group of the traced command.
+static TRACEE_PGID: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward_signal(signal: libc::c_int) {
+  unsafe { libc::kill(-TRACEE_PGID.load(Ordering::Relaxed), signal) };
+}
+
+fn set_forwarded_signals(handler: SigHandler) {
+  let action = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+  for signal in FORWARDED_SIGNALS {
+    unsafe { sigaction(signal, &action) }.unwrap();
+  }
+}
+
+/// Stops mountbox along with the traced command, for the shell to see the job stopped. Once
+/// continued, resumes the command, handing the terminal back to it in the foreground.
+fn stop_job() {
+  let tracee = TRACEE_PGID.load(Ordering::Relaxed);
+  unsafe {
+    libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+    if libc::tcgetpgrp(libc::STDIN_FILENO) == tracee {
+      libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+    }
+    // Discarded if no shell is there to continue mountbox
+    libc::raise(libc::SIGTSTP);
+    if libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp() {
+      libc::tcsetpgrp(libc::STDIN_FILENO, tracee);
+    }
+    libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+    libc::kill(-tracee, libc::SIGCONT);
+  }
+}
+
+/// Ends mountbox by the signal that killed the traced command, for its parent to see the same
+/// status. Falls back to the exit code a shell reports for it.
+fn exit_by_signal(signal: Signal) -> ExitCode {
+  let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+  if unsafe { sigaction(signal, &action) }.is_ok() {
+    let mut mask = SigSet::empty();
+    mask.add(signal);
+    let _ = mask.thread_unblock();
+    let _ = raise(signal);
+  }
+  ExitCode::from(128 + signal as u8)
+}
+
 fn main() -> ExitCode {
   let args = Cli::parse();
 
+  // Loaded before the command runs, for a bad bind not to leave it behind
+  let libraries = Libraries::default();
+  let mut mountsockets: Vec<(NativePathBuf, Arc<Plugin>)> = vec![];
+  for bind in args.bind.iter().flatten() {
+    let library = match libraries.open(Path::new(&bind.plugin)) {
+      Ok(library) => library,
+      Err(err) => {
+        eprintln!("mountbox: cannot load {}: {err}", bind.plugin);
+        return ExitCode::FAILURE;
+      }
+    };
+    let mut plugin = match Plugin::load_shared(library, bind.symbol.as_deref()) {
+      Ok(plugin) => plugin,
+      Err(err) => {
+        eprintln!("mountbox: cannot load {}: {err}", bind.plugin);
+        return ExitCode::FAILURE;
+      }
+    };
+    let opts: Vec<&str> = bind.opts.iter().map(String::as_str).collect();
+    if let Err(err) = plugin.init(&opts) {
+      eprintln!("mountbox: cannot set {} up on {}: {err}", bind.plugin, bind.dir);
+      return ExitCode::FAILURE;
+    }
+    mountsockets.push((NativePathBuf::from(&bind.dir), Arc::new(plugin)));
+  }
+  for connect in args.connect.iter().flatten() {
+    let opts: Vec<&str> = connect.opts.iter().map(String::as_str).collect();
+    match Remote::connect(&connect.socket, &opts, remote::DEFAULT_TIMEOUT) {
+      Ok(remote) => mountsockets.push((NativePathBuf::from(&connect.dir), Arc::new(Plugin::from_filesystem(Arc::new(remote))))),
+      Err(err) => {
+        eprintln!("mountbox: cannot connect to {}: {err}", connect.socket);
+        return ExitCode::FAILURE;
+      }
+    }
+  }
+
   match unsafe { fork().unwrap() } {
     ForkResult::Child => {
+      // Its own process group, for signals to the group not to reach mountbox
+      unsafe { libc::setpgid(0, 0) };
+      if args.backend == Backend::Notify {
+        tracer::notify::install_filter().unwrap();
+      }
       unsafe { libc::raise(libc::SIGSTOP) };
+      if args.backend == Backend::Ptrace {
+        tracer::install_filter().unwrap();
+      }
       let mut cmd = Command::new(&args.command[0]);
       if args.command.len() > 1 {
         cmd.args(&args.command[1..]);
@@ -37,26 +190,37 @@ fn main() -> ExitCode {
     }
 
     ForkResult::Parent { child } => {
-      let mut mountsockets: Vec<(NativePathBuf, Arc<Plugin>)> = vec![];
-      if let Some(value) = &args.bind {
-        for [dirp, plugin_path] in value {
-          static LIB: OnceLock<Library> = OnceLock::new();
-          LIB.get_or_init(|| Library::open(plugin_path).unwrap());
-          let plugin = Arc::new(Plugin::load(&LIB.get().unwrap(), None));
-          mountsockets.push((NativePathBuf::from(dirp), plugin));
-        }
+      unsafe { libc::setpgid(child.as_raw(), child.as_raw()) };
+      TRACEE_PGID.store(child.as_raw(), Ordering::Relaxed);
+      set_forwarded_signals(SigHandler::Handler(forward_signal));
+      // The command takes the terminal over while mountbox is in the foreground
+      let foreground = unsafe { libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp() };
+      if foreground {
+        unsafe { libc::tcsetpgrp(libc::STDIN_FILENO, child.as_raw()) };
       }
       let state = Arc::new(State {
         mounts: Mounts::new(&mountsockets),
+        libraries,
         cwd: RwLock::new(NativePathBuf::from(std::env::current_dir().unwrap().as_os_str().as_encoded_bytes())),
+        on_stop: Some(stop_job),
         ..Default::default()
       });
-      match tracer::attach(state, child).unwrap() {
+      let status = match args.backend {
+        Backend::Ptrace => tracer::attach(state, child),
+        Backend::Notify => tracer::notify::attach(state, child)
+      }.unwrap();
+      set_forwarded_signals(SigHandler::SigDfl);
+      if foreground {
+        // Taking the terminal back from the background
+        unsafe {
+          libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+          libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+          libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+        }
+      }
+      match status {
         tracer::TraceeStatus::Exited(code) => ExitCode::from(code),
-        tracer::TraceeStatus::Killed(signal) => {
-          nix::sys::signal::kill(Pid::from_raw(0), signal).unwrap();
-          unreachable!();
-        },
+        tracer::TraceeStatus::Killed(signal) => exit_by_signal(signal),
       }
     }
   }
@@ -1,72 +1,117 @@
-use std::{collections::BTreeMap, fs::File, os::fd::{AsRawFd, OwnedFd}, sync::Arc};
-use dashmap::{mapref::one::Ref, DashMap};
+use std::{collections::BTreeMap, sync::{Arc, RwLock}};
+use nix::libc;
 use typed_path::{Utf8UnixPathBuf, NativePath, NativePathBuf};
-use crate::plugin::Plugin;
+use nix::errno::Errno;
+use crate::plugin::{Filesystem, Plugin, PluginError};
 
 pub struct FileInfo {
-  pub fd: OwnedFd,
   pub fh: u64,
+  pub offset: i64,
+  pub flags: i32,
+  /// Whether the plugin opened it with opendir
+  pub dir: bool,
   pub path: Utf8UnixPathBuf,
   pub mountpath: Arc<NativePath>
 }
 
+impl FileInfo {
+  pub fn is_dir(&self) -> bool {
+    self.dir
+  }
+
+  pub fn is_readable(&self) -> bool {
+    matches!(self.flags & libc::O_ACCMODE, libc::O_RDONLY | libc::O_RDWR)
+  }
+
+  pub fn is_writable(&self) -> bool {
+    matches!(self.flags & libc::O_ACCMODE, libc::O_WRONLY | libc::O_RDWR)
+  }
+}
+
+/// An open file description. Duplicated and inherited fds share it, offset included, and the
+/// plugin handle is only released along with the last of them.
+pub type OpenFile = Arc<RwLock<FileInfo>>;
+
 pub struct Mount {
   pub path: Arc<NativePath>,
-  pub plugin: Arc<Plugin<'static>>,
-  fds: DashMap<u16, FileInfo>,
-  fd_lookup_table: Arc<DashMap<u16, Arc<NativePath>>>
+  /// Device number reported as st_dev for everything in the mount
+  pub dev: u64,
+  pub plugin: Arc<Plugin<'static>>
 }
 
 impl Mount {
-  pub fn get_fd_info(&self, fd: u16) -> Option<Ref<u16, FileInfo>> {
-    self.fds.get(&fd)
-  }
-
-  pub fn allocate_fd(&self, path: &str, fh: Option<u64>) -> Result<u16, std::io::Error> {
-    let fd = OwnedFd::from(File::open("/dev/null")?);
-    let raw_fd = fd.as_raw_fd() as u16;
-    self.fds.insert(raw_fd, FileInfo {
-      fd,
-      fh: fh.unwrap_or(0),
+  pub fn open_file(&self, path: &str, flags: i32, dir: bool, fh: u64) -> OpenFile {
+    Arc::new(RwLock::new(FileInfo {
+      fh,
+      offset: 0,
+      flags,
+      dir,
       path: path.into(),
       mountpath: self.path.clone()
-    });
-    self.fd_lookup_table.insert(raw_fd, self.path.clone());
-    Ok(raw_fd)
+    }))
   }
-  
-  pub fn release_fd(&self, fd: u16) {
-    self.fds.remove(&fd);
-    self.fd_lookup_table.remove(&fd);
+
+  /// Drops a reference to `file`, giving its handle back to the plugin if it was the last one.
+  pub fn release(&self, file: OpenFile) -> Result<(), PluginError> {
+    let Some(file) = Arc::into_inner(file) else { return Ok(()) };
+    let file = file.into_inner().unwrap();
+    // Plugins without handles to give back may leave both ops out
+    let res = if file.is_dir() && self.plugin.has_releasedir() {
+      self.plugin.releasedir(file.path.as_str(), file.fh)
+    } else if !file.is_dir() && self.plugin.has_close() {
+      self.plugin.close(file.path.as_str(), file.fh)
+    } else {
+      Ok(())
+    };
+    match res {
+      Err(PluginError::Errno(Errno::ENOSYS)) => Ok(()),
+      res => res
+    }
+  }
+}
+
+/// FNV-1a hash of a path, for numbers that must stay the same across runs.
+pub(crate) fn path_hash(path: &[u8]) -> u64 {
+  path.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// An anonymous (major 0) device number derived from the mount path.
+fn device_number(path: &NativePath) -> u64 {
+  libc::makedev(0, (path_hash(path.as_bytes()) & 0xfffff) as u32)
+}
+
+/// What a mount can be served by: a loaded plugin, or a filesystem of the process itself.
+pub trait IntoPlugin {
+  fn into_plugin(self) -> Arc<Plugin<'static>>;
+}
+
+impl IntoPlugin for Arc<Plugin<'static>> {
+  fn into_plugin(self) -> Arc<Plugin<'static>> {
+    self
+  }
+}
+
+impl<F: Filesystem + ?Sized + 'static> IntoPlugin for Arc<F> {
+  fn into_plugin(self) -> Arc<Plugin<'static>> {
+    Arc::new(Plugin::from_filesystem(self))
   }
 }
 
 pub struct Mounts {
-  mounts: BTreeMap<Arc<NativePath>, Mount>,
-  fd_lookup_table: Arc<DashMap<u16, Arc<NativePath>>>
+  mounts: BTreeMap<Arc<NativePath>, Mount>
 }
 
 impl Mounts {
-  pub fn new(mounts: &[(NativePathBuf, Arc<Plugin<'static>>)]) -> Mounts {
-    let fd_lookup_table = Arc::new(DashMap::new());
+  pub fn new<P: IntoPlugin + Clone>(mounts: &[(NativePathBuf, P)]) -> Mounts {
     let mounts = mounts.into_iter().map(|(pathbuf, plugin)| {
       let path = Arc::<NativePath>::from(pathbuf.as_path());
       (path.clone(), Mount {
+        dev: device_number(&path),
         path,
-        plugin: plugin.clone(),
-        fds: DashMap::new(),
-        fd_lookup_table: fd_lookup_table.clone()
+        plugin: plugin.clone().into_plugin()
       })
     }).collect::<BTreeMap<Arc<NativePath>, Mount>>();
-    Mounts { mounts, fd_lookup_table }
-  }
-
-  pub fn get_mount_of_fd(&self, fd: u16) -> Option<&Mount> {
-    if let Some(mountpath) = self.fd_lookup_table.get(&fd) {
-      self.mounts.get(&*mountpath)
-    } else {
-      None
-    }
+    Mounts { mounts }
   }
 
   pub fn get_mount_of_path(&self, path: &NativePath) -> Option<&Mount> {
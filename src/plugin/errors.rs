@@ -1,11 +1,28 @@
+use nix::errno::Errno;
 use thiserror::Error;
+use super::raw;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, PartialEq)]
 pub enum PluginError {
+  /// The plugin returned a negative value that is not a known errno, reported as EIO
   #[error("Unknown error")]
   UNKNOWN,
-  #[error("Operation not permitted")]
-  EPERM,
-  #[error("No such file or directory")]
-  ENOENT
-}
\ No newline at end of file
+  #[error(transparent)]
+  Errno(#[from] Errno),
+  /// Nothing usable is exported under the symbol
+  #[error("No plugin exported as {0}")]
+  MissingPlugin(String),
+  /// The plugin was built against a header of another ABI version
+  #[error("Plugin built for ABI version {0}, not {current}", current = raw::MOUNTBOX_ABI_VERSION)]
+  AbiVersion(u32)
+}
+
+impl PluginError {
+  /// Maps a negative operation result to the errno it carries.
+  pub fn from_result(res: i32) -> PluginError {
+    match i32::try_from(res.unsigned_abs()).map(Errno::from_raw) {
+      Ok(Errno::UnknownErrno) | Err(_) => PluginError::UNKNOWN,
+      Ok(errno) => PluginError::Errno(errno)
+    }
+  }
+}
@@ -0,0 +1,429 @@
+//! Plugins written in Rust, served through the same operations as the ones loaded from
+//! libraries.
+use std::{ffi::{c_char, c_int, c_void, CStr, CString}, panic::{catch_unwind, AssertUnwindSafe}, ptr, sync::{Arc, OnceLock}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use nix::{errno::Errno, libc};
+use super::raw;
+
+pub type Result<T> = std::result::Result<T, Errno>;
+
+/// The attributes of a file, the safe counterpart of `struct stat`. Fields left as None are
+/// made up by mountbox.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stat {
+  /// The S_IF* type of the file
+  pub kind: u16,
+  /// The permission bits of st_mode
+  pub perm: Option<u16>,
+  pub size: Option<u64>,
+  pub uid: Option<u32>,
+  pub gid: Option<u32>,
+  pub ino: Option<u64>,
+  pub nlink: Option<u64>,
+  pub blocks: Option<u64>,
+  pub blksize: Option<u32>,
+  pub rdev: Option<u64>,
+  pub atime: Option<SystemTime>,
+  pub mtime: Option<SystemTime>,
+  pub ctime: Option<SystemTime>
+}
+
+/// A time as seconds since the epoch, negative before it, and the nanoseconds past them.
+fn timespec(time: SystemTime) -> raw::mountbox_timespec {
+  match time.duration_since(UNIX_EPOCH) {
+    Ok(since) => raw::mountbox_timespec { sec: since.as_secs() as i64, nsec: since.subsec_nanos() as i64 },
+    Err(err) => match err.duration() {
+      before if before.subsec_nanos() == 0 => raw::mountbox_timespec { sec: -(before.as_secs() as i64), nsec: 0 },
+      before => raw::mountbox_timespec { sec: -(before.as_secs() as i64) - 1, nsec: 1_000_000_000 - before.subsec_nanos() as i64 }
+    }
+  }
+}
+
+/// The time a timespec stands for, the epoch standing in for the ones out of range.
+fn system_time(time: raw::mountbox_timespec) -> SystemTime {
+  let shift = |time: SystemTime, by: Duration, before: bool| if before { time.checked_sub(by) } else { time.checked_add(by) };
+  shift(UNIX_EPOCH, Duration::from_secs(time.sec.unsigned_abs()), time.sec < 0)
+    .and_then(|since| shift(since, Duration::from_nanos(time.nsec.unsigned_abs()), time.nsec < 0))
+    .unwrap_or(UNIX_EPOCH)
+}
+
+impl From<&Stat> for raw::stat {
+  fn from(stat: &Stat) -> raw::stat {
+    let mut raw: raw::stat = unsafe { std::mem::zeroed() };
+    raw.version = raw::MOUNTBOX_STAT_VERSION;
+    raw.mask = raw::MOUNTBOX_STAT_TYPE;
+    raw.mode = stat.kind & raw::S_IFMT;
+    let mut provide = |bit: u32, provided: bool| if provided { raw.mask |= bit };
+    provide(raw::MOUNTBOX_STAT_MODE, stat.perm.is_some());
+    provide(raw::MOUNTBOX_STAT_SIZE, stat.size.is_some());
+    provide(raw::MOUNTBOX_STAT_UID, stat.uid.is_some());
+    provide(raw::MOUNTBOX_STAT_GID, stat.gid.is_some());
+    provide(raw::MOUNTBOX_STAT_INO, stat.ino.is_some());
+    provide(raw::MOUNTBOX_STAT_NLINK, stat.nlink.is_some());
+    provide(raw::MOUNTBOX_STAT_BLOCKS, stat.blocks.is_some());
+    provide(raw::MOUNTBOX_STAT_BLKSIZE, stat.blksize.is_some());
+    provide(raw::MOUNTBOX_STAT_RDEV, stat.rdev.is_some());
+    provide(raw::MOUNTBOX_STAT_ATIME, stat.atime.is_some());
+    provide(raw::MOUNTBOX_STAT_MTIME, stat.mtime.is_some());
+    provide(raw::MOUNTBOX_STAT_CTIME, stat.ctime.is_some());
+    raw.mode |= stat.perm.unwrap_or(0) & 0o7777;
+    raw.size = stat.size.unwrap_or(0);
+    raw.uid = stat.uid.unwrap_or(0);
+    raw.gid = stat.gid.unwrap_or(0);
+    raw.ino = stat.ino.unwrap_or(0);
+    raw.nlink = stat.nlink.unwrap_or(0);
+    raw.blocks = stat.blocks.unwrap_or(0);
+    raw.blksize = stat.blksize.unwrap_or(0);
+    raw.rdev = stat.rdev.unwrap_or(0);
+    raw.atime = stat.atime.map_or(raw.atime, timespec);
+    raw.mtime = stat.mtime.map_or(raw.mtime, timespec);
+    raw.ctime = stat.ctime.map_or(raw.ctime, timespec);
+    raw
+  }
+}
+
+impl From<&raw::stat> for Stat {
+  fn from(raw: &raw::stat) -> Stat {
+    let mask = if raw.mask == 0 { raw::MOUNTBOX_STAT_LEGACY } else { raw.mask };
+    let provides = |bit: u32| mask & bit != 0;
+    Stat {
+      kind: raw.mode & raw::S_IFMT,
+      perm: provides(raw::MOUNTBOX_STAT_MODE).then_some(raw.mode & 0o7777),
+      size: provides(raw::MOUNTBOX_STAT_SIZE).then_some(raw.size),
+      uid: provides(raw::MOUNTBOX_STAT_UID).then_some(raw.uid),
+      gid: provides(raw::MOUNTBOX_STAT_GID).then_some(raw.gid),
+      ino: provides(raw::MOUNTBOX_STAT_INO).then_some(raw.ino),
+      nlink: provides(raw::MOUNTBOX_STAT_NLINK).then_some(raw.nlink),
+      blocks: provides(raw::MOUNTBOX_STAT_BLOCKS).then_some(raw.blocks),
+      blksize: provides(raw::MOUNTBOX_STAT_BLKSIZE).then_some(raw.blksize),
+      rdev: provides(raw::MOUNTBOX_STAT_RDEV).then_some(raw.rdev),
+      atime: provides(raw::MOUNTBOX_STAT_ATIME).then(|| system_time(raw.atime)),
+      mtime: provides(raw::MOUNTBOX_STAT_MTIME).then(|| system_time(raw.mtime)),
+      ctime: provides(raw::MOUNTBOX_STAT_CTIME).then(|| system_time(raw.ctime))
+    }
+  }
+}
+
+/// A filesystem served in-process, the safe counterpart of `mountbox_operations`. Paths are
+/// relative to the mount, and operations left out fail with ENOSYS.
+pub trait Filesystem: Send + Sync {
+  /// The MOUNTBOX_CAP_* the filesystem opts into.
+  fn capabilities(&self) -> u64 {
+    0
+  }
+
+  /// Opens `path` with the open(2) `flags` and returns a handle for it.
+  fn open(&self, _path: &str, _flags: i32) -> Result<u64> {
+    Err(Errno::ENOSYS)
+  }
+
+  fn close(&self, _path: &str, _fh: u64) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+
+  /// Reads at `offset` into `buf`, returning how much was read.
+  fn read(&self, _path: &str, _buf: &mut [u8], _offset: i64, _fh: u64) -> Result<usize> {
+    Err(Errno::ENOSYS)
+  }
+
+  /// The attributes of `path`.
+  fn getattr(&self, _path: &str) -> Result<Stat> {
+    Err(Errno::ENOSYS)
+  }
+
+  /// Opens the directory `path` and returns a handle for it.
+  fn opendir(&self, _path: &str) -> Result<u64> {
+    Err(Errno::ENOSYS)
+  }
+
+  /// Gives the entries of the directory from `offset` on to `filler`, until it returns true
+  /// for being full.
+  fn readdir(&self, _path: &str, _offset: i64, _fh: u64, _filler: &mut dyn FnMut(&str, Option<&Stat>) -> bool) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+
+  fn releasedir(&self, _path: &str, _fh: u64) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+
+  /// Writes `buf` at `offset`, returning how much was written.
+  fn write(&self, _path: &str, _buf: &[u8], _offset: i64, _fh: u64) -> Result<usize> {
+    Err(Errno::ENOSYS)
+  }
+
+  fn create(&self, _path: &str, _mode: u32) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+
+  fn truncate(&self, _path: &str, _size: i64) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+
+  fn mkdir(&self, _path: &str, _mode: u32) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+
+  fn rmdir(&self, _path: &str) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+
+  fn unlink(&self, _path: &str) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+
+  fn rename(&self, _from: &str, _to: &str, _flags: u32) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+
+  fn symlink(&self, _target: &str, _path: &str) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+
+  fn link(&self, _from: &str, _to: &str) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+
+  fn readlink(&self, _path: &str) -> Result<String> {
+    Err(Errno::ENOSYS)
+  }
+
+  /// Checks the access(2) `mask` on `path`. The mode bits from getattr apply when left out.
+  fn access(&self, _path: &str, _mask: i32) -> Result<()> {
+    Err(Errno::ENOSYS)
+  }
+}
+
+/// The private data of the running operation, as mountbox sees it.
+///
+/// Looked up in the global scope, for a library built with this crate to reach the mountbox
+/// that loaded it rather than its own copy.
+fn private_data() -> *mut c_void {
+  static LOOKUP: OnceLock<Option<extern "C" fn() -> *mut c_void>> = OnceLock::new();
+  let lookup = LOOKUP.get_or_init(|| {
+    let symbol = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"mountbox_private_data".as_ptr()) };
+    (!symbol.is_null()).then(|| unsafe { std::mem::transmute::<*mut c_void, extern "C" fn() -> *mut c_void>(symbol) })
+  });
+  lookup.unwrap_or(super::plugin::mountbox_private_data)()
+}
+
+/// What an operation failing with `errno` returns. EIO stands in for UnknownErrno, whose 0
+/// would tell success.
+fn failure(errno: Errno) -> c_int {
+  match errno {
+    Errno::UnknownErrno => -(Errno::EIO as c_int),
+    errno => -(errno as c_int)
+  }
+}
+
+/// Runs an operation on the filesystem of the mount, turning its result into what the
+/// operations return and panics into EIO.
+fn call<F: Filesystem + ?Sized>(op: impl FnOnce(&F) -> Result<c_int>) -> c_int {
+  let fs = private_data() as *const Arc<F>;
+  if fs.is_null() {
+    return -(Errno::EIO as c_int);
+  }
+  match catch_unwind(AssertUnwindSafe(|| op(unsafe { &**fs }))) {
+    Ok(Ok(res)) => res,
+    Ok(Err(errno)) => failure(errno),
+    Err(_) => -(Errno::EIO as c_int)
+  }
+}
+
+/// The path an operation is given, which mountbox always passes as UTF-8.
+unsafe fn path<'a>(path: *const c_char) -> Result<&'a str> {
+  unsafe { CStr::from_ptr(path) }.to_str().map_err(|_| Errno::EINVAL)
+}
+
+/// A byte count as the operations return it.
+fn count(len: usize) -> c_int {
+  len.min(c_int::MAX as usize) as c_int
+}
+
+unsafe extern "C" fn open<F: Filesystem + ?Sized>(p: *const c_char, flags: i32, fh: *mut u64) -> c_int {
+  call::<F>(|fs| {
+    unsafe { *fh = fs.open(path(p)?, flags)? };
+    Ok(0)
+  })
+}
+
+unsafe extern "C" fn close<F: Filesystem + ?Sized>(p: *const c_char, fh: u64) -> c_int {
+  call::<F>(|fs| fs.close(unsafe { path(p) }?, fh).map(|_| 0))
+}
+
+unsafe extern "C" fn read<F: Filesystem + ?Sized>(p: *const c_char, buf: *mut c_char, size: u64, offset: i64, fh: u64) -> c_int {
+  call::<F>(|fs| {
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size.min(c_int::MAX as u64) as usize) };
+    fs.read(unsafe { path(p) }?, buf, offset, fh).map(count)
+  })
+}
+
+unsafe extern "C" fn getattr<F: Filesystem + ?Sized>(p: *const c_char, stat: *mut raw::stat) -> c_int {
+  call::<F>(|fs| {
+    unsafe { *stat = raw::stat::from(&fs.getattr(path(p)?)?) };
+    Ok(0)
+  })
+}
+
+unsafe extern "C" fn opendir<F: Filesystem + ?Sized>(p: *const c_char, fh: *mut u64) -> c_int {
+  call::<F>(|fs| {
+    unsafe { *fh = fs.opendir(path(p)?)? };
+    Ok(0)
+  })
+}
+
+unsafe extern "C" fn readdir<F: Filesystem + ?Sized>(p: *const c_char, buf: *mut c_void, filler: raw::mountbox_fill_dir_t, offset: i64, fh: u64) -> c_int {
+  call::<F>(|fs| {
+    let filler = filler.ok_or(Errno::EINVAL)?;
+    fs.readdir(unsafe { path(p) }?, offset, fh, &mut |name: &str, stat: Option<&Stat>| {
+      // Names with a NUL cannot be listed
+      let Ok(name) = CString::new(name) else { return false };
+      let stat = stat.map(raw::stat::from);
+      unsafe { filler(buf, name.as_ptr(), stat.as_ref().map_or(ptr::null(), |stat| stat as *const raw::stat)) != 0 }
+    }).map(|_| 0)
+  })
+}
+
+unsafe extern "C" fn releasedir<F: Filesystem + ?Sized>(p: *const c_char, fh: u64) -> c_int {
+  call::<F>(|fs| fs.releasedir(unsafe { path(p) }?, fh).map(|_| 0))
+}
+
+unsafe extern "C" fn write<F: Filesystem + ?Sized>(p: *const c_char, buf: *const c_char, size: u64, offset: i64, fh: u64) -> c_int {
+  call::<F>(|fs| {
+    let buf = unsafe { std::slice::from_raw_parts(buf as *const u8, size.min(c_int::MAX as u64) as usize) };
+    fs.write(unsafe { path(p) }?, buf, offset, fh).map(count)
+  })
+}
+
+unsafe extern "C" fn create<F: Filesystem + ?Sized>(p: *const c_char, mode: u32) -> c_int {
+  call::<F>(|fs| fs.create(unsafe { path(p) }?, mode).map(|_| 0))
+}
+
+unsafe extern "C" fn truncate<F: Filesystem + ?Sized>(p: *const c_char, size: i64) -> c_int {
+  call::<F>(|fs| fs.truncate(unsafe { path(p) }?, size).map(|_| 0))
+}
+
+unsafe extern "C" fn mkdir<F: Filesystem + ?Sized>(p: *const c_char, mode: u32) -> c_int {
+  call::<F>(|fs| fs.mkdir(unsafe { path(p) }?, mode).map(|_| 0))
+}
+
+unsafe extern "C" fn rmdir<F: Filesystem + ?Sized>(p: *const c_char) -> c_int {
+  call::<F>(|fs| fs.rmdir(unsafe { path(p) }?).map(|_| 0))
+}
+
+unsafe extern "C" fn unlink<F: Filesystem + ?Sized>(p: *const c_char) -> c_int {
+  call::<F>(|fs| fs.unlink(unsafe { path(p) }?).map(|_| 0))
+}
+
+unsafe extern "C" fn rename<F: Filesystem + ?Sized>(from: *const c_char, to: *const c_char, flags: u32) -> c_int {
+  call::<F>(|fs| fs.rename(unsafe { path(from) }?, unsafe { path(to) }?, flags).map(|_| 0))
+}
+
+unsafe extern "C" fn symlink<F: Filesystem + ?Sized>(target: *const c_char, p: *const c_char) -> c_int {
+  call::<F>(|fs| fs.symlink(unsafe { path(target) }?, unsafe { path(p) }?).map(|_| 0))
+}
+
+unsafe extern "C" fn link<F: Filesystem + ?Sized>(from: *const c_char, to: *const c_char) -> c_int {
+  call::<F>(|fs| fs.link(unsafe { path(from) }?, unsafe { path(to) }?).map(|_| 0))
+}
+
+unsafe extern "C" fn readlink<F: Filesystem + ?Sized>(p: *const c_char, buf: *mut c_char, size: u64) -> c_int {
+  call::<F>(|fs| {
+    let target = fs.readlink(unsafe { path(p) }?)?;
+    let len = target.len().min(size as usize);
+    unsafe { ptr::copy_nonoverlapping(target.as_ptr(), buf as *mut u8, len) };
+    Ok(count(len))
+  })
+}
+
+unsafe extern "C" fn access<F: Filesystem + ?Sized>(p: *const c_char, mask: i32) -> c_int {
+  call::<F>(|fs| fs.access(unsafe { path(p) }?, mask).map(|_| 0))
+}
+
+unsafe extern "C" fn destroy<F: Filesystem + ?Sized>(private_data: *mut c_void) {
+  if !private_data.is_null() {
+    drop(unsafe { Box::from_raw(private_data as *mut Arc<F>) });
+  }
+}
+
+/// The operations serving the filesystem `F` that the private data of the mount points to,
+/// as set by `init` or [`Plugin::from_filesystem`](super::Plugin::from_filesystem).
+pub const fn operations<F: Filesystem + ?Sized>() -> raw::mountbox_operations {
+  raw::mountbox_operations {
+    open: Some(open::<F>),
+    close: Some(close::<F>),
+    read: Some(read::<F>),
+    getattr: Some(getattr::<F>),
+    opendir: Some(opendir::<F>),
+    readdir: Some(readdir::<F>),
+    releasedir: Some(releasedir::<F>),
+    write: Some(write::<F>),
+    create: Some(create::<F>),
+    truncate: Some(truncate::<F>),
+    mkdir: Some(mkdir::<F>),
+    rmdir: Some(rmdir::<F>),
+    unlink: Some(unlink::<F>),
+    rename: Some(rename::<F>),
+    symlink: Some(symlink::<F>),
+    link: Some(link::<F>),
+    readlink: Some(readlink::<F>),
+    access: Some(access::<F>),
+    init: None,
+    destroy: Some(destroy::<F>)
+  }
+}
+
+/// The private data of a mount served by `fs`.
+pub(crate) fn into_private_data<F: Filesystem + ?Sized>(fs: Arc<F>) -> *mut c_void {
+  Box::into_raw(Box::new(fs)) as *mut c_void
+}
+
+/// Implements init for [`export_filesystem!`], building the filesystem of a mount from its
+/// options with `new`.
+///
+/// # Safety
+/// `opts` and `private_data` must be as mountbox passes them to init.
+pub unsafe fn init<F: Filesystem>(opts: *const *const c_char, private_data: *mut *mut c_void, new: fn(&[&str]) -> Result<F>) -> c_int {
+  let mut args = vec![];
+  let mut opt = opts;
+  while !unsafe { *opt }.is_null() {
+    match unsafe { path(*opt) } {
+      Ok(arg) => args.push(arg),
+      Err(errno) => return failure(errno)
+    }
+    opt = unsafe { opt.add(1) };
+  }
+  match catch_unwind(|| new(&args)) {
+    Ok(Ok(fs)) => {
+      unsafe { *private_data = into_private_data(Arc::new(fs)) };
+      0
+    },
+    Ok(Err(errno)) => failure(errno),
+    Err(_) => -(Errno::EIO as c_int)
+  }
+}
+
+/// Exports the [`Filesystem`] `$fs` as the plugin `$name` of the library, built for each mount
+/// from its options by `$new`, optionally with MOUNTBOX_CAP_* capabilities.
+#[macro_export]
+macro_rules! export_filesystem {
+  ($name:ident, $fs:ty, $new:expr $(, $caps:expr)?) => {
+    #[unsafe(no_mangle)]
+    #[allow(non_upper_case_globals)]
+    pub static mut $name: $crate::plugin::mountbox_plugin_info = {
+      unsafe extern "C" fn init(opts: *const *const std::ffi::c_char, private_data: *mut *mut std::ffi::c_void) -> std::ffi::c_int {
+        unsafe { $crate::plugin::filesystem::init::<$fs>(opts, private_data, $new) }
+      }
+
+      static OPERATIONS: $crate::plugin::mountbox_operations = $crate::plugin::mountbox_operations {
+        init: Some(init),
+        ..$crate::plugin::filesystem::operations::<$fs>()
+      };
+
+      $crate::plugin::mountbox_plugin_info {
+        abi_version: $crate::plugin::MOUNTBOX_ABI_VERSION,
+        operations_size: std::mem::size_of::<$crate::plugin::mountbox_operations>() as u32,
+        capabilities: 0 $(| $caps as u64)?,
+        operations: &OPERATIONS
+      }
+    };
+  };
+}
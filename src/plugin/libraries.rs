@@ -0,0 +1,22 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::{Arc, Mutex}};
+use dlopen::symbor::Library;
+
+/// The plugin libraries of a session, each loaded once whatever path it is named by.
+#[derive(Default)]
+pub struct Libraries {
+  loaded: Mutex<HashMap<PathBuf, Arc<Library>>>
+}
+
+impl Libraries {
+  /// The library at `path`, loaded on first use. Names dlopen looks up are kept as they are.
+  pub fn open(&self, path: &Path) -> Result<Arc<Library>, dlopen::Error> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut loaded = self.loaded.lock().unwrap();
+    if let Some(library) = loaded.get(&path) {
+      return Ok(library.clone());
+    }
+    let library = Arc::new(Library::open(&path)?);
+    loaded.insert(path, library.clone());
+    Ok(library)
+  }
+}
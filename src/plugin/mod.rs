@@ -1,7 +1,17 @@
 mod plugin;
 mod raw;
 mod errors;
+mod libraries;
+pub mod filesystem;
+pub mod remote;
 
 pub use plugin::Plugin;
 pub use errors::PluginError;
-pub use raw::{S_IFMT, S_IFDIR, S_IFLNK, S_IFREG, stat};
\ No newline at end of file
+pub use libraries::Libraries;
+pub use filesystem::{Filesystem, Stat};
+pub use raw::{S_IFMT, S_IFDIR, S_IFLNK, S_IFREG, RENAME_NOREPLACE, RENAME_EXCHANGE, stat, mountbox_timespec, mountbox_fill_dir_t};
+pub use raw::{MOUNTBOX_STAT_VERSION, MOUNTBOX_STAT_TYPE, MOUNTBOX_STAT_MODE, MOUNTBOX_STAT_NLINK, MOUNTBOX_STAT_UID, MOUNTBOX_STAT_GID,
+  MOUNTBOX_STAT_ATIME, MOUNTBOX_STAT_MTIME, MOUNTBOX_STAT_CTIME, MOUNTBOX_STAT_INO, MOUNTBOX_STAT_SIZE, MOUNTBOX_STAT_BLOCKS,
+  MOUNTBOX_STAT_BLKSIZE, MOUNTBOX_STAT_RDEV, MOUNTBOX_STAT_LEGACY};
+pub use raw::{MOUNTBOX_ABI_VERSION, MOUNTBOX_CAP_RENAME_FLAGS, MOUNTBOX_CAP_ATOMIC_O_TRUNC};
+pub use raw::{mountbox_operations, mountbox_plugin_info};
\ No newline at end of file
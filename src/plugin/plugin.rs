@@ -1,47 +1,186 @@
-use std::{ffi::CString, mem::MaybeUninit};
-use dlopen::symbor::{Library, Symbol};
-use super::{errors::PluginError, raw};
+use std::{cell::Cell, ffi::{c_char, c_int, c_void, CStr, CString}, mem::{size_of, MaybeUninit}, ptr, sync::Arc};
+use dlopen::symbor::Library;
+use super::{errors::PluginError, filesystem::{self, Filesystem}, raw};
 
 type Result<T> = std::result::Result<T, PluginError>;
 
+/// Symbol plugins are looked up by when none is given
+const DEFAULT_SYMBOL: &str = "mountbox_plugin_info";
+
+/// Capabilities this version of mountbox knows of
+const CAPABILITIES: u64 = (raw::MOUNTBOX_CAP_RENAME_FLAGS | raw::MOUNTBOX_CAP_ATOMIC_O_TRUNC) as u64;
+
 pub struct Plugin<'a> {
-  raw_operations: Symbol<'a, &'a raw::mountbox_operations>
+  /// The operations the plugin was built with, the others left out
+  raw_operations: raw::mountbox_operations,
+  /// Capabilities of the plugin mountbox knows of
+  capabilities: u64,
+  /// Where the operations come from, kept loaded for as long as the plugin is around
+  _source: Source<'a>,
+  /// What init stored for the mount, once it ran
+  private_data: Option<PrivateData>
+}
+
+/// What keeps the operations of a plugin loaded, only ever held
+#[allow(dead_code)]
+enum Source<'a> {
+  Borrowed(&'a Library),
+  Shared(Arc<Library>),
+  /// Operations of mountbox itself
+  Builtin
+}
+
+struct PrivateData(*mut c_void);
+
+// Only ever handed back to the plugin, which is expected to share it between threads
+unsafe impl Send for PrivateData {}
+unsafe impl Sync for PrivateData {}
+
+thread_local! {
+  /// Private data of the plugin whose operation is running on the thread
+  static PRIVATE_DATA: Cell<*mut c_void> = const { Cell::new(ptr::null_mut()) };
+}
+
+/// The private data init stored for the mount the running operation is made on, null
+/// outside of operations or for plugins left uninitialized.
+#[unsafe(no_mangle)]
+pub extern "C" fn mountbox_private_data() -> *mut c_void {
+  PRIVATE_DATA.get()
 }
 
 macro_rules! exec {
   ($self:tt, $op:tt $(, $($args:expr),*)?) => {
-    ($self.raw_operations.$op.unwrap_or_else(|| unimplemented!()))($($($args),*)?)
+    match $self.raw_operations.$op {
+      Some(op) => {
+        let previous = PRIVATE_DATA.replace($self.private_data.as_ref().map_or(ptr::null_mut(), |data| data.0));
+        let res = op($($($args),*)?);
+        PRIVATE_DATA.set(previous);
+        res
+      },
+      None => -(raw::ENOSYS as c_int)
+    }
   };
 }
 
 macro_rules! int_to_result {
   ($int:tt) => {
     if $int < 0 {
-      Err(match $int.unsigned_abs() {
-        raw::EPERM => PluginError::EPERM,
-        raw::ENOENT => PluginError::ENOENT,
-        _ => PluginError::UNKNOWN
-      })
+      Err(PluginError::from_result($int))
     } else {
       Ok(())
     }
   };
 }
 
+unsafe extern "C" fn fill_dir<F: FnMut(&CStr, Option<&raw::stat>) -> bool>(buf: *mut c_void, name: *const c_char, stat: *const raw::stat) -> c_int {
+  let filler = unsafe { &mut *(buf as *mut F) };
+  filler(unsafe { CStr::from_ptr(name) }, unsafe { stat.as_ref() }) as c_int
+}
+
+/// Copies the operations and capabilities of the plugin `lib` exports as `symbol_name`, or
+/// mountbox_plugin_info by default.
+fn resolve(lib: &Library, symbol_name: Option<&str>) -> Result<(raw::mountbox_operations, u64)> {
+  let symbol_name = symbol_name.unwrap_or(DEFAULT_SYMBOL);
+  let info = unsafe { lib.symbol::<&raw::mountbox_plugin_info>(symbol_name) }
+    .map_err(|_| PluginError::MissingPlugin(symbol_name.to_string()))?;
+  if info.abi_version != raw::MOUNTBOX_ABI_VERSION {
+    return Err(PluginError::AbiVersion(info.abi_version));
+  }
+  if info.operations.is_null() {
+    return Err(PluginError::MissingPlugin(symbol_name.to_string()));
+  }
+  // Operations added since the plugin was built are left out, and the ones it knows of past
+  // these ignored. A size ending within an operation leaves it out too
+  let mut raw_operations = MaybeUninit::<raw::mountbox_operations>::zeroed();
+  let size = (info.operations_size as usize).min(size_of::<raw::mountbox_operations>()) / size_of::<usize>() * size_of::<usize>();
+  let raw_operations = unsafe {
+    ptr::copy_nonoverlapping(info.operations as *const u8, raw_operations.as_mut_ptr() as *mut u8, size);
+    raw_operations.assume_init()
+  };
+  Ok((raw_operations, info.capabilities & CAPABILITIES))
+}
+
+impl Plugin<'static> {
+  /// Loads the plugin of `library`, which stays loaded for as long as the plugin is around.
+  pub fn load_shared(library: Arc<Library>, symbol_name: Option<&str>) -> Result<Plugin<'static>> {
+    let (raw_operations, capabilities) = resolve(&library, symbol_name)?;
+    Ok(Plugin {
+      raw_operations,
+      capabilities,
+      _source: Source::Shared(library),
+      private_data: None
+    })
+  }
+
+  /// Serves the mount with `fs`, in-process. No init is needed.
+  pub fn from_filesystem<F: Filesystem + ?Sized + 'static>(fs: Arc<F>) -> Plugin<'static> {
+    Plugin {
+      raw_operations: filesystem::operations::<F>(),
+      capabilities: fs.capabilities() & CAPABILITIES,
+      _source: Source::Builtin,
+      private_data: Some(PrivateData(filesystem::into_private_data(fs)))
+    }
+  }
+}
+
+impl Drop for Plugin<'_> {
+  fn drop(&mut self) {
+    if let (Some(data), Some(destroy)) = (&self.private_data, self.raw_operations.destroy) {
+      unsafe { destroy(data.0) };
+    }
+  }
+}
+
 impl<'a> Plugin<'a> {
-  pub fn load(lib: &'a Library, symbol_name: Option<&str>) -> Plugin<'a> {
-    let raw_operations = unsafe {
-      lib.symbol::<&raw::mountbox_operations>(symbol_name.unwrap_or("operations")).unwrap()
-    };
-    Plugin { raw_operations }
+  /// Loads the plugin `lib` exports as `symbol_name`, or mountbox_plugin_info by default.
+  pub fn load(lib: &'a Library, symbol_name: Option<&str>) -> Result<Plugin<'a>> {
+    let (raw_operations, capabilities) = resolve(lib, symbol_name)?;
+    Ok(Plugin {
+      raw_operations,
+      capabilities,
+      _source: Source::Borrowed(lib),
+      private_data: None
+    })
+  }
+
+  /// Whether the plugin opted into the MOUNTBOX_CAP_* `capability`.
+  pub fn has_capability(&self, capability: u32) -> bool {
+    self.capabilities & capability as u64 != 0
   }
 
-  pub fn open(&self, path: &str) -> Result<()> {
+  /// Sets the plugin up for a mount with its key=value `opts`. Meant to be called once, before
+  /// any other operation, for destroy to be called when the plugin is dropped.
+  pub fn init(&mut self, opts: &[&str]) -> Result<()> {
+    if self.raw_operations.init.is_none() {
+      // Plugins without init may have been given their data already
+      self.private_data.get_or_insert(PrivateData(ptr::null_mut()));
+      return Ok(());
+    }
+    let opts = opts.iter().map(|opt| CString::new(*opt).map_err(|_| PluginError::Errno(nix::errno::Errno::EINVAL)))
+      .collect::<Result<Vec<CString>>>()?;
+    let argv: Vec<*const c_char> = opts.iter().map(|opt| opt.as_ptr()).chain([ptr::null()]).collect();
+    let mut data = ptr::null_mut();
+    unsafe {
+      let res = exec!(self, init, argv.as_ptr(), &mut data);
+      int_to_result!(res)?;
+    }
+    self.private_data = Some(PrivateData(data));
+    Ok(())
+  }
+
+  /// Opens `path` with the open(2) `flags` and returns the handle the plugin chose for it.
+  pub fn open(&self, path: &str, flags: i32) -> Result<u64> {
     let cpath = CString::new(path).unwrap();
+    let mut fh: u64 = 0;
     unsafe {
-      let res = exec!(self, open, cpath.as_ptr());
-      int_to_result!(res)
+      let res = exec!(self, open, cpath.as_ptr(), flags, &mut fh);
+      int_to_result!(res)?;
     }
+    Ok(fh)
+  }
+
+  pub fn has_close(&self) -> bool {
+    self.raw_operations.close.is_some()
   }
 
   pub fn close(&self, path: &str, fh: u64) -> Result<()> {
@@ -65,10 +204,149 @@ impl<'a> Plugin<'a> {
     let cpath = CString::new(path).unwrap();
     unsafe {
       let mut stat = MaybeUninit::<raw::stat>::zeroed();
+      (*stat.as_mut_ptr()).version = raw::MOUNTBOX_STAT_VERSION;
       let res = exec!(self, getattr, cpath.as_ptr(), stat.as_mut_ptr());
       int_to_result!(res)?;
       Ok(stat.assume_init())
     }
   }
+
+  pub fn opendir(&self, path: &str) -> Result<u64> {
+    let cpath = CString::new(path).unwrap();
+    let mut fh: u64 = 0;
+    unsafe {
+      let res = exec!(self, opendir, cpath.as_ptr(), &mut fh);
+      int_to_result!(res)?;
+    }
+    Ok(fh)
+  }
+
+  /// Lists the directory starting at the `offset`th entry. `filler` is called for every entry
+  /// and returns true once it cannot take any more.
+  pub fn readdir<F: FnMut(&CStr, Option<&raw::stat>) -> bool>(&self, path: &str, offset: i64, fh: u64, mut filler: F) -> Result<()> {
+    let cpath = CString::new(path).unwrap();
+    unsafe {
+      let res = exec!(self, readdir, cpath.as_ptr(), &mut filler as *mut F as *mut c_void, Some(fill_dir::<F>), offset, fh);
+      int_to_result!(res)
+    }
+  }
+
+  pub fn has_releasedir(&self) -> bool {
+    self.raw_operations.releasedir.is_some()
+  }
+
+  pub fn releasedir(&self, path: &str, fh: u64) -> Result<()> {
+    let cpath = CString::new(path).unwrap();
+    unsafe {
+      let res = exec!(self, releasedir, cpath.as_ptr(), fh);
+      int_to_result!(res)
+    }
+  }
+
+  pub fn write(&self, path: &str, buf: &[u8], offset: i64, fh: u64) -> Result<u64> {
+    let cpath = CString::new(path).unwrap();
+    unsafe {
+      let res = exec!(self, write, cpath.as_ptr(), buf.as_ptr() as *const i8, buf.len() as u64, offset, fh);
+      int_to_result!(res)?;
+      Ok(res as u64)
+    }
+  }
+
+  pub fn create(&self, path: &str, mode: u32) -> Result<()> {
+    let cpath = CString::new(path).unwrap();
+    unsafe {
+      let res = exec!(self, create, cpath.as_ptr(), mode);
+      int_to_result!(res)
+    }
+  }
+
+  pub fn truncate(&self, path: &str, size: i64) -> Result<()> {
+    let cpath = CString::new(path).unwrap();
+    unsafe {
+      let res = exec!(self, truncate, cpath.as_ptr(), size);
+      int_to_result!(res)
+    }
+  }
+
+  pub fn mkdir(&self, path: &str, mode: u32) -> Result<()> {
+    let cpath = CString::new(path).unwrap();
+    unsafe {
+      let res = exec!(self, mkdir, cpath.as_ptr(), mode);
+      int_to_result!(res)
+    }
+  }
+
+  pub fn rmdir(&self, path: &str) -> Result<()> {
+    let cpath = CString::new(path).unwrap();
+    unsafe {
+      let res = exec!(self, rmdir, cpath.as_ptr());
+      int_to_result!(res)
+    }
+  }
+
+  pub fn unlink(&self, path: &str) -> Result<()> {
+    let cpath = CString::new(path).unwrap();
+    unsafe {
+      let res = exec!(self, unlink, cpath.as_ptr());
+      int_to_result!(res)
+    }
+  }
+
+  pub fn rename(&self, from: &str, to: &str, flags: u32) -> Result<()> {
+    let cfrom = CString::new(from).unwrap();
+    let cto = CString::new(to).unwrap();
+    unsafe {
+      let res = exec!(self, rename, cfrom.as_ptr(), cto.as_ptr(), flags);
+      int_to_result!(res)
+    }
+  }
+
+  pub fn symlink(&self, target: &str, path: &str) -> Result<()> {
+    let ctarget = CString::new(target).unwrap();
+    let cpath = CString::new(path).unwrap();
+    unsafe {
+      let res = exec!(self, symlink, ctarget.as_ptr(), cpath.as_ptr());
+      int_to_result!(res)
+    }
+  }
+
+  pub fn link(&self, from: &str, to: &str) -> Result<()> {
+    let cfrom = CString::new(from).unwrap();
+    let cto = CString::new(to).unwrap();
+    unsafe {
+      let res = exec!(self, link, cfrom.as_ptr(), cto.as_ptr());
+      int_to_result!(res)
+    }
+  }
+
+  /// Whether the plugin can report symlink targets. Path walking only looks for links
+  /// in mounts whose plugin does.
+  pub fn has_readlink(&self) -> bool {
+    self.raw_operations.readlink.is_some()
+  }
+
+  pub fn readlink(&self, path: &str) -> Result<String> {
+    let cpath = CString::new(path).unwrap();
+    let mut buf = vec![0u8; nix::libc::PATH_MAX as usize];
+    unsafe {
+      let res = exec!(self, readlink, cpath.as_ptr(), buf.as_mut_ptr() as *mut i8, buf.len() as u64);
+      int_to_result!(res)?;
+      buf.truncate(res as usize);
+    }
+    String::from_utf8(buf).map_err(|_| PluginError::UNKNOWN)
+  }
+
+  /// Whether the plugin checks permissions itself. Otherwise the mode bits from getattr apply.
+  pub fn has_access(&self) -> bool {
+    self.raw_operations.access.is_some()
+  }
+
+  pub fn access(&self, path: &str, mask: i32) -> Result<()> {
+    let cpath = CString::new(path).unwrap();
+    unsafe {
+      let res = exec!(self, access, cpath.as_ptr(), mask);
+      int_to_result!(res)
+    }
+  }
 }
 
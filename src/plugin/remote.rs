@@ -0,0 +1,577 @@
+//! Plugins served by a daemon over a Unix stream socket, for a crashing plugin to only fail the
+//! operations on its mounts with EIO rather than take the traced processes down.
+//!
+//! Each connection serves one mount. Messages are a u32 length followed by that many bytes.
+//! Integers are little-endian, and strings and buffers are a u32 length followed by their bytes.
+//!
+//! Requests are the u32 opcode of the operation, its index in `mountbox_operations`, followed by
+//! its arguments. Responses are the i32 result of the operation, a negative errno on failure,
+//! followed on success by what it returns:
+//!
+//! | Opcode | Operation | Arguments | Returns |
+//! |--------|-----------|-----------|---------|
+//! | 0  | open       | path, flags i32 | fh u64 |
+//! | 1  | close      | path, fh u64 | |
+//! | 2  | read       | path, size u64, offset i64, fh u64 | data, its length as result |
+//! | 3  | getattr    | path | stat |
+//! | 4  | opendir    | path | fh u64 |
+//! | 5  | readdir    | path, offset i64, fh u64 | u32 count of entries, each a name, u8 1 and a stat or u8 0 |
+//! | 6  | releasedir | path, fh u64 | |
+//! | 7  | write      | path, data, offset i64, fh u64 | length written as result |
+//! | 8  | create     | path, mode u32 | |
+//! | 9  | truncate   | path, size i64 | |
+//! | 10 | mkdir      | path, mode u32 | |
+//! | 11 | rmdir      | path | |
+//! | 12 | unlink     | path | |
+//! | 13 | rename     | from, to, flags u32 | |
+//! | 14 | symlink    | target, path | |
+//! | 15 | link       | from, to | |
+//! | 16 | readlink   | path | target |
+//! | 17 | access     | path, mask i32 | |
+//! | 18 | init       | u32 count of options, each a key=value string | capabilities u64 |
+//!
+//! A stat is its fields in the order of `struct stat`, timestamps being an i64 sec and i64 nsec.
+//! init is the first request of every connection, mountbox connecting again with the same
+//! options after losing one. Operations on handles opened on a previous connection fail with
+//! EBADF without reaching the daemon. Results that are not a known errno are taken as EIO.
+use std::{collections::HashMap, io::{self, Read, Write}, os::unix::net::{UnixListener, UnixStream}, path::{Path, PathBuf}, sync::{Arc, Mutex}, thread, time::Duration};
+use nix::errno::Errno;
+use super::{filesystem::{Filesystem, Result, Stat}, raw};
+
+/// How long mountbox waits on a daemon before failing the operation
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Larger messages are taken as a broken connection
+const MAX_MESSAGE: u32 = 64 << 20;
+
+const OPEN: u32 = 0;
+const CLOSE: u32 = 1;
+const READ: u32 = 2;
+const GETATTR: u32 = 3;
+const OPENDIR: u32 = 4;
+const READDIR: u32 = 5;
+const RELEASEDIR: u32 = 6;
+const WRITE: u32 = 7;
+const CREATE: u32 = 8;
+const TRUNCATE: u32 = 9;
+const MKDIR: u32 = 10;
+const RMDIR: u32 = 11;
+const UNLINK: u32 = 12;
+const RENAME: u32 = 13;
+const SYMLINK: u32 = 14;
+const LINK: u32 = 15;
+const READLINK: u32 = 16;
+const ACCESS: u32 = 17;
+const INIT: u32 = 18;
+
+/// A message being built.
+#[derive(Clone, Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+  fn request(op: u32) -> Encoder {
+    Encoder::default().u32(op)
+  }
+
+  fn response(res: i32) -> Encoder {
+    Encoder::default().i32(res)
+  }
+
+  fn u8(mut self, value: u8) -> Encoder {
+    self.0.push(value);
+    self
+  }
+
+  fn u16(mut self, value: u16) -> Encoder {
+    self.0.extend(value.to_le_bytes());
+    self
+  }
+
+  fn u32(mut self, value: u32) -> Encoder {
+    self.0.extend(value.to_le_bytes());
+    self
+  }
+
+  fn i32(mut self, value: i32) -> Encoder {
+    self.0.extend(value.to_le_bytes());
+    self
+  }
+
+  fn u64(mut self, value: u64) -> Encoder {
+    self.0.extend(value.to_le_bytes());
+    self
+  }
+
+  fn i64(mut self, value: i64) -> Encoder {
+    self.0.extend(value.to_le_bytes());
+    self
+  }
+
+  fn bytes(self, value: &[u8]) -> Encoder {
+    let mut encoder = self.u32(value.len() as u32);
+    encoder.0.extend(value);
+    encoder
+  }
+
+  fn str(self, value: &str) -> Encoder {
+    self.bytes(value.as_bytes())
+  }
+
+  fn stat(self, stat: &Stat) -> Encoder {
+    let stat = raw::stat::from(stat);
+    self.u32(stat.version).u32(stat.mask).u64(stat.size).u16(stat.mode).u32(stat.uid).u32(stat.gid)
+      .u64(stat.ino).u64(stat.nlink).u64(stat.blocks).u32(stat.blksize).u64(stat.rdev)
+      .i64(stat.atime.sec).i64(stat.atime.nsec).i64(stat.mtime.sec).i64(stat.mtime.nsec).i64(stat.ctime.sec).i64(stat.ctime.nsec)
+  }
+}
+
+/// A message being read.
+struct Decoder {
+  buf: Vec<u8>,
+  pos: usize
+}
+
+fn truncated() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "truncated message")
+}
+
+impl Decoder {
+  fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+    let bytes = self.buf.get(self.pos..self.pos + N).ok_or_else(truncated)?;
+    self.pos += N;
+    Ok(bytes.try_into().unwrap())
+  }
+
+  fn u8(&mut self) -> io::Result<u8> {
+    self.take().map(u8::from_le_bytes)
+  }
+
+  fn u16(&mut self) -> io::Result<u16> {
+    self.take().map(u16::from_le_bytes)
+  }
+
+  fn u32(&mut self) -> io::Result<u32> {
+    self.take().map(u32::from_le_bytes)
+  }
+
+  fn i32(&mut self) -> io::Result<i32> {
+    self.take().map(i32::from_le_bytes)
+  }
+
+  fn u64(&mut self) -> io::Result<u64> {
+    self.take().map(u64::from_le_bytes)
+  }
+
+  fn i64(&mut self) -> io::Result<i64> {
+    self.take().map(i64::from_le_bytes)
+  }
+
+  fn bytes(&mut self) -> io::Result<Vec<u8>> {
+    let len = self.u32()? as usize;
+    let bytes = self.buf.get(self.pos..self.pos + len).ok_or_else(truncated)?.to_vec();
+    self.pos += len;
+    Ok(bytes)
+  }
+
+  fn str(&mut self) -> io::Result<String> {
+    String::from_utf8(self.bytes()?).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+  }
+
+  fn stat(&mut self) -> io::Result<Stat> {
+    let mut stat: raw::stat = unsafe { std::mem::zeroed() };
+    stat.version = self.u32()?;
+    stat.mask = self.u32()?;
+    stat.size = self.u64()?;
+    stat.mode = self.u16()?;
+    stat.uid = self.u32()?;
+    stat.gid = self.u32()?;
+    stat.ino = self.u64()?;
+    stat.nlink = self.u64()?;
+    stat.blocks = self.u64()?;
+    stat.blksize = self.u32()?;
+    stat.rdev = self.u64()?;
+    for time in [&mut stat.atime, &mut stat.mtime, &mut stat.ctime] {
+      time.sec = self.i64()?;
+      time.nsec = self.i64()?;
+    }
+    Ok(Stat::from(&stat))
+  }
+}
+
+fn send(stream: &mut UnixStream, message: &Encoder) -> io::Result<()> {
+  let mut frame = Vec::with_capacity(4 + message.0.len());
+  frame.extend((message.0.len() as u32).to_le_bytes());
+  frame.extend(&message.0);
+  stream.write_all(&frame)
+}
+
+fn receive(stream: &mut UnixStream) -> io::Result<Decoder> {
+  let mut len = [0u8; 4];
+  stream.read_exact(&mut len)?;
+  let len = u32::from_le_bytes(len);
+  if len > MAX_MESSAGE {
+    return Err(io::Error::new(io::ErrorKind::InvalidData, "message too large"));
+  }
+  let mut buf = vec![0u8; len as usize];
+  stream.read_exact(&mut buf)?;
+  Ok(Decoder { buf, pos: 0 })
+}
+
+/// A mount served by the daemon listening at a socket. Operations fail with EIO while the
+/// daemon does not answer, and the connection is made again on the next one.
+pub struct Remote {
+  path: PathBuf,
+  opts: Vec<String>,
+  timeout: Duration,
+  capabilities: u64,
+  connection: Mutex<Connection>
+}
+
+/// The connection to the daemon, if any, along with the handles opened on it and earlier ones.
+#[derive(Default)]
+struct Connection {
+  stream: Option<UnixStream>,
+  /// Counts the connections made, for handles to tell the one they were opened on
+  session: u64,
+  /// The session and daemon handle of every handle given out
+  handles: HashMap<u64, (u64, u64)>,
+  next_handle: u64
+}
+
+impl Connection {
+  /// The daemon handle of `fh`, failing with EBADF when it was opened on an earlier connection.
+  fn handle(&self, fh: Option<u64>) -> Result<u64> {
+    match fh.map(|fh| self.handles.get(&fh)) {
+      None => Ok(0),
+      Some(Some(&(session, fh))) if session == self.session => Ok(fh),
+      Some(_) => Err(Errno::EBADF)
+    }
+  }
+}
+
+impl Remote {
+  /// Connects to the daemon listening at `path` and has it set the mount up with `opts`.
+  /// Operations the daemon takes longer than `timeout` to answer fail.
+  pub fn connect(path: impl AsRef<Path>, opts: &[&str], timeout: Duration) -> io::Result<Remote> {
+    let mut remote = Remote {
+      path: path.as_ref().to_path_buf(),
+      opts: opts.iter().map(|opt| opt.to_string()).collect(),
+      timeout,
+      capabilities: 0,
+      connection: Mutex::default()
+    };
+    let (stream, capabilities) = remote.session()?;
+    remote.capabilities = capabilities;
+    remote.connection = Mutex::new(Connection { stream: Some(stream), ..Default::default() });
+    Ok(remote)
+  }
+
+  /// A new connection, with the mount set up on it.
+  fn session(&self) -> io::Result<(UnixStream, u64)> {
+    let mut stream = UnixStream::connect(&self.path)?;
+    stream.set_read_timeout(Some(self.timeout))?;
+    stream.set_write_timeout(Some(self.timeout))?;
+    let request = self.opts.iter().fold(Encoder::request(INIT).u32(self.opts.len() as u32), |request, opt| request.str(opt));
+    send(&mut stream, &request)?;
+    let mut response = receive(&mut stream)?;
+    match response.i32()? {
+      res if res < 0 => Err(io::Error::from_raw_os_error(-res)),
+      _ => Ok((stream, response.u64()?))
+    }
+  }
+
+  /// Replaces the connection with a new one, starting a session.
+  fn reconnect(&self, connection: &mut Connection) -> Result<()> {
+    connection.stream = Some(self.session().map_err(|_| Errno::EIO)?.0);
+    connection.session += 1;
+    Ok(())
+  }
+
+  /// Sends the request `request` builds from the daemon handle of `fh` and returns the result
+  /// of the response with what follows and the session it was answered on, failing with the
+  /// errno the daemon answered or EIO when it did not.
+  fn call(&self, fh: Option<u64>, request: impl Fn(u64) -> Encoder) -> Result<(i32, Decoder, u64)> {
+    let mut connection = self.connection.lock().unwrap();
+    let reused = connection.stream.is_some();
+    if !reused {
+      self.reconnect(&mut connection)?;
+    }
+    let handle = connection.handle(fh)?;
+    let mut stream = connection.stream.take().unwrap();
+    if send(&mut stream, &request(handle)).is_err() {
+      if !reused {
+        return Err(Errno::EIO);
+      }
+      // The daemon went away since the last operation, which this one never reached
+      self.reconnect(&mut connection)?;
+      let handle = connection.handle(fh)?;
+      stream = connection.stream.take().unwrap();
+      send(&mut stream, &request(handle)).map_err(|_| Errno::EIO)?;
+    }
+    // Left closed when anything goes wrong, a late response being of no use to the next one
+    let mut response = receive(&mut stream).map_err(|_| Errno::EIO)?;
+    let res = response.i32().map_err(|_| Errno::EIO)?;
+    connection.stream = Some(stream);
+    if res < 0 {
+      return Err(match Errno::from_raw(-res) {
+        Errno::UnknownErrno => Errno::EIO,
+        errno => errno
+      });
+    }
+    Ok((res, response, connection.session))
+  }
+
+  /// Runs an operation with no result but success.
+  fn call_unit(&self, request: Encoder) -> Result<()> {
+    self.call(None, |_| request.clone()).map(drop)
+  }
+
+  /// Runs an operation opening a handle, which is given out in place of the one of the daemon.
+  fn call_open(&self, request: Encoder) -> Result<u64> {
+    let (_, mut response, session) = self.call(None, |_| request.clone())?;
+    let fh = response.u64().map_err(malformed)?;
+    let mut connection = self.connection.lock().unwrap();
+    connection.next_handle += 1;
+    let handle = connection.next_handle;
+    connection.handles.insert(handle, (session, fh));
+    Ok(handle)
+  }
+
+  /// Runs an operation releasing the handle `fh`, which is forgotten whether it succeeds or not.
+  fn call_release(&self, fh: u64, request: impl Fn(u64) -> Encoder) -> Result<()> {
+    let res = self.call(Some(fh), request).map(drop);
+    self.connection.lock().unwrap().handles.remove(&fh);
+    res
+  }
+}
+
+/// A response that does not hold what its operation returns.
+fn malformed(_: io::Error) -> Errno {
+  Errno::EIO
+}
+
+impl Filesystem for Remote {
+  fn capabilities(&self) -> u64 {
+    self.capabilities
+  }
+
+  fn open(&self, path: &str, flags: i32) -> Result<u64> {
+    self.call_open(Encoder::request(OPEN).str(path).i32(flags))
+  }
+
+  fn close(&self, path: &str, fh: u64) -> Result<()> {
+    self.call_release(fh, |fh| Encoder::request(CLOSE).str(path).u64(fh))
+  }
+
+  fn read(&self, path: &str, buf: &mut [u8], offset: i64, fh: u64) -> Result<usize> {
+    let (_, mut response, _) = self.call(Some(fh), |fh| Encoder::request(READ).str(path).u64(buf.len() as u64).i64(offset).u64(fh))?;
+    let data = response.bytes().map_err(malformed)?;
+    let len = data.len().min(buf.len());
+    buf[..len].copy_from_slice(&data[..len]);
+    Ok(len)
+  }
+
+  fn getattr(&self, path: &str) -> Result<Stat> {
+    let (_, mut response, _) = self.call(None, |_| Encoder::request(GETATTR).str(path))?;
+    response.stat().map_err(malformed)
+  }
+
+  fn opendir(&self, path: &str) -> Result<u64> {
+    self.call_open(Encoder::request(OPENDIR).str(path))
+  }
+
+  fn readdir(&self, path: &str, offset: i64, fh: u64, filler: &mut dyn FnMut(&str, Option<&Stat>) -> bool) -> Result<()> {
+    let (_, mut response, _) = self.call(Some(fh), |fh| Encoder::request(READDIR).str(path).i64(offset).u64(fh))?;
+    for _ in 0..response.u32().map_err(malformed)? {
+      let name = response.str().map_err(malformed)?;
+      let stat = if response.u8().map_err(malformed)? != 0 {
+        Some(response.stat().map_err(malformed)?)
+      } else {
+        None
+      };
+      if filler(&name, stat.as_ref()) {
+        break;
+      }
+    }
+    Ok(())
+  }
+
+  fn releasedir(&self, path: &str, fh: u64) -> Result<()> {
+    self.call_release(fh, |fh| Encoder::request(RELEASEDIR).str(path).u64(fh))
+  }
+
+  fn write(&self, path: &str, buf: &[u8], offset: i64, fh: u64) -> Result<usize> {
+    let (len, _, _) = self.call(Some(fh), |fh| Encoder::request(WRITE).str(path).bytes(buf).i64(offset).u64(fh))?;
+    Ok((len as usize).min(buf.len()))
+  }
+
+  fn create(&self, path: &str, mode: u32) -> Result<()> {
+    self.call_unit(Encoder::request(CREATE).str(path).u32(mode))
+  }
+
+  fn truncate(&self, path: &str, size: i64) -> Result<()> {
+    self.call_unit(Encoder::request(TRUNCATE).str(path).i64(size))
+  }
+
+  fn mkdir(&self, path: &str, mode: u32) -> Result<()> {
+    self.call_unit(Encoder::request(MKDIR).str(path).u32(mode))
+  }
+
+  fn rmdir(&self, path: &str) -> Result<()> {
+    self.call_unit(Encoder::request(RMDIR).str(path))
+  }
+
+  fn unlink(&self, path: &str) -> Result<()> {
+    self.call_unit(Encoder::request(UNLINK).str(path))
+  }
+
+  fn rename(&self, from: &str, to: &str, flags: u32) -> Result<()> {
+    self.call_unit(Encoder::request(RENAME).str(from).str(to).u32(flags))
+  }
+
+  fn symlink(&self, target: &str, path: &str) -> Result<()> {
+    self.call_unit(Encoder::request(SYMLINK).str(target).str(path))
+  }
+
+  fn link(&self, from: &str, to: &str) -> Result<()> {
+    self.call_unit(Encoder::request(LINK).str(from).str(to))
+  }
+
+  fn readlink(&self, path: &str) -> Result<String> {
+    let (_, mut response, _) = self.call(None, |_| Encoder::request(READLINK).str(path))?;
+    response.str().map_err(malformed)
+  }
+
+  fn access(&self, path: &str, mask: i32) -> Result<()> {
+    self.call_unit(Encoder::request(ACCESS).str(path).i32(mask))
+  }
+}
+
+/// The response of an operation failing with `errno`. EIO stands in for UnknownErrno, whose 0
+/// would tell success.
+fn failure(errno: Errno) -> Encoder {
+  match errno {
+    Errno::UnknownErrno => Encoder::response(-(Errno::EIO as i32)),
+    errno => Encoder::response(-(errno as i32))
+  }
+}
+
+/// The response of `fs` to `request`.
+fn handle(fs: &dyn Filesystem, mut request: Decoder) -> io::Result<Encoder> {
+  let unit = |res: Result<()>| match res {
+    Ok(()) => Encoder::response(0),
+    Err(errno) => failure(errno)
+  };
+  let response = match request.u32()? {
+    OPEN => match fs.open(&request.str()?, request.i32()?) {
+      Ok(fh) => Encoder::response(0).u64(fh),
+      Err(errno) => failure(errno)
+    },
+    CLOSE => unit(fs.close(&request.str()?, request.u64()?)),
+    READ => {
+      let path = request.str()?;
+      let mut buf = vec![0u8; request.u64()?.min(MAX_MESSAGE as u64 / 2) as usize];
+      match fs.read(&path, &mut buf, request.i64()?, request.u64()?) {
+        Ok(len) => Encoder::response(len as i32).bytes(&buf[..len.min(buf.len())]),
+        Err(errno) => failure(errno)
+      }
+    },
+    GETATTR => match fs.getattr(&request.str()?) {
+      Ok(stat) => Encoder::response(0).stat(&stat),
+      Err(errno) => failure(errno)
+    },
+    OPENDIR => match fs.opendir(&request.str()?) {
+      Ok(fh) => Encoder::response(0).u64(fh),
+      Err(errno) => failure(errno)
+    },
+    READDIR => {
+      let path = request.str()?;
+      let (mut count, mut entries) = (0, Encoder::default());
+      let res = fs.readdir(&path, request.i64()?, request.u64()?, &mut |name, stat| {
+        count += 1;
+        entries = match stat {
+          Some(stat) => std::mem::take(&mut entries).str(name).u8(1).stat(stat),
+          None => std::mem::take(&mut entries).str(name).u8(0)
+        };
+        entries.0.len() > MAX_MESSAGE as usize / 2
+      });
+      match res {
+        Ok(()) => {
+          let mut response = Encoder::response(0).u32(count);
+          response.0.extend(entries.0);
+          response
+        },
+        Err(errno) => failure(errno)
+      }
+    },
+    RELEASEDIR => unit(fs.releasedir(&request.str()?, request.u64()?)),
+    WRITE => {
+      let path = request.str()?;
+      let data = request.bytes()?;
+      match fs.write(&path, &data, request.i64()?, request.u64()?) {
+        Ok(len) => Encoder::response(len as i32),
+        Err(errno) => failure(errno)
+      }
+    },
+    CREATE => unit(fs.create(&request.str()?, request.u32()?)),
+    TRUNCATE => unit(fs.truncate(&request.str()?, request.i64()?)),
+    MKDIR => unit(fs.mkdir(&request.str()?, request.u32()?)),
+    RMDIR => unit(fs.rmdir(&request.str()?)),
+    UNLINK => unit(fs.unlink(&request.str()?)),
+    RENAME => {
+      let from = request.str()?;
+      unit(fs.rename(&from, &request.str()?, request.u32()?))
+    },
+    SYMLINK => {
+      let target = request.str()?;
+      unit(fs.symlink(&target, &request.str()?))
+    },
+    LINK => {
+      let from = request.str()?;
+      unit(fs.link(&from, &request.str()?))
+    },
+    READLINK => match fs.readlink(&request.str()?) {
+      Ok(target) => Encoder::response(0).str(&target),
+      Err(errno) => failure(errno)
+    },
+    ACCESS => unit(fs.access(&request.str()?, request.i32()?)),
+    _ => Encoder::response(-(Errno::ENOSYS as i32))
+  };
+  Ok(response)
+}
+
+/// Serves the mount of a connection, set up by its first request.
+fn session<F: Filesystem>(mut stream: UnixStream, new: &impl Fn(&[&str]) -> Result<F>) -> io::Result<()> {
+  let mut request = receive(&mut stream)?;
+  if request.u32()? != INIT {
+    return send(&mut stream, &Encoder::response(-(Errno::EINVAL as i32)));
+  }
+  let mut opts = vec![];
+  for _ in 0..request.u32()? {
+    opts.push(request.str()?);
+  }
+  let fs = match new(&opts.iter().map(String::as_str).collect::<Vec<&str>>()) {
+    Ok(fs) => fs,
+    Err(errno) => return send(&mut stream, &failure(errno))
+  };
+  send(&mut stream, &Encoder::response(0).u64(fs.capabilities()))?;
+  loop {
+    let request = match receive(&mut stream) {
+      Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+      request => request?
+    };
+    send(&mut stream, &handle(&fs, request)?)?;
+  }
+}
+
+/// Serves the mounts of every connection to `listener`, each with the filesystem `new` builds
+/// from its options, until accepting fails. Meant to be the main loop of a plugin daemon.
+pub fn serve<F: Filesystem + 'static>(listener: UnixListener, new: impl Fn(&[&str]) -> Result<F> + Send + Sync + 'static) -> io::Result<()> {
+  let new = Arc::new(new);
+  for stream in listener.incoming() {
+    let stream = stream?;
+    let new = new.clone();
+    thread::spawn(move || session(stream, &*new));
+  }
+  Ok(())
+}
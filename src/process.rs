@@ -0,0 +1,100 @@
+use std::sync::{Arc, RwLock};
+use dashmap::DashMap;
+use nix::libc;
+use typed_path::NativePathBuf;
+use crate::mounts::OpenFile;
+
+/// A fd of the table, along with whether execve closes it.
+#[derive(Clone)]
+struct Fd {
+  file: OpenFile,
+  cloexec: bool
+}
+
+/// The fds of a process that refer to mounted files, by their number in the tracee.
+#[derive(Default)]
+pub struct FdTable {
+  fds: DashMap<u16, Fd>
+}
+
+impl FdTable {
+  pub fn get(&self, fd: u16) -> Option<OpenFile> {
+    self.fds.get(&fd).map(|entry| entry.file.clone())
+  }
+
+  pub fn insert(&self, fd: u16, file: OpenFile, cloexec: bool) {
+    self.fds.insert(fd, Fd { file, cloexec });
+  }
+
+  pub fn remove(&self, fd: u16) -> Option<OpenFile> {
+    self.fds.remove(&fd).map(|(_, entry)| entry.file)
+  }
+
+  /// Sets whether execve closes `fd`, as `FD_CLOEXEC` does.
+  pub fn set_cloexec(&self, fd: u16, cloexec: bool) {
+    if let Some(mut entry) = self.fds.get_mut(&fd) {
+      entry.cloexec = cloexec;
+    }
+  }
+
+  /// The fds execve closes.
+  pub fn cloexec(&self) -> Vec<u16> {
+    self.fds.iter().filter(|entry| entry.cloexec).map(|entry| *entry.key()).collect()
+  }
+
+  /// Takes every open file out of the table.
+  pub fn drain(&self) -> Vec<OpenFile> {
+    let fds: Vec<u16> = self.fds.iter().map(|entry| *entry.key()).collect();
+    fds.into_iter().filter_map(|fd| self.remove(fd)).collect()
+  }
+
+  /// A copy of the table whose fds share their open files with this one, as after fork.
+  fn duplicate(&self) -> FdTable {
+    FdTable {
+      fds: self.fds.iter().map(|entry| (*entry.key(), entry.value().clone())).collect()
+    }
+  }
+}
+
+/// What the tracer emulates for a traced process, possibly shared with other processes.
+#[derive(Clone)]
+pub struct Process {
+  pub fds: Arc<FdTable>,
+  pub cwd: Arc<RwLock<NativePathBuf>>
+}
+
+impl Process {
+  pub fn new(cwd: NativePathBuf) -> Process {
+    Process {
+      fds: Arc::new(FdTable::default()),
+      cwd: Arc::new(RwLock::new(cwd))
+    }
+  }
+
+  /// Applies an execve to the state, which unshares the fd table. Returns the files of the
+  /// fds it closes.
+  pub fn exec(&mut self) -> Vec<OpenFile> {
+    let fds = self.fds.duplicate();
+    let closed = fds.cloexec().into_iter().filter_map(|fd| fds.remove(fd)).collect();
+    self.fds = Arc::new(fds);
+    closed
+  }
+
+  /// The state of a child created by clone with `flags`. The fd table and cwd are shared under
+  /// `CLONE_FILES` and `CLONE_FS` and copied otherwise, fork and vfork taking no flags.
+  pub fn fork(&self, flags: u64) -> Process {
+    let flags = flags as i32;
+    Process {
+      fds: if flags & libc::CLONE_FILES != 0 {
+        self.fds.clone()
+      } else {
+        Arc::new(self.fds.duplicate())
+      },
+      cwd: if flags & libc::CLONE_FS != 0 {
+        self.cwd.clone()
+      } else {
+        Arc::new(RwLock::new(self.cwd.read().unwrap().clone()))
+      }
+    }
+  }
+}
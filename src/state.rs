@@ -1,21 +1,72 @@
-use std::{ffi::CString, os::fd::IntoRawFd, sync::RwLock};
-use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+use std::{ffi::CString, os::fd::IntoRawFd, sync::{Arc, RwLock}};
+use dashmap::DashMap;
+use nix::{sys::memfd::{memfd_create, MemFdCreateFlag}, unistd::Pid};
 use typed_path::NativePathBuf;
 
-use crate::mounts::Mounts;
+use crate::{mounts::{Mounts, OpenFile}, plugin::{Libraries, Plugin}, process::Process};
 
 pub struct State {
   pub mounts: Mounts,
+  /// Libraries the plugins of the mounts come from
+  pub libraries: Libraries,
+  /// Working directory of the first traced process, the others inheriting theirs
   pub cwd: RwLock<NativePathBuf>,
-  pub execve_fd: RwLock<u16>
+  pub processes: DashMap<Pid, Process>,
+  pub execve_fd: RwLock<u16>,
+  /// Called once a signal stopped the first traced process, which stays stopped until sent
+  /// SIGCONT. The tracer waits for it to return
+  pub on_stop: Option<fn()>
+}
+
+impl State {
+  /// The state of the traced process `pid`, registered on first use.
+  pub fn process(&self, pid: Pid) -> Process {
+    self.processes.entry(pid)
+      .or_insert_with(|| Process::new(self.cwd.read().unwrap().clone()))
+      .clone()
+  }
+
+  /// Forgets the exited process `pid`, releasing the files no other process holds.
+  pub fn exit_process(&self, pid: Pid) {
+    let Some((_, process)) = self.processes.remove(&pid) else { return };
+    if let Some(fds) = Arc::into_inner(process.fds) {
+      self.release(fds.drain());
+    }
+  }
+
+  /// Applies the execve `pid` went through, releasing the files of the fds it closed.
+  pub fn exec_process(&self, pid: Pid) {
+    let Some(mut process) = self.processes.get_mut(&pid) else { return };
+    let shared = process.fds.clone();
+    let closed = process.exec();
+    drop(process);
+    self.release(closed);
+    // The table left behind is only held by the threads execve ended, if by anything
+    if let Some(fds) = Arc::into_inner(shared) {
+      self.release(fds.drain());
+    }
+  }
+
+  /// Drops references to `files`, giving the handles no fd holds anymore back to the plugins.
+  pub fn release(&self, files: Vec<OpenFile>) {
+    for file in files {
+      let mountpath = file.read().unwrap().mountpath.clone();
+      if let Some(mount) = self.mounts.get_mount(&mountpath) {
+        let _ = mount.release(file);
+      }
+    }
+  }
 }
 
 impl Default for State {
   fn default() -> Self {
     State {
-      mounts: Mounts::new(&[]),
+      mounts: Mounts::new::<Arc<Plugin>>(&[]),
+      libraries: Libraries::default(),
       cwd: RwLock::new(NativePathBuf::new()),
-      execve_fd: RwLock::new(memfd_create(CString::new("mountbox").unwrap().as_c_str(), MemFdCreateFlag::empty()).unwrap().into_raw_fd() as u16)
+      processes: DashMap::new(),
+      execve_fd: RwLock::new(memfd_create(CString::new("mountbox").unwrap().as_c_str(), MemFdCreateFlag::empty()).unwrap().into_raw_fd() as u16),
+      on_stop: None
     }
   }
-}
\ No newline at end of file
+}
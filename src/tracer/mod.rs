@@ -1,33 +1,36 @@
-use std::{cell::RefCell, sync::Arc, thread};
-use nix::{errno::Errno, sys::{signal, wait::{waitpid, WaitStatus}}};
+use std::{cell::Cell, collections::HashSet, sync::Arc};
+use nix::{errno::Errno, libc, sys::{signal, wait::{waitpid, WaitPidFlag, WaitStatus}}};
 use crate::{plugin, state::State};
+use router::Syscall;
 
 mod ptrace;
 mod router;
+mod seccomp;
+pub mod notify;
+
+pub use seccomp::install_filter;
 
 impl plugin::PluginError {
   fn to_errno(&self) -> i32 {
     match self {
-      plugin::PluginError::UNKNOWN => nix::libc::EPERM,
-      plugin::PluginError::EPERM => nix::libc::EPERM,
-      plugin::PluginError::ENOENT => nix::libc::ENOENT,
+      plugin::PluginError::UNKNOWN => nix::libc::EIO,
+      plugin::PluginError::Errno(errno) => *errno as i32,
+      // Only ever met loading plugins
+      plugin::PluginError::MissingPlugin(_) | plugin::PluginError::AbiVersion(_) => nix::libc::EIO
     }
   }
 }
 
-macro_rules! waitpid {
-  ($pid:expr $(, $body:expr)?) => {
-    match waitpid($pid, None)? {
-      WaitStatus::Exited(_, code) => {
-        return Ok(TraceeStatus::Exited(code as u8));
-      },
-      WaitStatus::Signaled(_, signal, _) => {
-        return Ok(TraceeStatus::Killed(signal))
-      },
-      $(WaitStatus::PtraceSyscall(_) => $body,)?
-      _ => {}
-    };
-  };
+impl router::RouterError {
+  /// The errno a syscall failing with this error reports.
+  fn to_errno(&self) -> i32 {
+    match self {
+      router::RouterError::PluginError(err) => err.to_errno(),
+      router::RouterError::PtraceError(errno) => *errno as i32,
+      router::RouterError::IOError(e) => e.raw_os_error().unwrap_or(libc::EPERM),
+      router::RouterError::TraceeExited(_) | router::RouterError::TraceeKilled(_) => libc::ESRCH
+    }
+  }
 }
 
 #[derive(PartialEq, Debug)]
@@ -36,106 +39,334 @@ pub enum TraceeStatus {
   Killed(signal::Signal)
 }
 
-pub fn attach(state: Arc<State>, pid: ptrace::Pid) -> Result<TraceeStatus, Errno> {
-  let res = _attach(state, pid);
-  if let Err(Errno::ESRCH) = res {
-    waitpid!(pid); // Retrieve exit code
+impl TraceeStatus {
+  fn of(status: WaitStatus) -> Option<TraceeStatus> {
+    match status {
+      WaitStatus::Exited(_, code) => Some(TraceeStatus::Exited(code as u8)),
+      WaitStatus::Signaled(_, signal, _) => Some(TraceeStatus::Killed(signal)),
+      _ => None
+    }
   }
-  res
 }
 
-pub fn _attach(state: Arc<State>, pid: ptrace::Pid) -> Result<TraceeStatus, Errno> {
-  ptrace::attach(pid)?;
-  waitpid!(pid); // TODO: support multiple tid per pid
-  ptrace::setoptions(pid, ptrace::Options::PTRACE_O_TRACESYSGOOD)?;
-  ptrace::syscall(pid, None)?;
-  let mut threads: Vec<thread::JoinHandle<Result<TraceeStatus, Errno>>> = vec![];
-  loop {
-    waitpid!(pid, {
-      let is_called = RefCell::new(false);
-      let wait_ptrace_ret = || {
-        ptrace::syscall(pid, None)?;
-        let sig = waitpid(pid, None)?;
-        *is_called.borrow_mut() = true;
-        return match sig {
-          WaitStatus::PtraceSyscall(_) => Ok(()),
-          WaitStatus::Exited(_, code) => Err(router::RouterError::TraceeExited(code)),
-          WaitStatus::Signaled(_, signal, _) => Err(router::RouterError::TraceeKilled(signal)),
-          _ => unreachable!()
-        }
-      };
-      macro_rules! wait_ptrace_ret {
-        () => {
-          if let Err(err) = wait_ptrace_ret() {
-            if let router::RouterError::TraceeExited(code) = err {
-              return Ok(TraceeStatus::Exited(code as u8));
-            } else if let router::RouterError::PtraceError(errno) = err {
-              // Irrecoverable ptrace error
-              return Err(errno);
-            } else {
-              unreachable!();
-            }
-          }
-        };
+/// The clone flags of a syscall creating a thread or process, fork and vfork taking none.
+fn clone_flags(tid: ptrace::Pid, regs: ptrace::Regs) -> Option<u64> {
+  #[cfg(target_arch="x86_64")]
+  if ptrace::syscall_info(tid).ok()?.arch == ptrace::AUDIT_ARCH_I386 {
+    // The first i386 argument is in ebx
+    return match ptrace::getreg!(regs, syscall_nr) {
+      ptrace::i386_syscall_nr!(fork) | ptrace::i386_syscall_nr!(vfork) => Some(0),
+      ptrace::i386_syscall_nr!(clone) => Some(regs.rbx as u32 as u64),
+      ptrace::i386_syscall_nr!(clone3) => ptrace::read_bytes(tid, regs.rbx as u32 as u64, 8).ok()
+        .map(|flags| u64::from_ne_bytes(flags.try_into().unwrap())),
+      _ => None
+    };
+  }
+  match ptrace::getreg!(regs, syscall_nr) {
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(fork) | ptrace::syscall_nr!(vfork) => Some(0),
+    ptrace::syscall_nr!(clone) => Some(ptrace::getreg!(regs, arg0)),
+    // The flags lead struct clone_args
+    ptrace::syscall_nr!(clone3) => ptrace::read_bytes(tid, ptrace::getreg!(regs, arg0), 8).ok()
+      .map(|flags| u64::from_ne_bytes(flags.try_into().unwrap())),
+    _ => None
+  }
+}
+
+/// The registers to route a syscall by, with its number and arguments in `regs` as the
+/// native ABI has them. None for syscalls of other ABIs that are not routed.
+fn routed_regs(regs: ptrace::Regs, arch: u32, nr: u64, args: [u64; 6]) -> Option<(router::Abi, ptrace::Regs)> {
+  let (abi, nr) = match arch {
+    ptrace::AUDIT_ARCH => (router::Abi::Native, nr),
+    #[cfg(target_arch="x86_64")]
+    ptrace::AUDIT_ARCH_I386 => (router::Abi::I386, ptrace::I386_SYSCALLS.iter().find(|(i386, _)| *i386 == nr)?.1),
+    _ => return None
+  };
+  let mut regs = regs;
+  ptrace::getreg!(regs, syscall_nr) = nr;
+  ptrace::getreg!(regs, arg0) = args[0];
+  ptrace::getreg!(regs, arg1) = args[1];
+  ptrace::getreg!(regs, arg2) = args[2];
+  ptrace::getreg!(regs, arg3) = args[3];
+  ptrace::getreg!(regs, arg4) = args[4];
+  ptrace::getreg!(regs, arg5) = args[5];
+  Some((abi, regs))
+}
+
+/// A syscall a ptrace tracee is stopped at the entry of.
+struct PtraceSyscall {
+  tid: ptrace::Pid,
+  /// The registers of the tracee, whatever the ABI
+  regs: ptrace::Regs,
+  abi: router::Abi,
+  /// Whether the syscall-exit-stop was reached
+  done: Cell<bool>,
+  /// Whether the syscall was an execve that succeeded
+  execed: Cell<bool>
+}
+
+impl PtraceSyscall {
+  /// Runs the syscall to its syscall-exit-stop.
+  fn wait(&self) -> router::Result<()> {
+    if self.done.replace(true) {
+      return Ok(());
+    }
+    ptrace::syscall(self.tid, None)?;
+    // Only this thread is waited for, the others are left stopped in the meantime
+    loop {
+      return match waitpid(self.tid, Some(WaitPidFlag::__WALL))? {
+        WaitStatus::PtraceSyscall(_) => Ok(()),
+        // A successful execve reports itself before returning
+        WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_EXEC) => {
+          self.execed.set(true);
+          ptrace::syscall(self.tid, None)?;
+          continue;
+        },
+        WaitStatus::Exited(_, code) => Err(router::RouterError::TraceeExited(code)),
+        WaitStatus::Signaled(_, signal, _) => Err(router::RouterError::TraceeKilled(signal)),
+        _ => unreachable!()
       }
-      let regs = ptrace::getregs(pid)?;
-      if matches!(ptrace::getreg!(regs, syscall_nr), ptrace::syscall_nr!(exit_group) | ptrace::syscall_nr!(exit)) {
-        for thread in threads {
-          thread.join().unwrap()?;
+    }
+  }
+}
+
+impl router::Syscall for PtraceSyscall {
+  fn ret(&self, value: i64) -> router::Result<()> {
+    if !self.done.get() {
+      let mut regs = self.regs;
+      ptrace::getreg!(regs, syscall_nr) = u64::MAX;
+      ptrace::setregs(self.tid, regs)?;
+      self.wait()?;
+    }
+    let mut regs = ptrace::getregs(self.tid)?;
+    ptrace::getreg!(regs, return_value) = value as u64;
+    ptrace::setregs(self.tid, regs)?;
+    Ok(())
+  }
+
+  /// Turns the syscall into a `memfd_create`, so that the fd is numbered by the tracee's own
+  /// fd table.
+  fn ret_fd(&self, name: &[u8], flags: i32) -> router::Result<i64> {
+    let mut sp = self.scratch()?;
+    let mut injected = self.regs;
+    ptrace::getreg!(injected, syscall_nr) = ptrace::syscall_nr!(memfd_create);
+    ptrace::getreg!(injected, arg0) = ptrace::push_cstr(self.tid, &mut sp, name)?;
+    ptrace::getreg!(injected, arg1) = if flags & libc::O_CLOEXEC != 0 { libc::MFD_CLOEXEC as u64 } else { 0 };
+    ptrace::setregs(self.tid, injected)?;
+    self.wait()?;
+    // Only the return value may tell that another syscall ran
+    let mut result = ptrace::getregs(self.tid)?;
+    let fd = ptrace::getreg!(result, return_value);
+    ptrace::getreg!(result, arg0) = ptrace::getreg!(self.regs, arg0);
+    ptrace::getreg!(result, arg1) = ptrace::getreg!(self.regs, arg1);
+    // Where it shares a register with the first argument
+    ptrace::getreg!(result, return_value) = fd;
+    ptrace::setregs(self.tid, result)?;
+    Ok(fd as i64)
+  }
+
+  /// The kernel duplicates the fd as the syscall asks.
+  fn ret_dup(&self, _fd: i32, _newfd: Option<i32>, _min: i32, _flags: i32) -> router::Result<i64> {
+    self.wait()?;
+    Ok(ptrace::getreg!(ptrace::getregs(self.tid)?, return_value) as i64)
+  }
+
+  fn run(&self) -> router::Result<Option<i64>> {
+    self.wait()?;
+    Ok(Some(ptrace::getreg!(ptrace::getregs(self.tid)?, return_value) as i64))
+  }
+
+  /// A stopped tracee cannot go away meanwhile.
+  fn validate(&self) -> router::Result<()> {
+    Ok(())
+  }
+
+  /// Only native syscalls have their registers laid out as `regs`.
+  fn rewrite(&self, regs: ptrace::Regs) -> router::Result<()> {
+    if self.abi != router::Abi::Native {
+      return Err(Errno::ENOSYS.into());
+    }
+    Ok(ptrace::setregs(self.tid, regs)?)
+  }
+
+  fn scratch(&self) -> router::Result<u64> {
+    Ok(ptrace::scratch(&self.regs))
+  }
+
+  fn abi(&self) -> router::Abi {
+    self.abi
+  }
+}
+
+struct Tracer<'s> {
+  state: &'s State,
+  root: ptrace::Pid,
+  tracees: HashSet<ptrace::Pid>,
+  /// New tracees whose first stop came before the event announcing them
+  unannounced: HashSet<ptrace::Pid>,
+  status: Option<TraceeStatus>
+}
+
+impl Tracer<'_> {
+  fn exited(&mut self, tid: ptrace::Pid, status: TraceeStatus) {
+    self.tracees.remove(&tid);
+    self.state.exit_process(tid);
+    if tid == self.root {
+      self.status = Some(status);
+    }
+  }
+
+  /// Follows a thread or process created by `tid`, which is stopped until then.
+  fn announce(&mut self, tid: ptrace::Pid, child: ptrace::Pid) -> Result<(), Errno> {
+    // Still stopped in the syscall creating it
+    let flags = clone_flags(tid, ptrace::getregs(tid)?).unwrap_or(0);
+    self.state.processes.insert(child, self.state.process(tid).fork(flags));
+    self.tracees.insert(child);
+    if self.unannounced.remove(&child) {
+      ptrace::cont(child, None)?;
+    }
+    Ok(())
+  }
+
+  /// Applies the execve `tid` went through, which ended the other threads of its process.
+  fn exec(&mut self, tid: ptrace::Pid) -> Result<(), Errno> {
+    // A thread other than the leader takes its tid, the leader going unreported
+    let former = ptrace::Pid::from_raw(ptrace::getevent(tid)? as i32);
+    if former != tid {
+      self.tracees.remove(&former);
+      self.state.exit_process(former);
+    }
+    self.state.exec_process(tid);
+    Ok(())
+  }
+
+  /// Routes the syscall `tid` is entering. Returns the status of `tid` if it ended meanwhile.
+  fn syscall_entry(&mut self, tid: ptrace::Pid) -> Result<Option<TraceeStatus>, Errno> {
+    let regs = ptrace::getregs(tid)?;
+    let info = ptrace::syscall_info(tid)?;
+    let Some((abi, routed)) = routed_regs(regs, info.arch, info.nr, info.args) else { return Ok(None) };
+    let syscall = PtraceSyscall { tid, regs, abi, done: Cell::new(false), execed: Cell::new(false) };
+    let res = match router::route(self.state, routed, tid, &syscall) {
+      Err(err @ (router::RouterError::TraceeExited(_) | router::RouterError::TraceeKilled(_))) => Err(err),
+      // The syscall already ran otherwise
+      Err(err) if !syscall.done.get() => syscall.ret(-err.to_errno() as i64),
+      _ => Ok(())
+    };
+    if syscall.execed.get() {
+      self.state.exec_process(tid);
+    }
+    match res {
+      Ok(()) => Ok(None),
+      Err(router::RouterError::TraceeExited(code)) => Ok(Some(TraceeStatus::Exited(code as u8))),
+      Err(router::RouterError::TraceeKilled(signal)) => Ok(Some(TraceeStatus::Killed(signal))),
+      Err(router::RouterError::PtraceError(errno)) => Err(errno),
+      Err(_) => unreachable!()
+    }
+  }
+
+  /// Handles the next stop of any tracee. Returns false once none is left.
+  fn step(&mut self) -> Result<bool, Errno> {
+    // __WNOTHREAD keeps the tracees of other tracer threads out
+    let status = match waitpid(None, Some(WaitPidFlag::__WALL | WaitPidFlag::__WNOTHREAD)) {
+      Err(Errno::ECHILD) => return Ok(false),
+      status => status?
+    };
+    let Some(tid) = status.pid() else { return Ok(true) };
+    if let Some(status) = TraceeStatus::of(status) {
+      self.exited(tid, status);
+      return Ok(true);
+    }
+    let mut signal = None;
+    match status {
+      WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_STOP) if !self.tracees.contains(&tid) => {
+        // Resumed once its parent reports it
+        self.unannounced.insert(tid);
+        return Ok(true);
+      },
+      // Stops only happen at the syscalls the seccomp filter lets through to the router
+      WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_SECCOMP) => match self.syscall_entry(tid) {
+        Ok(Some(status)) => {
+          self.exited(tid, status);
+          return Ok(true);
+        },
+        Ok(None) => {},
+        // Killed meanwhile, the exit is reported next
+        Err(Errno::ESRCH) => return Ok(true),
+        Err(err) => return Err(err)
+      },
+      WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK | libc::PTRACE_EVENT_CLONE) => {
+        let child = ptrace::Pid::from_raw(ptrace::getevent(tid)? as i32);
+        self.announce(tid, child)?;
+      },
+      WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_EXEC) => self.exec(tid)?,
+      WaitStatus::PtraceEvent(_, stop, libc::PTRACE_EVENT_STOP) if is_stop_signal(stop) => {
+        // Group-stop: the tracee stays stopped until SIGCONT, which reports it again
+        match ptrace::listen(tid) {
+          Err(Errno::ESRCH) => return Ok(true),
+          res => res?
         }
-        return Ok(TraceeStatus::Exited(ptrace::getreg!(regs, arg0) as u8));
-      } else if matches!(ptrace::getreg!(regs, syscall_nr), ptrace::syscall_nr!(vfork) | ptrace::syscall_nr!(fork)) {
-        wait_ptrace_ret!();
-        let pid = ptrace::Pid::from_raw(ptrace::getreg!(ptrace::getregs(pid)?, rax).cast_signed() as i32);
-        let s = state.clone();
-        let join = thread::spawn(move || -> Result<TraceeStatus, Errno> {
-          attach(s, pid)
-        });
-        threads.push(join);
-      } else {
-        match router::route(&state, regs, pid, wait_ptrace_ret) {
-          Ok(_) => {},
-          Err(router::RouterError::TraceeExited(code)) => {
-            return Ok(TraceeStatus::Exited(code as u8));
-          },
-          Err(router::RouterError::TraceeKilled(signal)) => {
-            return Ok(TraceeStatus::Killed(signal))
-          },
-          Err(router::RouterError::PluginError(err)) => {
-            dbg!(&err);
-            if !*is_called.borrow() {
-              // Propagate plugin error to syscall error
-              wait_ptrace_ret!();
-              ptrace::setregs(pid, ptrace::user_regs_struct {
-                rax: -err.to_errno() as u64,
-                ..ptrace::getregs(pid)?
-              }).unwrap();
-            }
-          },
-          Err(router::RouterError::PtraceError(errno)) => {
-            dbg!(errno);
-            if !*is_called.borrow() {
-              wait_ptrace_ret!();
-              ptrace::setregs(pid, ptrace::user_regs_struct {
-                rax: -(errno as i64) as u64,
-                ..ptrace::getregs(pid)?
-              })?;
-            }
-          }
-          Err(router::RouterError::IOError(e)) => {
-            dbg!(&e);
-            if !*is_called.borrow() {
-              wait_ptrace_ret!();
-              ptrace::setregs(pid, ptrace::user_regs_struct {
-                rax: -e.raw_os_error().unwrap_or(nix::libc::EPERM) as u64,
-                ..ptrace::getregs(pid)?
-              }).unwrap();
-            }
-          }
+        if tid == self.root && let Some(on_stop) = self.state.on_stop {
+          on_stop();
         }
-        assert!(*is_called.borrow(), "wait_ptrace_ret must be called");
+        return Ok(true);
+      },
+      WaitStatus::Stopped(_, stop) => signal = Some(stop),
+      _ => {}
+    }
+    match ptrace::cont(tid, signal) {
+      Err(Errno::ESRCH) => Ok(true),
+      res => res.map(|_| true)
+    }
+  }
+}
+
+fn is_stop_signal(signal: signal::Signal) -> bool {
+  matches!(signal, signal::Signal::SIGSTOP | signal::Signal::SIGTSTP | signal::Signal::SIGTTIN | signal::Signal::SIGTTOU)
+}
+
+/// Traces `pid` along with every thread and process it creates, serving their syscalls on
+/// the mounts until all of them are gone. Returns how `pid` ended.
+///
+/// `pid` is expected to stop itself with SIGSTOP to wait for the tracer, which takes over
+/// that stop, then to install the filter of [`install_filter`] before running anything to
+/// trace. Any other signal is delivered to the tracees as usual.
+pub fn attach(state: Arc<State>, pid: ptrace::Pid) -> Result<TraceeStatus, Errno> {
+  let options = ptrace::Options::PTRACE_O_TRACESYSGOOD | ptrace::Options::PTRACE_O_TRACECLONE
+    | ptrace::Options::PTRACE_O_TRACEFORK | ptrace::Options::PTRACE_O_TRACEVFORK
+    | ptrace::Options::PTRACE_O_TRACEEXEC | ptrace::Options::PTRACE_O_TRACESECCOMP;
+  match ptrace::seize(pid, options) {
+    // Retrieve exit code
+    Err(Errno::ESRCH) => return TraceeStatus::of(waitpid(pid, None)?).ok_or(Errno::ESRCH),
+    res => res?
+  }
+  let mut continuing = false;
+  loop {
+    match waitpid(pid, Some(WaitPidFlag::__WALL))? {
+      // Stopped after the seize: the SIGSTOP is just not delivered
+      WaitStatus::Stopped(_, signal::Signal::SIGSTOP) if !continuing => break,
+      // Stopped before: the group-stop is ended, otherwise new tracees would join it
+      WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_STOP) if !continuing => {
+        signal::kill(pid, signal::Signal::SIGCONT)?;
+        continuing = true;
+        ptrace::cont(pid, None)?;
+      },
+      WaitStatus::Stopped(_, signal::Signal::SIGCONT) if continuing => break,
+      // Notified of the SIGCONT first
+      WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_STOP) => ptrace::cont(pid, None)?,
+      WaitStatus::Stopped(_, signal) => ptrace::cont(pid, signal)?,
+      status => if let Some(status) = TraceeStatus::of(status) {
+        return Ok(status);
       }
-    });
-    ptrace::syscall(pid, None)?;
+    }
   }
-}
\ No newline at end of file
+  ptrace::cont(pid, None)?;
+  let mut tracer = Tracer {
+    state: &state,
+    root: pid,
+    tracees: HashSet::from([pid]),
+    unannounced: HashSet::new(),
+    status: None
+  };
+  while tracer.step()? {}
+  tracer.status.ok_or(Errno::ECHILD)
+}
@@ -0,0 +1,291 @@
+//! Backend serving the syscalls of the router through seccomp user notifications, leaving
+//! the tracees free to be traced by debuggers.
+use std::{cell::Cell, collections::HashSet, ffi::CString, os::{fd::{AsRawFd, FromRawFd, OwnedFd, RawFd}, unix::ffi::OsStrExt}, sync::Arc};
+use nix::{errno::Errno, libc, sys::{signal, wait::{waitid, waitpid, Id, WaitPidFlag, WaitStatus}}};
+use crate::state::State;
+use super::{ptrace, router::{self, Syscall}, routed_regs, seccomp, TraceeStatus};
+
+// linux/seccomp.h ioctls, missing from libc
+const SECCOMP_IOCTL_NOTIF_RECV: libc::c_ulong = 0xc050_2100;
+const SECCOMP_IOCTL_NOTIF_SEND: libc::c_ulong = 0xc018_2101;
+const SECCOMP_IOCTL_NOTIF_ID_VALID: libc::c_ulong = 0x4008_2102;
+const SECCOMP_IOCTL_NOTIF_ADDFD: libc::c_ulong = 0x4018_2103;
+
+/// How often processes are checked for having exited or stopped, as nothing notifies about it
+const REAP_INTERVAL_MS: libc::c_int = 1000;
+
+/// Installs the filter notifying the tracer of routed syscalls, whose listener fd is left
+/// for the tracer to take in the calling process.
+///
+/// Meant to be called by the command to trace before it stops itself for [`attach`]: filtered
+/// syscalls block until the tracer answers.
+pub fn install_filter() -> Result<(), Errno> {
+  seccomp::install(libc::SECCOMP_RET_USER_NOTIF, libc::SECCOMP_FILTER_FLAG_NEW_LISTENER).map(drop)
+}
+
+/// A syscall a tracee is blocked in until it gets a response.
+struct NotifiedSyscall<'l> {
+  listener: &'l OwnedFd,
+  id: u64,
+  tid: ptrace::Pid,
+  abi: router::Abi,
+  /// Whether the response was sent
+  done: Cell<bool>
+}
+
+impl NotifiedSyscall<'_> {
+  fn respond(&self, val: i64, error: i32, flags: u32) -> router::Result<()> {
+    self.done.set(true);
+    let mut resp = libc::seccomp_notif_resp { id: self.id, val, error, flags };
+    match Errno::result(unsafe { libc::ioctl(self.listener.as_raw_fd(), SECCOMP_IOCTL_NOTIF_SEND, &mut resp) }) {
+      // Interrupted by a signal or killed meanwhile, with nothing left to answer
+      Err(Errno::ENOENT) => Ok(()),
+      res => res.map(drop).map_err(Into::into)
+    }
+  }
+
+  /// Fails the syscall with `errno`, which is returned negated.
+  fn fail(&self, errno: Errno) -> router::Result<i64> {
+    self.ret(-(errno as i64))?;
+    Ok(-(errno as i64))
+  }
+
+  /// Installs `srcfd` in the tracee, at `newfd` if given, answering the syscall with its number.
+  fn addfd(&self, srcfd: &OwnedFd, newfd: Option<i32>, flags: i32) -> router::Result<i64> {
+    let setfd = if newfd.is_some() { libc::SECCOMP_ADDFD_FLAG_SETFD } else { 0 };
+    let mut addfd = libc::seccomp_notif_addfd {
+      id: self.id,
+      flags: (libc::SECCOMP_ADDFD_FLAG_SEND | setfd) as u32,
+      srcfd: srcfd.as_raw_fd() as u32,
+      newfd: newfd.unwrap_or(0) as u32,
+      newfd_flags: (flags & libc::O_CLOEXEC) as u32
+    };
+    match Errno::result(unsafe { libc::ioctl(self.listener.as_raw_fd(), SECCOMP_IOCTL_NOTIF_ADDFD, &mut addfd) }) {
+      Ok(fd) => {
+        self.done.set(true);
+        Ok(fd as i64)
+      },
+      Err(Errno::ENOENT) => Err(Errno::ENOENT.into()),
+      // The tracee is out of fds, or `newfd` is beyond its limit
+      Err(errno) => self.fail(errno)
+    }
+  }
+}
+
+impl Syscall for NotifiedSyscall<'_> {
+  fn ret(&self, value: i64) -> router::Result<()> {
+    if value < 0 {
+      self.respond(0, value as i32, 0)
+    } else {
+      self.respond(value, 0, 0)
+    }
+  }
+
+  /// Installs a memfd of the tracer in the tracee, answering the syscall with its number.
+  fn ret_fd(&self, name: &[u8], flags: i32) -> router::Result<i64> {
+    let name = CString::new(name).map_err(|_| Errno::EINVAL)?;
+    let memfd = Errno::result(unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) })?;
+    let memfd = unsafe { OwnedFd::from_raw_fd(memfd) };
+    self.addfd(&memfd, None, flags)
+  }
+
+  /// Takes the file of the tracee fd and installs it back. Only the lowest free fd may be
+  /// given by the kernel, so the one from `min` is looked for in the tracee, which its other
+  /// threads could take meanwhile.
+  fn ret_dup(&self, fd: i32, newfd: Option<i32>, min: i32, flags: i32) -> router::Result<i64> {
+    let pid = parents(self.tid).map_or(self.tid, |(tgid, _)| tgid);
+    let pidfd = Errno::result(unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) })?;
+    let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd as RawFd) };
+    let file = match Errno::result(unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd.as_raw_fd(), fd, 0) }) {
+      Ok(file) => unsafe { OwnedFd::from_raw_fd(file as RawFd) },
+      Err(Errno::EBADF) => return self.fail(Errno::EBADF),
+      Err(errno) => return Err(errno.into())
+    };
+    if newfd.is_some() || min == 0 {
+      return self.addfd(&file, newfd, flags);
+    }
+    let mut limit: libc::rlimit = unsafe { std::mem::zeroed() };
+    Errno::result(unsafe { libc::prlimit(pid.as_raw(), libc::RLIMIT_NOFILE, std::ptr::null(), &mut limit) })?;
+    if min < 0 || min as u64 >= limit.rlim_cur {
+      return self.fail(Errno::EINVAL);
+    }
+    let used: HashSet<i32> = std::fs::read_dir(format!("/proc/{}/fd", self.tid.as_raw()))?
+      .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+      .collect();
+    match (min..).find(|fd| !used.contains(fd)) {
+      Some(free) if (free as u64) < limit.rlim_cur => self.addfd(&file, Some(free), flags),
+      _ => self.fail(Errno::EMFILE)
+    }
+  }
+
+  /// The kernel runs the syscall after the response, out of sight.
+  fn run(&self) -> router::Result<Option<i64>> {
+    self.respond(0, 0, libc::SECCOMP_USER_NOTIF_FLAG_CONTINUE as u32)?;
+    Ok(None)
+  }
+
+  fn validate(&self) -> router::Result<()> {
+    Errno::result(unsafe { libc::ioctl(self.listener.as_raw_fd(), SECCOMP_IOCTL_NOTIF_ID_VALID, &self.id) })?;
+    Ok(())
+  }
+
+  /// The kernel only ever runs the syscall as the tracee made it.
+  fn rewrite(&self, _regs: ptrace::Regs) -> router::Result<()> {
+    Err(Errno::ENOSYS.into())
+  }
+
+  fn scratch(&self) -> router::Result<u64> {
+    Err(Errno::ENOSYS.into())
+  }
+
+  fn abi(&self) -> router::Abi {
+    self.abi
+  }
+}
+
+/// The thread group and parent of `tid`.
+fn parents(tid: ptrace::Pid) -> Option<(ptrace::Pid, ptrace::Pid)> {
+  let status = std::fs::read_to_string(format!("/proc/{}/status", tid.as_raw())).ok()?;
+  let field = |key: &str| status.lines().find_map(|line| line.strip_prefix(key)?.trim().parse().ok())
+    .map(ptrace::Pid::from_raw);
+  Some((field("Tgid:")?, field("PPid:")?))
+}
+
+/// Gives `tid` the state of its thread group or parent, the clones it was created by going
+/// unseen. Threads are taken to share their fd table and cwd, and children to copy them.
+fn adopt(state: &State, tid: ptrace::Pid) {
+  if state.processes.contains_key(&tid) {
+    return;
+  }
+  let Some((tgid, ppid)) = parents(tid) else { return };
+  let process = if tgid != tid {
+    adopt(state, tgid);
+    state.process(tgid).fork((libc::CLONE_FILES | libc::CLONE_FS) as u64)
+  } else if state.processes.contains_key(&ppid) {
+    state.process(ppid).fork(0)
+  } else {
+    return;
+  };
+  state.processes.insert(tid, process);
+}
+
+/// Drops the state of the processes that are gone.
+fn reap(state: &State) {
+  let pids: Vec<ptrace::Pid> = state.processes.iter().map(|entry| *entry.key()).collect();
+  for pid in pids {
+    if signal::kill(pid, None) == Err(Errno::ESRCH) {
+      state.exit_process(pid);
+    }
+  }
+}
+
+/// Whether `pid` went through execve since its fds were last known, which shows as the
+/// placeholders of its close-on-exec fds being gone.
+fn execed(state: &State, pid: ptrace::Pid) -> bool {
+  let Some(process) = state.processes.get(&pid) else { return false };
+  process.fds.cloexec().into_iter().any(|fd| !std::fs::read_link(format!("/proc/{}/fd/{fd}", pid.as_raw()))
+    .is_ok_and(|link| link.as_os_str().as_bytes().starts_with(b"/memfd:mountbox:")))
+}
+
+/// Takes the listener fd `install_filter` left in `pid`.
+fn take_listener(pid: ptrace::Pid) -> Result<OwnedFd, Errno> {
+  let fd = std::fs::read_dir(format!("/proc/{}/fd", pid.as_raw())).map_err(|_| Errno::ESRCH)?
+    .filter_map(|entry| entry.ok())
+    .find(|entry| std::fs::read_link(entry.path()).is_ok_and(|link| link.as_os_str() == "anon_inode:seccomp notify"))
+    .and_then(|entry| entry.file_name().to_str()?.parse::<RawFd>().ok())
+    .ok_or(Errno::EINVAL)?;
+  let pidfd = Errno::result(unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) })?;
+  let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd as RawFd) };
+  let listener = Errno::result(unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd.as_raw_fd(), fd, 0) })?;
+  Ok(unsafe { OwnedFd::from_raw_fd(listener as RawFd) })
+}
+
+/// Answers the next notification of `listener`. `execs` holds the processes that may have
+/// gone through an execve since, as the kernel runs it out of sight.
+fn serve(state: &State, listener: &OwnedFd, execs: &mut HashSet<ptrace::Pid>) -> Result<(), Errno> {
+  let mut notif: libc::seccomp_notif = unsafe { std::mem::zeroed() };
+  match Errno::result(unsafe { libc::ioctl(listener.as_raw_fd(), SECCOMP_IOCTL_NOTIF_RECV, &mut notif) }) {
+    // The tracee went away before it could be received
+    Err(Errno::ENOENT | Errno::EINTR) => return Ok(()),
+    res => res?
+  };
+  let tid = ptrace::Pid::from_raw(notif.pid as i32);
+  adopt(state, tid);
+  let tgid = parents(tid).map_or(tid, |(tgid, _)| tgid);
+  if execs.remove(&tgid) && execed(state, tgid) {
+    state.exec_process(tgid);
+  }
+  let mut regs: ptrace::Regs = unsafe { std::mem::zeroed() };
+  ptrace::getreg!(regs, instruction_pointer) = notif.data.instruction_pointer;
+  let routed = routed_regs(regs, notif.data.arch, notif.data.nr as u64, notif.data.args);
+  let abi = routed.map_or(router::Abi::Native, |(abi, _)| abi);
+  let syscall = NotifiedSyscall { listener, id: notif.id, tid, abi, done: Cell::new(false) };
+  let res = match routed.map(|(_, regs)| router::route(state, regs, tid, &syscall)) {
+    // Not one for the router
+    None => syscall.run().map(drop),
+    Some(Ok(())) if !syscall.done.get() => syscall.run().map(drop),
+    Some(Err(err)) if !syscall.done.get() => syscall.ret(-err.to_errno() as i64),
+    _ => Ok(())
+  };
+  if routed.is_some_and(|(_, regs)| ptrace::getreg!(regs, syscall_nr) == ptrace::syscall_nr!(execve)) {
+    execs.insert(tgid);
+  }
+  match res {
+    Err(router::RouterError::PtraceError(errno)) => Err(errno),
+    _ => Ok(())
+  }
+}
+
+/// Serves the syscalls of `pid` and every process it creates on the mounts until all of
+/// them are gone. Returns how `pid` ended.
+///
+/// `pid` is expected to call [`install_filter`] then stop itself with SIGSTOP, and is resumed
+/// once its listener is taken. It is not traced otherwise, and its processes may be.
+pub fn attach(state: Arc<State>, pid: ptrace::Pid) -> Result<TraceeStatus, Errno> {
+  match waitpid(pid, Some(WaitPidFlag::WUNTRACED))? {
+    WaitStatus::Stopped(_, _) => {},
+    status => return TraceeStatus::of(status).ok_or(Errno::ECHILD)
+  }
+  let listener = take_listener(pid)?;
+  let pidfd = Errno::result(unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) })?;
+  let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd as RawFd) };
+  signal::kill(pid, signal::Signal::SIGCONT)?;
+  let mut status = None;
+  let mut execs = HashSet::new();
+  let mut fds = [
+    libc::pollfd { fd: listener.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+    libc::pollfd { fd: pidfd.as_raw_fd(), events: libc::POLLIN, revents: 0 }
+  ];
+  loop {
+    let ready = match Errno::result(unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, REAP_INTERVAL_MS) }) {
+      Err(Errno::EINTR) => continue,
+      res => res?
+    };
+    // Failing with ECHILD once it exited, only exits being left to wait for
+    if fds[1].fd != -1 && let Some(on_stop) = state.on_stop
+      && let Ok(WaitStatus::Stopped(_, _)) = waitid(Id::Pid(pid), WaitPidFlag::WSTOPPED | WaitPidFlag::WNOHANG) {
+      on_stop();
+    }
+    if ready == 0 {
+      reap(&state);
+      continue;
+    }
+    if fds[1].revents & libc::POLLIN != 0 {
+      // Reaped for the filter to be released along with it
+      status = TraceeStatus::of(waitpid(pid, None)?);
+      fds[1].fd = -1;
+      reap(&state);
+    }
+    if fds[0].revents & libc::POLLIN != 0 {
+      serve(&state, &listener, &mut execs)?;
+    } else if fds[0].revents & libc::POLLHUP != 0 {
+      // No process is left with the filter
+      break;
+    }
+  }
+  if status.is_none() {
+    status = TraceeStatus::of(waitpid(pid, None)?);
+  }
+  reap(&state);
+  status.ok_or(Errno::ECHILD)
+}
@@ -1,8 +1,11 @@
-pub use nix::{unistd::Pid, errno::Errno, libc::user_regs_struct, sys::ptrace::{attach, setregs, getregs, Options, setoptions, syscall}};
-use std::ffi::{c_long, c_void, CStr};
-use nix::sys::ptrace;
+pub use nix::{unistd::Pid, errno::Errno, sys::ptrace::{cont, seize, getevent, Options, syscall}};
+#[cfg(target_arch="x86_64")]
+pub use nix::{libc::user_regs_struct as Regs, sys::ptrace::{setregs, getregs}};
+use std::{ffi::{c_long, c_void}, os::unix::fs::FileExt};
+use nix::{libc, sys::ptrace};
 
 const LONG_LEN: usize = (c_long::BITS/8) as usize;
+const RED_ZONE: u64 = 128;
 
 #[cfg(target_arch="x86_64")]
 #[macro_export]
@@ -14,64 +17,501 @@ macro_rules! getreg {
   ($r:expr, arg3) => { $r.r10 };
   ($r:expr, arg4) => { $r.r8 };
   ($r:expr, arg5) => { $r.r9 };
-  ($r:expr, rip) => { $r.rip };
-  ($r:expr, rax) => { $r.rax };
+  ($r:expr, instruction_pointer) => { $r.rip };
+  ($r:expr, stack_pointer) => { $r.rsp };
+  ($r:expr, return_value) => { $r.rax };
+}
+
+/// The general purpose registers along with the syscall number, which aarch64 keeps out of
+/// them.
+#[cfg(target_arch="aarch64")]
+#[derive(Clone, Copy)]
+pub struct Regs {
+  pub regs: libc::user_regs_struct,
+  pub syscall_nr: u64
+}
+
+#[cfg(target_arch="aarch64")]
+const NT_ARM_SYSTEM_CALL: libc::c_int = 0x404;
+
+#[cfg(target_arch="aarch64")]
+pub fn getregs(pid: Pid) -> Result<Regs, Errno> {
+  let mut nr: libc::c_int = 0;
+  let mut iov = libc::iovec { iov_base: &mut nr as *mut libc::c_int as *mut c_void, iov_len: size_of::<libc::c_int>() };
+  Errno::result(unsafe { libc::ptrace(libc::PTRACE_GETREGSET, pid.as_raw(), NT_ARM_SYSTEM_CALL, &mut iov) })?;
+  Ok(Regs { regs: ptrace::getregs(pid)?, syscall_nr: nr as u64 })
+}
+
+/// Sets the registers, then the syscall number, -1 skipping the syscall.
+#[cfg(target_arch="aarch64")]
+pub fn setregs(pid: Pid, regs: Regs) -> Result<(), Errno> {
+  ptrace::setregs(pid, regs.regs)?;
+  let mut nr = regs.syscall_nr as libc::c_int;
+  let mut iov = libc::iovec { iov_base: &mut nr as *mut libc::c_int as *mut c_void, iov_len: size_of::<libc::c_int>() };
+  Errno::result(unsafe { libc::ptrace(libc::PTRACE_SETREGSET, pid.as_raw(), NT_ARM_SYSTEM_CALL, &mut iov) }).map(drop)
+}
+
+// The return value takes the place of the first argument
+#[cfg(target_arch="aarch64")]
+#[macro_export]
+macro_rules! getreg {
+  ($r:expr, syscall_nr) => { $r.syscall_nr };
+  ($r:expr, arg0) => { $r.regs.regs[0] };
+  ($r:expr, arg1) => { $r.regs.regs[1] };
+  ($r:expr, arg2) => { $r.regs.regs[2] };
+  ($r:expr, arg3) => { $r.regs.regs[3] };
+  ($r:expr, arg4) => { $r.regs.regs[4] };
+  ($r:expr, arg5) => { $r.regs.regs[5] };
+  ($r:expr, instruction_pointer) => { $r.regs.pc };
+  ($r:expr, stack_pointer) => { $r.regs.sp };
+  ($r:expr, return_value) => { $r.regs.regs[0] };
 }
 
 #[cfg(target_arch="x86_64")]
 #[macro_export]
 macro_rules! syscall_nr {
   (read) => { 0 };
+  (write) => { 1 };
   (open) => { 2 };
   (close) => { 3 };
   (stat) => { 4 };
   (fstat) => { 5 };
   (lstat) => { 6 };
+  (lseek) => { 8 };
+  (pread64) => { 17 };
+  (pwrite64) => { 18 };
+  (readv) => { 19 };
+  (writev) => { 20 };
+  (access) => { 21 };
+  (dup) => { 32 };
+  (dup2) => { 33 };
+  (clone) => { 56 };
   (fork) => { 57 };
   (vfork) => { 58 };
   (execve) => { 59 };
   (exit) => { 60 };
+  (fcntl) => { 72 };
+  (truncate) => { 76 };
+  (ftruncate) => { 77 };
+  (getdents) => { 78 };
   (getcwd) => { 79 };
   (chdir) => { 80 };
+  (rename) => { 82 };
+  (mkdir) => { 83 };
+  (rmdir) => { 84 };
+  (creat) => { 85 };
+  (link) => { 86 };
+  (unlink) => { 87 };
+  (symlink) => { 88 };
+  (readlink) => { 89 };
+  (getdents64) => { 217 };
   (exit_group) => { 231 };
   (openat) => { 257 };
+  (mkdirat) => { 258 };
+  (newfstatat) => { 262 };
+  (unlinkat) => { 263 };
+  (renameat) => { 264 };
+  (linkat) => { 265 };
+  (symlinkat) => { 266 };
+  (readlinkat) => { 267 };
+  (faccessat) => { 269 };
+  (dup3) => { 292 };
+  (preadv) => { 295 };
+  (renameat2) => { 316 };
+  (memfd_create) => { 319 };
   (execveat) => { 322 };
+  (preadv2) => { 327 };
   (statx) => { 332 };
+  (clone3) => { 435 };
+  (openat2) => { 437 };
+  (faccessat2) => { 439 };
+}
+
+// The generic syscall table, without the syscalls that have *at variants
+#[cfg(target_arch="aarch64")]
+#[macro_export]
+macro_rules! syscall_nr {
+  (getcwd) => { 17 };
+  (dup) => { 23 };
+  (dup3) => { 24 };
+  (fcntl) => { 25 };
+  (mkdirat) => { 34 };
+  (unlinkat) => { 35 };
+  (symlinkat) => { 36 };
+  (linkat) => { 37 };
+  (truncate) => { 45 };
+  (ftruncate) => { 46 };
+  (faccessat) => { 48 };
+  (chdir) => { 49 };
+  (openat) => { 56 };
+  (close) => { 57 };
+  (getdents64) => { 61 };
+  (lseek) => { 62 };
+  (read) => { 63 };
+  (write) => { 64 };
+  (readv) => { 65 };
+  (writev) => { 66 };
+  (pread64) => { 67 };
+  (pwrite64) => { 68 };
+  (preadv) => { 69 };
+  (readlinkat) => { 78 };
+  (newfstatat) => { 79 };
+  (fstat) => { 80 };
+  (exit) => { 93 };
+  (exit_group) => { 94 };
+  (clone) => { 220 };
+  (execve) => { 221 };
+  (renameat2) => { 276 };
+  (memfd_create) => { 279 };
+  (execveat) => { 281 };
+  (preadv2) => { 286 };
+  (statx) => { 291 };
+  (clone3) => { 435 };
+  (openat2) => { 437 };
+  (faccessat2) => { 439 };
+}
+
+// Numbers the router needs of the i386 syscalls 64-bit kernels run too
+#[cfg(target_arch="x86_64")]
+#[macro_export]
+macro_rules! i386_syscall_nr {
+  (fork) => { 2 };
+  (read) => { 3 };
+  (write) => { 4 };
+  (open) => { 5 };
+  (close) => { 6 };
+  (creat) => { 8 };
+  (link) => { 9 };
+  (unlink) => { 10 };
+  (execve) => { 11 };
+  (chdir) => { 12 };
+  (lseek) => { 19 };
+  (access) => { 33 };
+  (rename) => { 38 };
+  (mkdir) => { 39 };
+  (rmdir) => { 40 };
+  (dup) => { 41 };
+  (fcntl) => { 55 };
+  (dup2) => { 63 };
+  (symlink) => { 83 };
+  (readlink) => { 85 };
+  (truncate) => { 92 };
+  (ftruncate) => { 93 };
+  (stat) => { 106 };
+  (lstat) => { 107 };
+  (fstat) => { 108 };
+  (clone) => { 120 };
+  (_llseek) => { 140 };
+  (getdents) => { 141 };
+  (readv) => { 145 };
+  (writev) => { 146 };
+  (pread64) => { 180 };
+  (pwrite64) => { 181 };
+  (getcwd) => { 183 };
+  (vfork) => { 190 };
+  (truncate64) => { 193 };
+  (ftruncate64) => { 194 };
+  (stat64) => { 195 };
+  (lstat64) => { 196 };
+  (fstat64) => { 197 };
+  (getdents64) => { 220 };
+  (fcntl64) => { 221 };
+  (openat) => { 295 };
+  (mkdirat) => { 296 };
+  (fstatat64) => { 300 };
+  (unlinkat) => { 301 };
+  (renameat) => { 302 };
+  (linkat) => { 303 };
+  (symlinkat) => { 304 };
+  (readlinkat) => { 305 };
+  (faccessat) => { 307 };
+  (dup3) => { 330 };
+  (preadv) => { 333 };
+  (renameat2) => { 353 };
+  (preadv2) => { 378 };
+  (statx) => { 383 };
+  (clone3) => { 435 };
+  (openat2) => { 437 };
+  (faccessat2) => { 439 };
 }
 
 pub use getreg;
 pub use syscall_nr;
+#[cfg(target_arch="x86_64")]
+pub use i386_syscall_nr;
+
+/// AUDIT_ARCH_* value of the syscalls made with the native ABI.
+#[cfg(target_arch="x86_64")]
+pub const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch="aarch64")]
+pub const AUDIT_ARCH: u32 = 0xc000_00b7;
+#[cfg(target_arch="x86_64")]
+pub const AUDIT_ARCH_I386: u32 = 0x4000_0003;
+
+/// The i386 syscalls the router handles, along with the native syscalls they are routed as.
+/// Variants taking 64-bit offsets or filling other stat layouts share the routing of the
+/// native syscall: only their path and fd arguments matter.
+#[cfg(target_arch="x86_64")]
+pub const I386_SYSCALLS: &[(u64, u64)] = &[
+  (i386_syscall_nr!(read), syscall_nr!(read)),
+  (i386_syscall_nr!(write), syscall_nr!(write)),
+  (i386_syscall_nr!(open), syscall_nr!(open)),
+  (i386_syscall_nr!(close), syscall_nr!(close)),
+  (i386_syscall_nr!(creat), syscall_nr!(creat)),
+  (i386_syscall_nr!(link), syscall_nr!(link)),
+  (i386_syscall_nr!(unlink), syscall_nr!(unlink)),
+  (i386_syscall_nr!(execve), syscall_nr!(execve)),
+  (i386_syscall_nr!(chdir), syscall_nr!(chdir)),
+  (i386_syscall_nr!(lseek), syscall_nr!(lseek)),
+  (i386_syscall_nr!(_llseek), syscall_nr!(lseek)),
+  (i386_syscall_nr!(access), syscall_nr!(access)),
+  (i386_syscall_nr!(rename), syscall_nr!(rename)),
+  (i386_syscall_nr!(mkdir), syscall_nr!(mkdir)),
+  (i386_syscall_nr!(rmdir), syscall_nr!(rmdir)),
+  (i386_syscall_nr!(dup), syscall_nr!(dup)),
+  (i386_syscall_nr!(fcntl), syscall_nr!(fcntl)),
+  (i386_syscall_nr!(dup2), syscall_nr!(dup2)),
+  (i386_syscall_nr!(symlink), syscall_nr!(symlink)),
+  (i386_syscall_nr!(readlink), syscall_nr!(readlink)),
+  (i386_syscall_nr!(truncate), syscall_nr!(truncate)),
+  (i386_syscall_nr!(truncate64), syscall_nr!(truncate)),
+  (i386_syscall_nr!(ftruncate), syscall_nr!(ftruncate)),
+  (i386_syscall_nr!(ftruncate64), syscall_nr!(ftruncate)),
+  (i386_syscall_nr!(stat), syscall_nr!(stat)),
+  (i386_syscall_nr!(stat64), syscall_nr!(stat)),
+  (i386_syscall_nr!(lstat), syscall_nr!(lstat)),
+  (i386_syscall_nr!(lstat64), syscall_nr!(lstat)),
+  (i386_syscall_nr!(fstat), syscall_nr!(fstat)),
+  (i386_syscall_nr!(fstat64), syscall_nr!(fstat)),
+  (i386_syscall_nr!(getdents), syscall_nr!(getdents)),
+  (i386_syscall_nr!(getdents64), syscall_nr!(getdents64)),
+  (i386_syscall_nr!(fcntl64), syscall_nr!(fcntl)),
+  (i386_syscall_nr!(readv), syscall_nr!(readv)),
+  (i386_syscall_nr!(writev), syscall_nr!(writev)),
+  (i386_syscall_nr!(pread64), syscall_nr!(pread64)),
+  (i386_syscall_nr!(pwrite64), syscall_nr!(pwrite64)),
+  (i386_syscall_nr!(getcwd), syscall_nr!(getcwd)),
+  (i386_syscall_nr!(openat), syscall_nr!(openat)),
+  (i386_syscall_nr!(mkdirat), syscall_nr!(mkdirat)),
+  (i386_syscall_nr!(fstatat64), syscall_nr!(newfstatat)),
+  (i386_syscall_nr!(unlinkat), syscall_nr!(unlinkat)),
+  (i386_syscall_nr!(renameat), syscall_nr!(renameat)),
+  (i386_syscall_nr!(linkat), syscall_nr!(linkat)),
+  (i386_syscall_nr!(symlinkat), syscall_nr!(symlinkat)),
+  (i386_syscall_nr!(readlinkat), syscall_nr!(readlinkat)),
+  (i386_syscall_nr!(faccessat), syscall_nr!(faccessat)),
+  (i386_syscall_nr!(dup3), syscall_nr!(dup3)),
+  (i386_syscall_nr!(preadv), syscall_nr!(preadv)),
+  (i386_syscall_nr!(renameat2), syscall_nr!(renameat2)),
+  (i386_syscall_nr!(preadv2), syscall_nr!(preadv2)),
+  (i386_syscall_nr!(statx), syscall_nr!(statx)),
+  (i386_syscall_nr!(openat2), syscall_nr!(openat2)),
+  (i386_syscall_nr!(faccessat2), syscall_nr!(faccessat2))
+];
+
+/// A syscall as its ABI has it, the number and arguments being only known at seccomp stops.
+pub struct SyscallInfo {
+  pub arch: u32,
+  pub nr: u64,
+  pub args: [u64; 6]
+}
+
+pub fn syscall_info(pid: Pid) -> Result<SyscallInfo, Errno> {
+  let mut info: libc::ptrace_syscall_info = unsafe { std::mem::zeroed() };
+  Errno::result(unsafe {
+    libc::ptrace(libc::PTRACE_GET_SYSCALL_INFO, pid.as_raw(), size_of::<libc::ptrace_syscall_info>(), &mut info)
+  })?;
+  let (nr, args) = match info.op {
+    libc::PTRACE_SYSCALL_INFO_SECCOMP => unsafe { (info.u.seccomp.nr, info.u.seccomp.args) },
+    _ => (0, [0; 6])
+  };
+  Ok(SyscallInfo { arch: info.arch, nr, args })
+}
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Transfers between `buf` and tracee memory at `addr` with process_vm_readv/writev, which
+/// works on processes that are not ptrace tracees as well. Returns how much was transferred
+/// before the first inaccessible page.
+fn vm_transfer(pid: Pid, addr: u64, buf: &mut [u8], write: bool) -> Result<usize, Errno> {
+  let mut done = 0;
+  while done < buf.len() {
+    // One remote iovec per page, for the transfer to stop right at the faulting one
+    let mut remote = Vec::new();
+    let mut at = addr + done as u64;
+    let end = addr + buf.len() as u64;
+    while at < end && remote.len() < libc::UIO_MAXIOV as usize {
+      let len = (PAGE_SIZE - at % PAGE_SIZE).min(end - at);
+      remote.push(libc::iovec { iov_base: at as *mut c_void, iov_len: len as usize });
+      at += len;
+    }
+    let batch = (at - addr) as usize - done;
+    let local = libc::iovec { iov_base: buf[done..].as_mut_ptr() as *mut c_void, iov_len: batch };
+    let len = Errno::result(unsafe { if write {
+      libc::process_vm_writev(pid.as_raw(), &local, 1, remote.as_ptr(), remote.len() as libc::c_ulong, 0)
+    } else {
+      libc::process_vm_readv(pid.as_raw(), &local, 1, remote.as_ptr(), remote.len() as libc::c_ulong, 0)
+    } });
+    match len {
+      Ok(len) => {
+        done += len as usize;
+        if (len as usize) < batch {
+          break;
+        }
+      },
+      Err(Errno::EFAULT) => break,
+      Err(err) => return Err(err)
+    }
+  }
+  Ok(done)
+}
+
+/// `vm_transfer` through /proc/PID/mem, which also ignores the protection of the pages.
+fn mem_transfer(pid: Pid, addr: u64, buf: &mut [u8], write: bool) -> Result<usize, Errno> {
+  let mem = std::fs::OpenOptions::new().read(!write).write(write).open(format!("/proc/{}/mem", pid.as_raw()))
+    .map_err(|_| Errno::ENOSYS)?;
+  let mut done = 0;
+  while done < buf.len() {
+    let at = addr + done as u64;
+    let res = if write { mem.write_at(&buf[done..], at) } else { mem.read_at(&mut buf[done..], at) };
+    match res {
+      Ok(0) => break,
+      Ok(len) => done += len,
+      // Unmapped memory
+      Err(err) if err.raw_os_error() == Some(libc::EIO) => break,
+      Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {},
+      Err(err) => return Err(Errno::from_raw(err.raw_os_error().unwrap_or(libc::EIO)))
+    }
+  }
+  Ok(done)
+}
+
+/// `vm_transfer` word by word with ptrace, which only works on tracees stopped by us.
+fn ptrace_transfer(pid: Pid, addr: u64, buf: &mut [u8], write: bool) -> Result<usize, Errno> {
+  let mut done = 0;
+  while done < buf.len() {
+    // Aligned words never straddle pages
+    let at = addr as usize + done;
+    let word_at = (at & !(LONG_LEN - 1)) as *mut c_void;
+    let skip = at % LONG_LEN;
+    let len = (LONG_LEN - skip).min(buf.len() - done);
+    let word = match ptrace::read(pid, word_at) {
+      Ok(word) => word,
+      Err(Errno::EIO | Errno::EFAULT) => break,
+      Err(err) => return Err(err)
+    };
+    let mut bytes = word.to_ne_bytes();
+    if write {
+      // Keeps the tracee memory around the buffer intact
+      bytes[skip..skip + len].copy_from_slice(&buf[done..done + len]);
+      match ptrace::write(pid, word_at, c_long::from_ne_bytes(bytes)) {
+        Ok(()) => {},
+        Err(Errno::EIO | Errno::EFAULT) => break,
+        Err(err) => return Err(err)
+      }
+    } else {
+      buf[done..done + len].copy_from_slice(&bytes[skip..skip + len]);
+    }
+    done += len;
+  }
+  Ok(done)
+}
+
+/// Transfers as much as the tracee memory at `addr` allows, trying each way in turn.
+fn transfer(pid: Pid, addr: u64, buf: &mut [u8], write: bool) -> Result<usize, Errno> {
+  match vm_transfer(pid, addr, buf, write) {
+    // Unsupported by the kernel, or denied by a seccomp policy around mountbox
+    Err(Errno::ENOSYS | Errno::EPERM) => {},
+    res => return res
+  }
+  match mem_transfer(pid, addr, buf, write) {
+    Err(Errno::ENOSYS) => ptrace_transfer(pid, addr, buf, write),
+    res => res
+  }
+}
 
 pub fn read_path(pid: Pid, addr: u64) -> Result<String, Errno> {
   let mut data: Vec<u8> = Vec::new();
   loop {
-    if data.len() > nix::libc::PATH_MAX as usize {
-      return Err(Errno::ENAMETOOLONG);
+    // Up to the end of the page, for an unmapped next page not to fail a shorter string
+    let at = addr + data.len() as u64;
+    let chunk = read_bytes(pid, at, (PAGE_SIZE - at % PAGE_SIZE) as usize)?;
+    match chunk.iter().position(|b| *b == 0) {
+      Some(nul) => {
+        data.extend(&chunk[..nul]);
+        break;
+      },
+      None => data.extend(chunk)
     }
-    let chunk = ptrace::read(pid, (addr as usize + data.len()) as *mut c_void)
-      .map_err(|e| if matches!(e, Errno::EIO) { Errno::EFAULT } else { e })?;
-    let bytes = chunk.to_ne_bytes();
-    data.extend(bytes);
-    if bytes.contains(&0) {
+    if data.len() >= libc::PATH_MAX as usize {
       break;
     }
   }
-  Ok(CStr::from_bytes_until_nul(&data).unwrap().to_str().map_err(|_| Errno::EINVAL)?.to_string())
+  if data.len() >= libc::PATH_MAX as usize {
+    return Err(Errno::ENAMETOOLONG);
+  }
+  String::from_utf8(data).map_err(|_| Errno::EINVAL)
 }
 
+/// Reads `len` bytes at `addr`, failing with EFAULT unless all of them are readable.
+pub fn read_bytes(pid: Pid, addr: u64, len: usize) -> Result<Vec<u8>, Errno> {
+  let data = read_partial(pid, addr, len)?;
+  if data.len() < len {
+    return Err(Errno::EFAULT);
+  }
+  Ok(data)
+}
+
+/// Reads up to `len` bytes at `addr`, stopping at the first unreadable page like the
+/// kernel copying a syscall buffer does. Fails with EFAULT only if nothing is readable.
+pub fn read_partial(pid: Pid, addr: u64, len: usize) -> Result<Vec<u8>, Errno> {
+  let mut data = vec![0u8; len];
+  let read = transfer(pid, addr, &mut data, false)?;
+  if read == 0 && len > 0 {
+    return Err(Errno::EFAULT);
+  }
+  data.truncate(read);
+  Ok(data)
+}
+
+/// Writes `bytes` at `addr`, up to `buffer_size`, failing with EFAULT unless all of them
+/// are writable.
 pub fn write_bytes(pid: Pid, addr: u64, bytes: &[u8], buffer_size: usize) -> Result<(), Errno> {
-  let mut pos = 0;
-  while pos < buffer_size && pos < bytes.len() {
-    let chunk: [u8; LONG_LEN] = if pos+LONG_LEN > bytes.len() {
-      let mut v = bytes[pos..bytes.len()].to_vec();
-      v.resize(LONG_LEN, 0);
-      v.try_into().unwrap()
-    } else {
-      bytes[pos..pos+LONG_LEN].try_into().unwrap()
-    };
-    ptrace::write(pid, (addr as usize + pos) as *mut c_void, c_long::from_ne_bytes(chunk))
-      .map_err(|e| if matches!(e, Errno::EIO) { Errno::EFAULT } else { e })?;
-    pos += LONG_LEN;
+  let len = buffer_size.min(bytes.len());
+  if write_partial(pid, addr, &bytes[..len])? < len {
+    return Err(Errno::EFAULT);
   }
   Ok(())
-}
\ No newline at end of file
+}
+
+/// Writes `bytes` at `addr`, stopping at the first unwritable page like the kernel filling
+/// a syscall buffer does. Returns how many were written, failing with EFAULT if none was.
+pub fn write_partial(pid: Pid, addr: u64, bytes: &[u8]) -> Result<usize, Errno> {
+  let written = transfer(pid, addr, &mut bytes.to_vec(), true)?;
+  if written == 0 && !bytes.is_empty() {
+    return Err(Errno::EFAULT);
+  }
+  Ok(written)
+}
+
+/// Start of the tracee stack area that can hold injected syscall arguments: everything below
+/// the red zone is free while the tracee is stopped in a syscall.
+pub fn scratch(regs: &Regs) -> u64 {
+  getreg!(regs, stack_pointer) - RED_ZONE
+}
+
+/// Copies `bytes` and a terminating NUL below `sp` on the tracee stack and returns their
+/// address, moving `sp` past them.
+pub fn push_cstr(pid: Pid, sp: &mut u64, bytes: &[u8]) -> Result<u64, Errno> {
+  let mut cstr = bytes.to_vec();
+  cstr.push(0);
+  *sp = (*sp - cstr.len() as u64) & !0xf;
+  write_bytes(pid, *sp, &cstr, cstr.len())?;
+  Ok(*sp)
+}
+
+/// Restarts a tracee in group-stop without letting it run, so that the tracer still sees it
+/// being continued by SIGCONT or new signals coming in.
+pub fn listen(pid: Pid) -> Result<(), Errno> {
+  Errno::result(unsafe { nix::libc::ptrace(nix::libc::PTRACE_LISTEN, pid.as_raw(), 0, 0) }).map(drop)
+}
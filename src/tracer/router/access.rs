@@ -0,0 +1,44 @@
+use nix::{errno::Errno, libc};
+use typed_path::Utf8UnixPath;
+use crate::{mounts::Mount, plugin::{self, PluginError}};
+use super::{attr, gids, groups, ptrace, uids, Result, Syscall};
+
+fn check(mount: &Mount, path: &Utf8UnixPath, tid: ptrace::Pid, mode: i32, flags: i32) -> Result<()> {
+  if mount.plugin.has_access() {
+    match mount.plugin.access(path.as_str(), mode) {
+      // Left to the mode bits
+      Err(PluginError::Errno(Errno::ENOSYS)) => {},
+      res => return Ok(res?)
+    }
+  }
+  let stat = attr::getattr(mount, path.as_str(), tid)?;
+  let perms = stat.mode as i32 & 0o7777;
+  // The real ids, or the filesystem ones the kernel checks other accesses with under AT_EACCESS
+  let ((uid, fsuid), (gid, fsgid)) = (uids(tid), gids(tid));
+  let (uid, gid) = if flags & libc::AT_EACCESS != 0 { (fsuid, fsgid) } else { (uid, gid) };
+  let allowed = if uid == 0 {
+    // Root may read and write anything, and execute whatever has an execute bit
+    let executable = stat.mode & plugin::S_IFMT == plugin::S_IFDIR || perms & 0o111 != 0;
+    libc::R_OK | libc::W_OK | if executable { libc::X_OK } else { 0 }
+  } else if uid == stat.uid {
+    perms >> 6 & 0o7
+  } else if gid == stat.gid || groups(tid).contains(&stat.gid) {
+    perms >> 3 & 0o7
+  } else {
+    perms & 0o7
+  };
+  if mode & !allowed != 0 {
+    return Err(Errno::EACCES.into());
+  }
+  Ok(())
+}
+
+pub fn access(mount: &Mount, path: &Utf8UnixPath, tid: ptrace::Pid, _regs: ptrace::Regs, syscall: &impl Syscall, mode: i32, flags: i32) -> Result<()> {
+  if mode & !(libc::R_OK | libc::W_OK | libc::X_OK) != 0
+    || flags & !(libc::AT_EACCESS | libc::AT_SYMLINK_NOFOLLOW | libc::AT_EMPTY_PATH) != 0 {
+    return Err(Errno::EINVAL.into());
+  }
+  check(mount, path, tid, mode, flags)?;
+  syscall.ret(0)?;
+  Ok(())
+}
@@ -0,0 +1,102 @@
+use std::mem::MaybeUninit;
+use nix::libc;
+use crate::{mounts::{self, Mount}, plugin};
+use super::{gids, permissions, ptrace, uids, Result};
+
+/// Gets the attributes of `path` and makes up the fields the plugin left out of the mask.
+/// The mask itself is kept as reported, with 0 standing for `MOUNTBOX_STAT_LEGACY`.
+pub fn getattr(mount: &Mount, path: &str, tid: ptrace::Pid) -> Result<plugin::stat> {
+  let mut stat = mount.plugin.getattr(path)?;
+  if stat.mask == 0 {
+    stat.mask = plugin::MOUNTBOX_STAT_LEGACY;
+  }
+  let provides = |bit: u32| stat.mask & bit != 0;
+  let is_dir = stat.mode & plugin::S_IFMT == plugin::S_IFDIR;
+  let mut complete = stat;
+  complete.mode = (stat.mode & plugin::S_IFMT) | permissions(&stat) as u16;
+  if !provides(plugin::MOUNTBOX_STAT_UID) {
+    // Mounted files belong to whoever runs the tracee
+    complete.uid = uids(tid).1;
+  }
+  if !provides(plugin::MOUNTBOX_STAT_GID) {
+    complete.gid = gids(tid).1;
+  }
+  if !provides(plugin::MOUNTBOX_STAT_NLINK) {
+    complete.nlink = if is_dir { 2 } else { 1 };
+  }
+  if !provides(plugin::MOUNTBOX_STAT_INO) {
+    complete.ino = path_ino(path);
+  }
+  if !provides(plugin::MOUNTBOX_STAT_BLOCKS) {
+    complete.blocks = stat.size.div_ceil(512);
+  }
+  if !provides(plugin::MOUNTBOX_STAT_BLKSIZE) {
+    complete.blksize = 4096;
+  }
+  Ok(complete)
+}
+
+/// The inode number of `path` when the plugin does not report one, stable across runs.
+pub fn path_ino(path: &str) -> u64 {
+  mounts::path_hash(path.as_bytes()).max(1)
+}
+
+pub fn to_stat(mount: &Mount, stat: &plugin::stat) -> libc::stat {
+  let mut cstat = unsafe { MaybeUninit::<libc::stat>::zeroed().assume_init() };
+  cstat.st_dev = mount.dev;
+  cstat.st_ino = stat.ino;
+  cstat.st_nlink = stat.nlink;
+  cstat.st_mode = stat.mode as u32;
+  cstat.st_uid = stat.uid;
+  cstat.st_gid = stat.gid;
+  cstat.st_rdev = stat.rdev;
+  cstat.st_size = stat.size as libc::off_t;
+  cstat.st_blksize = stat.blksize as libc::blksize_t;
+  cstat.st_blocks = stat.blocks as libc::blkcnt_t;
+  cstat.st_atime = stat.atime.sec;
+  cstat.st_atime_nsec = stat.atime.nsec;
+  cstat.st_mtime = stat.mtime.sec;
+  cstat.st_mtime_nsec = stat.mtime.nsec;
+  cstat.st_ctime = stat.ctime.sec;
+  cstat.st_ctime_nsec = stat.ctime.nsec;
+  cstat
+}
+
+fn to_statx_timestamp(time: &plugin::mountbox_timespec) -> libc::statx_timestamp {
+  let mut timestamp = unsafe { MaybeUninit::<libc::statx_timestamp>::zeroed().assume_init() };
+  timestamp.tv_sec = time.sec;
+  timestamp.tv_nsec = time.nsec as u32;
+  timestamp
+}
+
+pub fn to_statx(mount: &Mount, stat: &plugin::stat) -> libc::statx {
+  let mut cstatx = unsafe { MaybeUninit::<libc::statx>::zeroed().assume_init() };
+  // Only the bits up to blocks share their values with STATX_*, the others having none
+  cstatx.stx_mask = stat.mask & libc::STATX_BASIC_STATS;
+  cstatx.stx_blksize = stat.blksize;
+  cstatx.stx_nlink = stat.nlink as u32;
+  cstatx.stx_uid = stat.uid;
+  cstatx.stx_gid = stat.gid;
+  cstatx.stx_mode = stat.mode;
+  cstatx.stx_ino = stat.ino;
+  cstatx.stx_size = stat.size;
+  cstatx.stx_blocks = stat.blocks;
+  cstatx.stx_atime = to_statx_timestamp(&stat.atime);
+  cstatx.stx_mtime = to_statx_timestamp(&stat.mtime);
+  cstatx.stx_ctime = to_statx_timestamp(&stat.ctime);
+  cstatx.stx_rdev_major = libc::major(stat.rdev);
+  cstatx.stx_rdev_minor = libc::minor(stat.rdev);
+  cstatx.stx_dev_major = libc::major(mount.dev);
+  cstatx.stx_dev_minor = libc::minor(mount.dev);
+  cstatx
+}
+
+/// Copies a `stat` or `statx` struct to the tracee buffer at `buf_ptr`.
+pub fn write<T>(tid: ptrace::Pid, buf_ptr: u64, value: &T) -> Result<()> {
+  let bytes = unsafe { core::slice::from_raw_parts(
+    (value as *const T) as *const u8,
+    core::mem::size_of::<T>(),
+  ) };
+  ptrace::write_bytes(tid, buf_ptr, bytes, bytes.len())?;
+  Ok(())
+}
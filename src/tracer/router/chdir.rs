@@ -1,26 +1,38 @@
-use crate::state::State;
-use super::{ptrace, Result};
+use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+use nix::unistd::{access, AccessFlags};
+use crate::{process::Process, state::State};
+use super::{ptrace, walk, Result, Syscall};
 use typed_path::NativePathBuf;
 
-pub fn chdir(state: &State, tid: ptrace::Pid, regs: ptrace::user_regs_struct, wait_ptrace_ret: impl Fn() -> Result<()>) -> Result<()> {
+pub fn chdir(state: &State, process: &Process, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
   let relpath = NativePathBuf::from(ptrace::read_path(tid, ptrace::getreg!(regs, arg0))?);
-  let path = state.cwd.read().unwrap().join(relpath);
+  syscall.validate()?;
+  let path = process.cwd.read().unwrap().join(relpath);
+  let (path, walked) = match walk::walk(state, &path, true, 0)? {
+    Some(walked) => (walked, true),
+    None => (path, false)
+  };
   if let Some(_) = state.mounts.get_mount_of_path(&path) {
-    *state.cwd.write().unwrap() = path; // TODO: emulate /proc/PID/cwd
-    ptrace::setregs(tid, ptrace::user_regs_struct {
-      orig_rax: u64::MAX,
-      ..regs
-    }).unwrap();
-    wait_ptrace_ret()?;
-    ptrace::setregs(tid, ptrace::user_regs_struct {
-      rax: 0,
-      ..ptrace::getregs(tid).unwrap()
-    }).unwrap();
+    *process.cwd.write().unwrap() = path; // TODO: emulate /proc/PID/cwd
+    syscall.ret(0)?;
   } else {
-    wait_ptrace_ret()?;
-    if ptrace::getreg!(ptrace::getregs(tid)?, rax) == 0 {
-      *state.cwd.write().unwrap() = path;
+    if walked {
+      let mut regs = regs;
+      let mut sp = syscall.scratch()?;
+      ptrace::getreg!(regs, arg0) = ptrace::push_cstr(tid, &mut sp, path.as_bytes())?;
+      syscall.rewrite(regs)?;
+    }
+    let changed = match syscall.run()? {
+      Some(ret) => ret == 0,
+      // Left to the kernel unseen, which agrees when the tracer can enter the directory too
+      None => {
+        let host = Path::new(OsStr::from_bytes(path.as_bytes()));
+        host.is_dir() && access(host, AccessFlags::X_OK).is_ok()
+      }
+    };
+    if changed {
+      *process.cwd.write().unwrap() = path;
     }
   }
   Ok(())
-}
\ No newline at end of file
+}
@@ -1,20 +1,17 @@
-use nix::libc::user_regs_struct;
-use crate::mounts::Mount;
-use super::{Result, ptrace};
+use crate::{process::FdTable, state::State};
+use super::{Result, ptrace, Syscall};
 
-pub fn close(mount: &Mount, fd: u16, tid: ptrace::Pid, regs: user_regs_struct, wait_ptrace_ret: impl Fn() -> Result<()>) -> Result<()> {
-  let fd_info = mount.get_fd_info(fd).unwrap();
-  mount.plugin.close(fd_info.path.as_str(), fd_info.fh)?;
-  drop(fd_info);
-  mount.release_fd(fd);
-  ptrace::setregs(tid, user_regs_struct {
-    orig_rax: u64::MAX,
-    ..regs
-  }).unwrap();
-  wait_ptrace_ret()?;
-  ptrace::setregs(tid, user_regs_struct {
-    rax: 0,
-    ..ptrace::getregs(tid).unwrap()
-  })?;
+pub fn close(state: &State, fds: &FdTable, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  // Like close(2), the fd is gone even when the plugin fails, so the placeholder is closed
+  // before the failure is reported
+  let released = fds.remove(ptrace::getreg!(regs, arg0) as u16).map(|file| {
+    let mount = state.mounts.get_mount(&file.read().unwrap().mountpath).unwrap();
+    mount.release(file)
+  });
+  let closed = syscall.run()?;
+  // Syscalls the kernel answers out of sight keep its result
+  if let Some(Err(err)) = released && closed.is_some() {
+    syscall.ret(-err.to_errno() as i64)?;
+  }
   Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,45 @@
+use nix::{errno::Errno, libc};
+use crate::{process::FdTable, state::State};
+use super::{ptrace, Result, Syscall};
+
+/// Duplicates `oldfd` when it or the `newfd` it replaces is on a mount: the new fd shares the
+/// open file of `oldfd`, and the file `newfd` referred to is released. Duplicates of host
+/// fds over host fds are left to the kernel.
+pub fn dup(state: &State, fds: &FdTable, syscall: &impl Syscall, oldfd: i32, newfd: Option<i32>, min: i32, flags: i32) -> Result<()> {
+  let file = fds.get(oldfd as u16);
+  let replaces = newfd.is_some_and(|newfd| fds.get(newfd as u16).is_some());
+  // Duplicating a fd over itself changes nothing, dup3 failing on it
+  if file.is_none() && !replaces || newfd == Some(oldfd) {
+    return Ok(());
+  }
+  if flags & !libc::O_CLOEXEC != 0 {
+    return Err(Errno::EINVAL.into());
+  }
+  let fd = syscall.ret_dup(oldfd, newfd, min, flags)?;
+  if fd < 0 {
+    return Ok(());
+  }
+  if let Some(replaced) = fds.remove(fd as u16) {
+    state.release(vec![replaced]);
+  }
+  if let Some(file) = file {
+    fds.insert(fd as u16, file, flags & libc::O_CLOEXEC != 0);
+  }
+  Ok(())
+}
+
+/// Handles the fcntl commands that create fds or change whether execve closes them.
+pub fn fcntl(state: &State, fds: &FdTable, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let fd = ptrace::getreg!(regs, arg0) as i32;
+  let arg = ptrace::getreg!(regs, arg2);
+  match ptrace::getreg!(regs, arg1) as i32 {
+    libc::F_DUPFD => dup(state, fds, syscall, fd, None, arg as i32, 0),
+    libc::F_DUPFD_CLOEXEC => dup(state, fds, syscall, fd, None, arg as i32, libc::O_CLOEXEC),
+    libc::F_SETFD => {
+      // The kernel sets it on the placeholder in any case
+      fds.set_cloexec(fd as u16, arg as i32 & libc::FD_CLOEXEC != 0);
+      Ok(())
+    },
+    _ => Ok(())
+  }
+}
@@ -1,21 +1,21 @@
 use std::{ffi::CString, fs::File, io::Write, os::fd::FromRawFd, sync::RwLock};
 use typed_path::Utf8UnixPath;
 use crate::mounts::Mount;
-use super::{ptrace, Result};
+use super::{ptrace, Result, Syscall};
 
-pub fn execve(mount: &Mount, path: &Utf8UnixPath, tid: ptrace::Pid, regs: ptrace::user_regs_struct, wait_ptrace_ret: impl Fn() -> Result<()>, execve_fd: &RwLock<u16>) -> Result<()> {
-  mount.plugin.open(path.as_str())?;  
+pub fn execve(mount: &Mount, path: &Utf8UnixPath, _tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall, execve_fd: &RwLock<u16>) -> Result<()> {
+  let fh = mount.plugin.open(path.as_str(), nix::libc::O_RDONLY)?;
   let mut read_buf = [0u8; 64*1024];
   let mut len: u64 = 0;
   let memfile_fd = *execve_fd.read().unwrap();
   let mut memfile = unsafe { File::from_raw_fd(memfile_fd as i32) };
-  while let Ok(read_len) = mount.plugin.read(path.as_str(), &mut read_buf, len as i64, 0) && read_len > 0 {
+  while let Ok(read_len) = mount.plugin.read(path.as_str(), &mut read_buf, len as i64, fh) && read_len > 0 {
     len += read_len;
     memfile.set_len(len)?;
     memfile.write_all(&read_buf[0..read_len as usize])?;
   }
   memfile.flush()?;
-  mount.plugin.close(path.as_str(), 0)?;
+  mount.plugin.close(path.as_str(), fh)?;
   
   let mut regs = regs.clone();
   ptrace::getreg!(regs, syscall_nr) = ptrace::syscall_nr!(execveat);
@@ -25,7 +25,7 @@ pub fn execve(mount: &Mount, path: &Utf8UnixPath, tid: ptrace::Pid, regs: ptrace
   ptrace::getreg!(regs, arg2) = 0;
   ptrace::getreg!(regs, arg3) = 0;
   ptrace::getreg!(regs, arg4) = nix::libc::AT_EMPTY_PATH as u64;
-  ptrace::setregs(tid, regs)?;
-  wait_ptrace_ret()?;
+  syscall.rewrite(regs)?;
+  syscall.run()?;
   Ok(())
 }
\ No newline at end of file
@@ -1,19 +1,11 @@
-use crate::state::State;
-use super::{ptrace, Result};
+use crate::process::Process;
+use super::{ptrace, Result, Syscall};
 
-pub fn getcwd(state: &State, tid: ptrace::Pid, regs: ptrace::user_regs_struct, wait_ptrace_ret: impl Fn() -> Result<()>) -> Result<()> {
+pub fn getcwd(process: &Process, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
   let buf_ptr = ptrace::getreg!(regs, arg0);
   let buf_size = ptrace::getreg!(regs, arg1);
-  let cwd = state.cwd.read().unwrap();
+  let cwd = process.cwd.read().unwrap();
   ptrace::write_bytes(tid, buf_ptr, cwd.as_bytes(), buf_size as usize)?;
-  ptrace::setregs(tid, ptrace::user_regs_struct {
-    orig_rax: u64::MAX,
-    ..regs
-  }).unwrap();
-  wait_ptrace_ret()?;
-  ptrace::setregs(tid, ptrace::user_regs_struct {
-    rax: 0,
-    ..ptrace::getregs(tid).unwrap()
-  }).unwrap();
+  syscall.ret(0)?;
   Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,86 @@
+use std::ffi::CStr;
+use nix::errno::Errno;
+use typed_path::{Utf8UnixPath, Utf8UnixPathBuf};
+use crate::{mounts::{Mount, OpenFile}, plugin};
+use super::{attr, ptrace, Result, Syscall};
+
+// struct linux_dirent64 { u64 d_ino; s64 d_off; u16 d_reclen; u8 d_type; char d_name[]; }
+fn dirent64(ino: u64, off: i64, d_type: u8, name: &[u8]) -> Vec<u8> {
+  let reclen = (19 + name.len() + 1).next_multiple_of(8);
+  let mut record = Vec::with_capacity(reclen);
+  record.extend(ino.to_ne_bytes());
+  record.extend(off.to_ne_bytes());
+  record.extend((reclen as u16).to_ne_bytes());
+  record.push(d_type);
+  record.extend(name);
+  record.resize(reclen, 0);
+  record
+}
+
+// struct linux_dirent { unsigned long d_ino; unsigned long d_off; unsigned short d_reclen; char d_name[]; }
+// with d_type stored in the last byte of the record
+fn dirent(ino: u64, off: i64, d_type: u8, name: &[u8]) -> Vec<u8> {
+  let reclen = (18 + name.len() + 2).next_multiple_of(8);
+  let mut record = Vec::with_capacity(reclen);
+  record.extend(ino.to_ne_bytes());
+  record.extend(off.to_ne_bytes());
+  record.extend((reclen as u16).to_ne_bytes());
+  record.extend(name);
+  record.resize(reclen, 0);
+  record[reclen - 1] = d_type;
+  record
+}
+
+/// The path of the entry `name` of the dir at `dir`, as stat sees it.
+fn entry_path(dir: &Utf8UnixPath, name: &[u8]) -> Utf8UnixPathBuf {
+  match name {
+    b"." => dir.to_path_buf(),
+    b".." => dir.parent().unwrap_or(dir).to_path_buf(),
+    name => dir.join(String::from_utf8_lossy(name).as_ref())
+  }
+}
+
+fn fill(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall, encode: fn(u64, i64, u8, &[u8]) -> Vec<u8>) -> Result<()> {
+  let mut fd_info = file.write().unwrap();
+  if !fd_info.is_dir() {
+    return Err(Errno::ENOTDIR.into());
+  }
+  let buf_ptr = ptrace::getreg!(regs, arg1);
+  let buf_size = ptrace::getreg!(regs, arg2) as u32 as usize;
+  let mut entries: Vec<u8> = vec![];
+  let mut offset = fd_info.offset;
+  let mut overflow = false;
+  mount.plugin.readdir(fd_info.path.as_str(), fd_info.offset, fd_info.fh, |name: &CStr, stat: Option<&plugin::stat>| {
+    // DT_* values are the S_IFMT bits shifted down, DT_UNKNOWN when the type is not given
+    let d_type = stat.map_or(0, |stat| ((stat.mode & plugin::S_IFMT) >> 12) as u8);
+    let ino = match stat {
+      Some(stat) if stat.mask & plugin::MOUNTBOX_STAT_INO != 0 => stat.ino,
+      _ => attr::path_ino(entry_path(&fd_info.path, name.to_bytes()).as_str())
+    };
+    let record = encode(ino, offset + 1, d_type, name.to_bytes());
+    if entries.len() + record.len() > buf_size {
+      overflow = true;
+      return true;
+    }
+    entries.extend(record);
+    offset += 1;
+    false
+  })?;
+  if entries.is_empty() && overflow {
+    return Err(Errno::EINVAL.into());
+  }
+  fd_info.offset = offset;
+  drop(fd_info);
+  ptrace::write_bytes(tid, buf_ptr, &entries, entries.len())?;
+  syscall.ret(entries.len() as i64)?;
+  Ok(())
+}
+
+#[cfg(target_arch="x86_64")]
+pub fn getdents(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  fill(mount, file, tid, regs, syscall, dirent)
+}
+
+pub fn getdents64(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  fill(mount, file, tid, regs, syscall, dirent64)
+}
@@ -0,0 +1,19 @@
+use nix::{errno::Errno, libc};
+use typed_path::Utf8UnixPath;
+use crate::mounts::Mount;
+use super::{ptrace, Result, Syscall};
+
+pub fn link(mount: &Mount, from: &Utf8UnixPath, to: &Utf8UnixPath, _tid: ptrace::Pid, _regs: ptrace::Regs, syscall: &impl Syscall, flags: i32) -> Result<()> {
+  if flags & !(libc::AT_SYMLINK_FOLLOW | libc::AT_EMPTY_PATH) != 0 {
+    return Err(Errno::EINVAL.into());
+  }
+  if from == "/" {
+    return Err(Errno::EPERM.into());
+  }
+  if to == "/" {
+    return Err(Errno::EEXIST.into());
+  }
+  mount.plugin.link(from.as_str(), to.as_str())?;
+  syscall.ret(0)?;
+  Ok(())
+}
@@ -0,0 +1,38 @@
+use nix::{errno::Errno, libc};
+use crate::mounts::{Mount, OpenFile};
+use super::{ptrace, Result, Syscall};
+
+pub fn lseek(mount: &Mount, file: &OpenFile, _tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let mut fd_info = file.write().unwrap();
+  let offset = ptrace::getreg!(regs, arg1) as i64;
+  let whence = ptrace::getreg!(regs, arg2) as i32;
+  let size = || -> Result<i64> {
+    if fd_info.is_dir() {
+      // Directory positions are entry indexes, there is no end to seek from
+      return Err(Errno::EINVAL.into());
+    }
+    Ok(mount.plugin.getattr(fd_info.path.as_str())?.size as i64)
+  };
+  let new_offset = match whence {
+    libc::SEEK_SET => Some(offset),
+    libc::SEEK_CUR => fd_info.offset.checked_add(offset),
+    libc::SEEK_END => size()?.checked_add(offset),
+    libc::SEEK_DATA => {
+      if offset >= size()? { return Err(Errno::ENXIO.into()) }
+      Some(offset)
+    },
+    libc::SEEK_HOLE => {
+      let size = size()?;
+      if offset >= size { return Err(Errno::ENXIO.into()) }
+      Some(size)
+    },
+    _ => return Err(Errno::EINVAL.into())
+  }.ok_or(Errno::EOVERFLOW)?;
+  if new_offset < 0 {
+    return Err(Errno::EINVAL.into());
+  }
+  fd_info.offset = new_offset;
+  drop(fd_info);
+  syscall.ret(new_offset)?;
+  Ok(())
+}
@@ -0,0 +1,13 @@
+use nix::errno::Errno;
+use typed_path::Utf8UnixPath;
+use crate::mounts::Mount;
+use super::{ptrace, umask, Result, Syscall};
+
+pub fn mkdir(mount: &Mount, path: &Utf8UnixPath, tid: ptrace::Pid, _regs: ptrace::Regs, syscall: &impl Syscall, mode: u64) -> Result<()> {
+  if path == "/" {
+    return Err(Errno::EEXIST.into());
+  }
+  mount.plugin.mkdir(path.as_str(), mode as u32 & 0o7777 & !umask(tid))?;
+  syscall.ret(0)?;
+  Ok(())
+}
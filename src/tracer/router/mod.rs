@@ -1,17 +1,38 @@
 mod close;
 mod fstat;
+#[cfg(target_arch="x86_64")]
 mod lstat;
 mod open;
 mod read;
+#[cfg(target_arch="x86_64")]
 mod stat;
 mod statx;
 mod getcwd;
 mod chdir;
 mod execve;
+mod getdents;
+mod lseek;
+mod readv;
+mod write;
+mod truncate;
+mod mkdir;
+#[cfg(target_arch="x86_64")]
+mod rmdir;
+mod unlink;
+mod rename;
+mod symlink;
+mod link;
+mod readlink;
+mod walk;
+mod newfstatat;
+mod access;
+mod attr;
+mod dup;
 
-use crate::{dirfd_resolver, plugin, state::State};
+use crate::{dirfd_resolver, mounts::Mount, plugin, state::State};
 use super::ptrace;
-use nix::{libc::user_regs_struct, unistd::Pid};
+use nix::{errno::Errno, libc, unistd::Pid};
+use typed_path::{NativePath, Utf8UnixPath, Utf8UnixPathBuf};
 use thiserror::Error;
 
 macro_rules! el {
@@ -35,53 +56,362 @@ pub enum RouterError {
 
 pub type Result<T> = std::result::Result<T, RouterError>;
 
-pub fn route<'a>(state: &State, regs: user_regs_struct, tid: Pid, wait_ptrace_ret: impl Fn() -> Result<()>) -> Result<()> {
-  macro_rules! route_path {
-    ($path_arg:tt $(@$dirfd_arg:tt)?, $body:expr $(, $($extra_args:expr),*)?) => {{
-      let cwd = state.cwd.read().unwrap();
+/// Most a single read or write transfers, larger counts being clamped to it as the kernel
+/// does: INT_MAX rounded down to a page
+const MAX_RW_COUNT: u64 = 0x7fff_f000;
+
+/// The syscall conventions a syscall was made with.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Abi {
+  Native,
+  /// i386 syscalls, made by 32-bit programs or with `int 0x80`. Arguments are passed where
+  /// native ones go, but structs keep the 32-bit layouts, which are not handled: these fail
+  /// with ENOSYS on the mounts.
+  #[cfg(target_arch="x86_64")]
+  I386
+}
+
+/// The syscall a tracee is stopped in, as completed on the backend that stopped it. Syscalls
+/// none of these are called for are left to the kernel as they are.
+pub trait Syscall {
+  /// Completes the syscall with `value`, a negated errno for failures, instead of running it.
+  fn ret(&self, value: i64) -> Result<()>;
+  /// Completes the syscall with a new fd in the tracee, open on an empty anonymous file named
+  /// `name` and close-on-exec along `O_CLOEXEC` in `flags`. Returns the fd, or the negated
+  /// errno the kernel refused it with.
+  fn ret_fd(&self, name: &[u8], flags: i32) -> Result<i64>;
+  /// Completes the syscall with a duplicate of the tracee fd `fd`: `newfd` replaced when given,
+  /// the lowest free fd from `min` otherwise, close-on-exec along `O_CLOEXEC` in `flags`.
+  /// Returns the new fd, or the negated errno the kernel refused it with.
+  fn ret_dup(&self, fd: i32, newfd: Option<i32>, min: i32, flags: i32) -> Result<i64>;
+  /// Runs the syscall in the kernel, returning its result when the backend gets to see it.
+  fn run(&self) -> Result<Option<i64>>;
+  /// Fails once the syscall is no longer pending, as memory read from the tracee since may
+  /// then come from another process reusing its tid. To call between reading the memory and
+  /// acting on it.
+  fn validate(&self) -> Result<()>;
+  /// Has the kernel run the syscall of `regs` instead.
+  fn rewrite(&self, regs: ptrace::Regs) -> Result<()>;
+  /// Start of the tracee memory free to hold the arguments of a rewritten syscall.
+  fn scratch(&self) -> Result<u64>;
+  /// The conventions the syscall was made with.
+  fn abi(&self) -> Abi;
+}
+
+/// Finds the mount holding `fullpath` along with the path relative to the mount root.
+fn locate<'s>(state: &'s State, fullpath: &NativePath) -> Result<Option<(&'s Mount, Utf8UnixPathBuf)>> {
+  if let Some(mount) = state.mounts.get_mount_of_path(fullpath) {
+    let relpath = Utf8UnixPath::from_bytes_path(fullpath.strip_prefix(&mount.path).unwrap()).map_err(|_| Errno::EINVAL)?;
+    Ok(Some((mount, Utf8UnixPathBuf::from("/").join(relpath))))
+  } else {
+    Ok(None)
+  }
+}
+
+fn umask(tid: Pid) -> u32 {
+  std::fs::read_to_string(format!("/proc/{}/status", tid.as_raw())).ok()
+    .and_then(|status| status.lines().find_map(|line| line.strip_prefix("Umask:").map(|v| v.trim().to_string())))
+    .and_then(|umask| u32::from_str_radix(&umask, 8).ok())
+    .unwrap_or(0o022)
+}
+
+/// Permission bits of a plugin stat. Plugins that leave them all unset get 0o777.
+fn permissions(stat: &plugin::stat) -> u32 {
+  match stat.mode as u32 & 0o7777 {
+    0 => 0o777,
+    perms => perms
+  }
+}
+
+/// The ids listed on the `key` line of the tracee status.
+fn status_ids(tid: Pid, key: &str) -> Vec<u32> {
+  std::fs::read_to_string(format!("/proc/{}/status", tid.as_raw())).ok()
+    .and_then(|status| status.lines().find_map(|line| line.strip_prefix(key).map(|v| {
+      v.split_whitespace().filter_map(|id| id.parse().ok()).collect()
+    })))
+    .unwrap_or_default()
+}
+
+/// The real and filesystem ids from a `Uid:` or `Gid:` line, the latter being the ones the
+/// kernel checks file accesses and sets the owner of new files with.
+fn ids(tid: Pid, key: &str) -> (u32, u32) {
+  let ids = status_ids(tid, key);
+  (ids.first().copied().unwrap_or(0), ids.get(3).copied().unwrap_or(0))
+}
+
+/// The real and filesystem uid of the tracee.
+fn uids(tid: Pid) -> (u32, u32) {
+  ids(tid, "Uid:")
+}
+
+/// The real and filesystem gid of the tracee.
+fn gids(tid: Pid) -> (u32, u32) {
+  ids(tid, "Gid:")
+}
+
+/// The supplementary groups of the tracee.
+fn groups(tid: Pid) -> Vec<u32> {
+  status_ids(tid, "Groups:")
+}
+
+/// The syscalls `route` handles, the only ones tracees need to stop at.
+pub const SYSCALLS: &[i64] = &[
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(open),
+  ptrace::syscall_nr!(openat),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(creat),
+  ptrace::syscall_nr!(openat2),
+  ptrace::syscall_nr!(read),
+  ptrace::syscall_nr!(pread64),
+  ptrace::syscall_nr!(readv),
+  ptrace::syscall_nr!(preadv),
+  ptrace::syscall_nr!(preadv2),
+  ptrace::syscall_nr!(lseek),
+  ptrace::syscall_nr!(write),
+  ptrace::syscall_nr!(pwrite64),
+  ptrace::syscall_nr!(writev),
+  ptrace::syscall_nr!(truncate),
+  ptrace::syscall_nr!(ftruncate),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(mkdir),
+  ptrace::syscall_nr!(mkdirat),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(rmdir),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(unlink),
+  ptrace::syscall_nr!(unlinkat),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(rename),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(renameat),
+  ptrace::syscall_nr!(renameat2),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(symlink),
+  ptrace::syscall_nr!(symlinkat),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(link),
+  ptrace::syscall_nr!(linkat),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(readlink),
+  ptrace::syscall_nr!(readlinkat),
+  ptrace::syscall_nr!(close),
+  ptrace::syscall_nr!(dup),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(dup2),
+  ptrace::syscall_nr!(dup3),
+  ptrace::syscall_nr!(fcntl),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(stat),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(lstat),
+  ptrace::syscall_nr!(fstat),
+  ptrace::syscall_nr!(statx),
+  ptrace::syscall_nr!(newfstatat),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(access),
+  ptrace::syscall_nr!(faccessat),
+  ptrace::syscall_nr!(faccessat2),
+  #[cfg(target_arch="x86_64")]
+  ptrace::syscall_nr!(getdents),
+  ptrace::syscall_nr!(getdents64),
+  ptrace::syscall_nr!(getcwd),
+  ptrace::syscall_nr!(chdir),
+  ptrace::syscall_nr!(execve)
+];
+
+/// Handles the syscall `tid` is entering, completing it through `syscall`.
+pub fn route<'a>(state: &State, regs: ptrace::Regs, tid: Pid, syscall: &impl Syscall) -> Result<()> {
+  const FOLLOW: bool = true;
+  const NOFOLLOW: bool = false;
+  let process = state.process(tid);
+
+  macro_rules! resolve_path {
+    ($path_arg:tt $(@$dirfd_arg:tt)?, $follow:expr) => {{
+      let cwd = process.cwd.read().unwrap();
       let raw_path = ptrace::read_path(tid, ptrace::getreg!(regs, $path_arg))?;
       $(
         let dirfd = ptrace::getreg!(regs, $dirfd_arg) as i32;
-        let fullpath = cwd.join(dirfd_resolver::resolve(tid, dirfd, &raw_path));
+        let fullpath = cwd.join(dirfd_resolver::resolve(&process.fds, tid, dirfd, &raw_path)?);
       )?
       el!(let fullpath = cwd.join(raw_path), $($dirfd_arg)?);
-      let mount = state.mounts.get_mount_of_path(fullpath.as_path());
-      if let Some(mount) = mount {
-        if let Ok(relpath) = typed_path::Utf8UnixPath::from_bytes_path(fullpath.strip_prefix(&mount.path).unwrap()) {
-          let path = typed_path::Utf8UnixPathBuf::from("/").join(relpath);
-          $body(mount, &path, tid, regs, wait_ptrace_ret $(, $($extra_args),*)?)?;
-        } else {
-          return Err(RouterError::PtraceError(nix::errno::Errno::EINVAL));
-        }
-      } else {
-        wait_ptrace_ret()?;
+      syscall.validate()?;
+      match walk::walk(state, &fullpath, $follow, 0)? {
+        Some(walked) => (walked, true),
+        None => (fullpath, false)
+      }
+    }};
+  }
+
+  // Stops syscalls of other ABIs from going any further on the mounts
+  macro_rules! native_only {
+    () => {
+      if syscall.abi() != Abi::Native {
+        return Err(Errno::ENOSYS.into());
+      }
+    };
+  }
+
+  // Points path arguments at walked paths before handing the syscall to the kernel
+  macro_rules! redirect {
+    ($($path_arg:tt => $path:expr),+) => {{
+      let mut regs = regs;
+      let mut sp = syscall.scratch()?;
+      $(ptrace::getreg!(regs, $path_arg) = ptrace::push_cstr(tid, &mut sp, $path.as_bytes())?;)+
+      syscall.rewrite(regs)?;
+    }};
+  }
+
+  macro_rules! route_fullpath {
+    ($fullpath:expr, $body:expr $(, $($extra_args:expr),*)?) => {{
+      if let Some((mount, path)) = locate(state, &$fullpath)? {
+        native_only!();
+        $body(mount, &path, tid, regs, syscall $(, $($extra_args),*)?)?;
+      }
+    }};
+  }
+
+  macro_rules! route_path {
+    ($path_arg:tt $(@$dirfd_arg:tt)?, $follow:expr, $body:expr $(, $($extra_args:expr),*)?) => {{
+      let (fullpath, walked) = resolve_path!($path_arg $(@$dirfd_arg)?, $follow);
+      if walked && locate(state, &fullpath)?.is_none() {
+        redirect!($path_arg => fullpath);
+      }
+      route_fullpath!(fullpath, $body $(, $($extra_args),*)?)
+    }};
+  }
+
+  macro_rules! route_path_pair {
+    ($from_arg:tt $(@$from_dirfd_arg:tt)?, $from_follow:expr, $to_arg:tt $(@$to_dirfd_arg:tt)?, $body:expr $(, $($extra_args:expr),*)?) => {{
+      let (from, from_walked) = resolve_path!($from_arg $(@$from_dirfd_arg)?, $from_follow);
+      let (to, to_walked) = resolve_path!($to_arg $(@$to_dirfd_arg)?, NOFOLLOW);
+      match (locate(state, &from)?, locate(state, &to)?) {
+        (None, None) => {
+          if from_walked || to_walked {
+            redirect!($from_arg => from, $to_arg => to);
+          }
+        },
+        (Some((from_mount, from)), Some((to_mount, to))) if from_mount.path == to_mount.path => {
+          native_only!();
+          $body(from_mount, &from, &to, tid, regs, syscall $(, $($extra_args),*)?)?;
+        },
+        // Neither plugins nor the kernel can move entries across mounts
+        _ => return Err(Errno::EXDEV.into())
       }
     }};
   }
 
   macro_rules! route_fd {
     ($fd_arg:tt, $body:expr) => {{
-      let raw_fd = ptrace::getreg!(regs, $fd_arg) as u16;
-      if let Some(mount) = state.mounts.get_mount_of_fd(raw_fd) {
-        $body(mount, raw_fd, tid, regs, wait_ptrace_ret)?;
-      } else {
-        wait_ptrace_ret()?;
+      if let Some(file) = process.fds.get(ptrace::getreg!(regs, $fd_arg) as u16) {
+        native_only!();
+        let mount = state.mounts.get_mount(&file.read().unwrap().mountpath).unwrap();
+        $body(mount, &file, tid, regs, syscall)?;
       }
     }};
   }
   
   match ptrace::getreg!(regs, syscall_nr) {
-    ptrace::syscall_nr!(open) => route_path!(arg0, open::open),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(open) => route_path!(arg0, open::follows(ptrace::getreg!(regs, arg1)), open::open, &process.fds, ptrace::getreg!(regs, arg1), ptrace::getreg!(regs, arg2)),
+    ptrace::syscall_nr!(openat) => route_path!(arg1@arg0, open::follows(ptrace::getreg!(regs, arg2)), open::open, &process.fds, ptrace::getreg!(regs, arg2), ptrace::getreg!(regs, arg3)),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(creat) => route_path!(arg0, FOLLOW, open::open, &process.fds, (libc::O_CREAT | libc::O_WRONLY | libc::O_TRUNC) as u64, ptrace::getreg!(regs, arg1)),
+    ptrace::syscall_nr!(openat2) => {
+      if let Some(how) = open::read_open_how(tid, regs) {
+        let cwd = process.cwd.read().unwrap();
+        let raw_path = ptrace::read_path(tid, ptrace::getreg!(regs, arg1))?;
+        let dirfd = ptrace::getreg!(regs, arg0) as i32;
+        let fullpath = dirfd_resolver::resolve_openat2(&state.mounts, &process.fds, tid, &cwd, dirfd, &raw_path, how.resolve)?;
+        syscall.validate()?;
+        let follow = how.flags & libc::O_NOFOLLOW as u64 == 0;
+        let fullpath = match walk::walk(state, &fullpath, follow, how.resolve)? {
+          Some(walked) if locate(state, &walked)?.is_none() => {
+            if how.resolve & (libc::RESOLVE_BENEATH | libc::RESOLVE_IN_ROOT) != 0 {
+              // The kernel would read the redirected path relative to dirfd
+              return Err(Errno::EXDEV.into());
+            }
+            redirect!(arg1 => walked);
+            walked
+          },
+          Some(walked) => walked,
+          None => fullpath
+        };
+        route_fullpath!(fullpath, open::openat2, &process.fds, &how)
+      }
+    },
     ptrace::syscall_nr!(read) => route_fd!(arg0, read::read),
-    ptrace::syscall_nr!(close) => route_fd!(arg0, close::close),
-    ptrace::syscall_nr!(stat) => route_path!(arg0, stat::stat),
-    ptrace::syscall_nr!(lstat) => route_path!(arg0, lstat::lstat),
+    ptrace::syscall_nr!(pread64) => route_fd!(arg0, read::pread64),
+    ptrace::syscall_nr!(readv) => route_fd!(arg0, readv::readv),
+    ptrace::syscall_nr!(preadv) => route_fd!(arg0, readv::preadv),
+    ptrace::syscall_nr!(preadv2) => route_fd!(arg0, readv::preadv2),
+    ptrace::syscall_nr!(lseek) => route_fd!(arg0, lseek::lseek),
+    ptrace::syscall_nr!(write) => route_fd!(arg0, write::write),
+    ptrace::syscall_nr!(pwrite64) => route_fd!(arg0, write::pwrite64),
+    ptrace::syscall_nr!(writev) => route_fd!(arg0, write::writev),
+    ptrace::syscall_nr!(truncate) => route_path!(arg0, FOLLOW, truncate::truncate),
+    ptrace::syscall_nr!(ftruncate) => route_fd!(arg0, truncate::ftruncate),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(mkdir) => route_path!(arg0, NOFOLLOW, mkdir::mkdir, ptrace::getreg!(regs, arg1)),
+    ptrace::syscall_nr!(mkdirat) => route_path!(arg1@arg0, NOFOLLOW, mkdir::mkdir, ptrace::getreg!(regs, arg2)),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(rmdir) => route_path!(arg0, NOFOLLOW, rmdir::rmdir),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(unlink) => route_path!(arg0, NOFOLLOW, unlink::unlink, 0),
+    ptrace::syscall_nr!(unlinkat) => route_path!(arg1@arg0, NOFOLLOW, unlink::unlink, ptrace::getreg!(regs, arg2) as i32),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(rename) => route_path_pair!(arg0, NOFOLLOW, arg1, rename::rename, 0),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(renameat) => route_path_pair!(arg1@arg0, NOFOLLOW, arg3@arg2, rename::rename, 0),
+    ptrace::syscall_nr!(renameat2) => route_path_pair!(arg1@arg0, NOFOLLOW, arg3@arg2, rename::rename, ptrace::getreg!(regs, arg4) as u32),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(symlink) => route_path!(arg1, NOFOLLOW, symlink::symlink, &ptrace::read_path(tid, ptrace::getreg!(regs, arg0))?),
+    ptrace::syscall_nr!(symlinkat) => route_path!(arg2@arg1, NOFOLLOW, symlink::symlink, &ptrace::read_path(tid, ptrace::getreg!(regs, arg0))?),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(link) => route_path_pair!(arg0, NOFOLLOW, arg1, link::link, 0),
+    ptrace::syscall_nr!(linkat) => route_path_pair!(arg1@arg0, ptrace::getreg!(regs, arg4) as i32 & libc::AT_SYMLINK_FOLLOW != 0, arg3@arg2, link::link, ptrace::getreg!(regs, arg4) as i32),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(readlink) => route_path!(arg0, NOFOLLOW, readlink::readlink, ptrace::getreg!(regs, arg1), ptrace::getreg!(regs, arg2)),
+    ptrace::syscall_nr!(readlinkat) => route_path!(arg1@arg0, NOFOLLOW, readlink::readlink, ptrace::getreg!(regs, arg2), ptrace::getreg!(regs, arg3)),
+    ptrace::syscall_nr!(close) => close::close(state, &process.fds, regs, syscall)?,
+    ptrace::syscall_nr!(dup) => dup::dup(state, &process.fds, syscall, ptrace::getreg!(regs, arg0) as i32, None, 0, 0)?,
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(dup2) => dup::dup(state, &process.fds, syscall, ptrace::getreg!(regs, arg0) as i32, Some(ptrace::getreg!(regs, arg1) as i32), 0, 0)?,
+    ptrace::syscall_nr!(dup3) => dup::dup(state, &process.fds, syscall, ptrace::getreg!(regs, arg0) as i32, Some(ptrace::getreg!(regs, arg1) as i32), 0, ptrace::getreg!(regs, arg2) as i32)?,
+    ptrace::syscall_nr!(fcntl) => dup::fcntl(state, &process.fds, regs, syscall)?,
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(stat) => route_path!(arg0, FOLLOW, stat::stat),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(lstat) => route_path!(arg0, NOFOLLOW, lstat::lstat),
     ptrace::syscall_nr!(fstat) => route_fd!(arg0, fstat::fstat),
-    ptrace::syscall_nr!(statx) => route_path!(arg1@arg0, statx::statx),
-    ptrace::syscall_nr!(getcwd) => getcwd::getcwd(state, tid, regs, wait_ptrace_ret)?,
-    ptrace::syscall_nr!(chdir) => chdir::chdir(state, tid, regs, wait_ptrace_ret)?,
-    ptrace::syscall_nr!(execve) => route_path!(arg0, execve::execve, &state.execve_fd),
-    _ => wait_ptrace_ret()?
+    ptrace::syscall_nr!(statx) => route_path!(arg1@arg0, ptrace::getreg!(regs, arg2) as i32 & libc::AT_SYMLINK_NOFOLLOW == 0, statx::statx),
+    ptrace::syscall_nr!(newfstatat) => {
+      let flags = ptrace::getreg!(regs, arg3) as i32;
+      let empty = flags & libc::AT_EMPTY_PATH != 0 && ptrace::read_path(tid, ptrace::getreg!(regs, arg1))?.is_empty();
+      syscall.validate()?;
+      if empty {
+        if ptrace::getreg!(regs, arg0) as i32 == libc::AT_FDCWD {
+          let cwd = process.cwd.read().unwrap().clone();
+          route_fullpath!(cwd, newfstatat::newfstatat)
+        } else {
+          route_fd!(arg0, newfstatat::newfstatat_fd)
+        }
+      } else {
+        route_path!(arg1@arg0, flags & libc::AT_SYMLINK_NOFOLLOW == 0, newfstatat::newfstatat)
+      }
+    },
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(access) => route_path!(arg0, FOLLOW, access::access, ptrace::getreg!(regs, arg1) as i32, 0),
+    ptrace::syscall_nr!(faccessat) => route_path!(arg1@arg0, FOLLOW, access::access, ptrace::getreg!(regs, arg2) as i32, 0),
+    ptrace::syscall_nr!(faccessat2) => route_path!(arg1@arg0, ptrace::getreg!(regs, arg3) as i32 & libc::AT_SYMLINK_NOFOLLOW == 0,
+      access::access, ptrace::getreg!(regs, arg2) as i32, ptrace::getreg!(regs, arg3) as i32),
+    #[cfg(target_arch="x86_64")]
+    ptrace::syscall_nr!(getdents) => route_fd!(arg0, getdents::getdents),
+    ptrace::syscall_nr!(getdents64) => route_fd!(arg0, getdents::getdents64),
+    ptrace::syscall_nr!(getcwd) => getcwd::getcwd(&process, tid, regs, syscall)?,
+    ptrace::syscall_nr!(chdir) => chdir::chdir(state, &process, tid, regs, syscall)?,
+    ptrace::syscall_nr!(execve) => route_path!(arg0, FOLLOW, execve::execve, &state.execve_fd),
+    _ => {}
   }
   Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,21 @@
+use typed_path::Utf8UnixPath;
+use crate::mounts::{Mount, OpenFile};
+use super::{attr, ptrace, Result, Syscall};
+
+fn write_stat(mount: &Mount, path: &str, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let stat = attr::getattr(mount, path, tid)?;
+  let buf_ptr = ptrace::getreg!(regs, arg2);
+  attr::write(tid, buf_ptr, &attr::to_stat(mount, &stat))?;
+  syscall.ret(0)?;
+  Ok(())
+}
+
+pub fn newfstatat(mount: &Mount, path: &Utf8UnixPath, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  write_stat(mount, path.as_str(), tid, regs, syscall)
+}
+
+/// `AT_EMPTY_PATH` with an empty path stats `dirfd` itself.
+pub fn newfstatat_fd(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let path = file.read().unwrap().path.clone();
+  write_stat(mount, path.as_str(), tid, regs, syscall)
+}
@@ -1,18 +1,114 @@
+use nix::{errno::Errno, libc};
 use typed_path::Utf8UnixPath;
-use crate::mounts::Mount;
-use super::{ptrace, Result};
+use crate::{mounts::Mount, plugin::{self, PluginError}, process::FdTable};
+use super::{ptrace, umask, Result, Syscall};
 
-pub fn open(mount: &Mount, path: &Utf8UnixPath, tid: ptrace::Pid, regs: ptrace::user_regs_struct, wait_ptrace_ret: impl Fn() -> Result<()>) -> Result<()> {
-  mount.plugin.open(path.as_str())?;
-  let fd = mount.allocate_fd(path.as_str(), None)?;
-  ptrace::setregs(tid, ptrace::user_regs_struct {
-    orig_rax: u64::MAX,
-    ..regs
-  })?;
-  wait_ptrace_ret()?;
-  ptrace::setregs(tid, ptrace::user_regs_struct {
-    rax: fd.into(),
-    ..ptrace::getregs(tid)?
-  })?;
+const OPEN_HOW_SIZE_VER0: u64 = 24;
+/// Longest memfd name, excluding the NUL
+const MFD_NAME_MAX: usize = 249;
+
+pub struct OpenHow {
+  pub flags: u64,
+  pub mode: u64,
+  pub resolve: u64
+}
+
+/// Reads the `struct open_how` passed to openat2. Malformed structs are reported as `None`
+/// so that the kernel can reject the syscall with the proper errno.
+pub fn read_open_how(tid: ptrace::Pid, regs: ptrace::Regs) -> Option<OpenHow> {
+  let size = ptrace::getreg!(regs, arg3);
+  if !(OPEN_HOW_SIZE_VER0..=4096).contains(&size) {
+    return None;
+  }
+  let bytes = ptrace::read_bytes(tid, ptrace::getreg!(regs, arg2), size as usize).ok()?;
+  if bytes[OPEN_HOW_SIZE_VER0 as usize..].iter().any(|b| *b != 0) {
+    return None;
+  }
+  let field = |i: usize| u64::from_ne_bytes(bytes[i*8..(i+1)*8].try_into().unwrap());
+  let how = OpenHow { flags: field(0), mode: field(1), resolve: field(2) };
+  let known_resolve = libc::RESOLVE_NO_XDEV | libc::RESOLVE_NO_MAGICLINKS | libc::RESOLVE_NO_SYMLINKS
+    | libc::RESOLVE_BENEATH | libc::RESOLVE_IN_ROOT | libc::RESOLVE_CACHED;
+  if how.flags > u32::MAX as u64 || how.resolve & !known_resolve != 0
+    || how.resolve & libc::RESOLVE_BENEATH != 0 && how.resolve & libc::RESOLVE_IN_ROOT != 0 {
+    return None;
+  }
+  Some(how)
+}
+
+/// Whether the last path component is followed when it is a symlink. An exclusive create
+/// must not go through a link, and O_NOFOLLOW has open fail on one.
+pub fn follows(flags: u64) -> bool {
+  let flags = flags as i32;
+  flags & libc::O_NOFOLLOW == 0 && flags & (libc::O_CREAT | libc::O_EXCL) != libc::O_CREAT | libc::O_EXCL
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn open(mount: &Mount, path: &Utf8UnixPath, tid: ptrace::Pid, _regs: ptrace::Regs, syscall: &impl Syscall, fds: &FdTable, flags: u64, mode: u64) -> Result<()> {
+  let flags = flags as i32;
+  let writable = matches!(flags & libc::O_ACCMODE, libc::O_WRONLY | libc::O_RDWR);
+  // What the path is, for links to be refused and directories to be told from files
+  let kind = if flags & libc::O_DIRECTORY == 0 || flags & libc::O_NOFOLLOW != 0 {
+    mount.plugin.getattr(path.as_str()).ok().map(|stat| stat.mode & plugin::S_IFMT)
+  } else {
+    None
+  };
+  if flags & libc::O_NOFOLLOW != 0 && kind == Some(plugin::S_IFLNK) {
+    return Err(Errno::ELOOP.into());
+  }
+  if writable && kind == Some(plugin::S_IFDIR) {
+    return Err(Errno::EISDIR.into());
+  }
+  // Directories are opened as such whether or not O_DIRECTORY asks for one
+  let dir = flags & libc::O_DIRECTORY != 0 || kind == Some(plugin::S_IFDIR) && flags & libc::O_CREAT == 0;
+  let fh = if dir {
+    if flags & libc::O_CREAT != 0 {
+      return Err(Errno::EINVAL.into());
+    }
+    if writable {
+      return Err(Errno::EISDIR.into());
+    }
+    mount.plugin.opendir(path.as_str())?
+  } else {
+    let mut created = false;
+    if flags & libc::O_CREAT != 0 {
+      match mount.plugin.getattr(path.as_str()) {
+        Ok(_) if flags & libc::O_EXCL != 0 => return Err(Errno::EEXIST.into()),
+        Ok(stat) if stat.mode & plugin::S_IFMT == plugin::S_IFDIR => return Err(Errno::EISDIR.into()),
+        Ok(_) => {},
+        Err(PluginError::Errno(Errno::ENOENT)) => {
+          mount.plugin.create(path.as_str(), mode as u32 & 0o7777 & !umask(tid))?;
+          created = true;
+        },
+        Err(err) => return Err(err.into())
+      }
+    }
+    // The creation flags were dealt with above and are not for the plugin
+    let fh = mount.plugin.open(path.as_str(), flags & !(libc::O_CREAT | libc::O_EXCL | libc::O_NOCTTY))?;
+    if flags & libc::O_TRUNC != 0 && writable && !created && !mount.plugin.has_capability(plugin::MOUNTBOX_CAP_ATOMIC_O_TRUNC) {
+      mount.plugin.truncate(path.as_str(), 0)?;
+    }
+    fh
+  };
+  let file = mount.open_file(path.as_str(), flags, dir, fh);
+  // A placeholder fd numbered by the tracee's own fd table
+  let mut name = format!("mountbox:{}", mount.path.join(path.as_str().trim_start_matches('/')).to_string_lossy()).into_bytes();
+  name.truncate(MFD_NAME_MAX);
+  match syscall.ret_fd(&name, flags) {
+    Ok(fd) if fd >= 0 => fds.insert(fd as u16, file, flags & libc::O_CLOEXEC != 0),
+    // The tracee is out of fds, and the kernel already reported so
+    Ok(_) => { let _ = mount.release(file); },
+    Err(err) => {
+      let _ = mount.release(file);
+      return Err(err);
+    }
+  }
   Ok(())
-}
\ No newline at end of file
+}
+
+pub fn openat2(mount: &Mount, path: &Utf8UnixPath, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall, fds: &FdTable, how: &OpenHow) -> Result<()> {
+  if how.resolve & libc::RESOLVE_CACHED != 0 {
+    // Plugin lookups can never be served from the dcache
+    return Err(Errno::EAGAIN.into());
+  }
+  open(mount, path, tid, regs, syscall, fds, how.flags, how.mode)
+}
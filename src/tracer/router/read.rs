@@ -1,23 +1,44 @@
-use nix::libc::user_regs_struct;
-use crate::mounts::Mount;
-use super::{ptrace, Result};
+use nix::errno::Errno;
+use crate::mounts::{FileInfo, Mount, OpenFile};
+use super::{ptrace, Result, Syscall, MAX_RW_COUNT};
 
-pub fn read(mount: &Mount, fd: u16, tid: ptrace::Pid, regs: user_regs_struct, wait_ptrace_ret: impl Fn() -> Result<()>) -> Result<()> {
-  let fd_info = mount.get_fd_info(fd).unwrap();
+pub fn read_at(mount: &Mount, fd_info: &FileInfo, size: u64, offset: i64) -> Result<Vec<u8>> {
+  if fd_info.is_dir() {
+    return Err(Errno::EISDIR.into());
+  }
+  if !fd_info.is_readable() {
+    return Err(Errno::EBADF.into());
+  }
+  let mut read_buf = vec![0u8; size.min(MAX_RW_COUNT) as usize];
+  let read_len = mount.plugin.read(fd_info.path.as_str(), &mut read_buf, offset, fd_info.fh)?;
+  read_buf.truncate(read_len as usize);
+  Ok(read_buf)
+}
+
+pub fn read(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let mut fd_info = file.write().unwrap();
+  let buf_ptr = ptrace::getreg!(regs, arg1);
+  let buf_size = ptrace::getreg!(regs, arg2);
+  let read_buf = read_at(mount, &fd_info, buf_size, fd_info.offset)?;
+  // Only what reached the buffer counts as read
+  let read_len = ptrace::write_partial(tid, buf_ptr, &read_buf)?;
+  fd_info.offset += read_len as i64;
+  drop(fd_info);
+  syscall.ret(read_len as i64)?;
+  Ok(())
+}
+
+pub fn pread64(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let fd_info = file.read().unwrap();
   let buf_ptr = ptrace::getreg!(regs, arg1);
   let buf_size = ptrace::getreg!(regs, arg2);
-  // TODO: impl offset
-  let mut read_buf = vec![0u8; buf_size as usize];
-  let read_len = mount.plugin.read(fd_info.path.as_str(), &mut read_buf, 0, fd_info.fh)?;
-  ptrace::write_bytes(tid, buf_ptr, &read_buf, read_len as usize)?;
-  ptrace::setregs(tid, user_regs_struct {
-    orig_rax: u64::MAX,
-    ..regs
-  }).unwrap();
-  wait_ptrace_ret()?;
-  ptrace::setregs(tid, user_regs_struct {
-    rax: read_len as u64,
-    ..ptrace::getregs(tid).unwrap()
-  }).unwrap();
+  let offset = ptrace::getreg!(regs, arg3) as i64;
+  if offset < 0 {
+    return Err(Errno::EINVAL.into());
+  }
+  let read_buf = read_at(mount, &fd_info, buf_size, offset)?;
+  drop(fd_info);
+  let read_len = ptrace::write_partial(tid, buf_ptr, &read_buf)?;
+  syscall.ret(read_len as i64)?;
   Ok(())
-}
\ No newline at end of file
+}
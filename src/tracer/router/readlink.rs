@@ -0,0 +1,20 @@
+use nix::errno::Errno;
+use typed_path::Utf8UnixPath;
+use crate::{mounts::Mount, plugin};
+use super::{ptrace, Result, Syscall};
+
+pub fn readlink(mount: &Mount, path: &Utf8UnixPath, tid: ptrace::Pid, _regs: ptrace::Regs, syscall: &impl Syscall, buf_ptr: u64, buf_size: u64) -> Result<()> {
+  if buf_size as i32 <= 0 {
+    return Err(Errno::EINVAL.into());
+  }
+  // Plugins without readlink cannot hold links
+  if !mount.plugin.has_readlink() || mount.plugin.getattr(path.as_str())?.mode & plugin::S_IFMT != plugin::S_IFLNK {
+    return Err(Errno::EINVAL.into());
+  }
+  let target = mount.plugin.readlink(path.as_str())?;
+  // The result is silently truncated and never NUL-terminated
+  let len = target.len().min(buf_size as i32 as usize);
+  ptrace::write_bytes(tid, buf_ptr, target.as_bytes(), len)?;
+  syscall.ret(len as i64)?;
+  Ok(())
+}
@@ -0,0 +1,77 @@
+use nix::{errno::Errno, libc};
+use crate::mounts::{Mount, OpenFile};
+use super::{ptrace, read::read_at, Result, Syscall};
+
+/// Reads the `struct iovec` array at `addr` as (base, len) pairs.
+pub fn read_iovecs(tid: ptrace::Pid, addr: u64, count: u64) -> Result<Vec<(u64, u64)>> {
+  if count > libc::UIO_MAXIOV as u64 {
+    return Err(Errno::EINVAL.into());
+  }
+  let bytes = ptrace::read_bytes(tid, addr, count as usize * 16)?;
+  let iovecs = bytes.chunks_exact(16).map(|iov| (
+    u64::from_ne_bytes(iov[0..8].try_into().unwrap()),
+    u64::from_ne_bytes(iov[8..16].try_into().unwrap())
+  )).collect::<Vec<(u64, u64)>>();
+  let total = iovecs.iter().try_fold(0u64, |total, (_, len)| total.checked_add(*len));
+  if total.is_none_or(|total| total > isize::MAX as u64) {
+    return Err(Errno::EINVAL.into());
+  }
+  Ok(iovecs)
+}
+
+/// Spreads `data` over the buffers of `iovecs` up to the first fault. Returns how much of it
+/// was written.
+fn scatter(tid: ptrace::Pid, iovecs: &[(u64, u64)], data: &[u8]) -> Result<usize> {
+  let mut pos = 0;
+  for (base, len) in iovecs {
+    if pos >= data.len() {
+      break;
+    }
+    let chunk = &data[pos..data.len().min(pos + *len as usize)];
+    let written = match ptrace::write_partial(tid, *base, chunk) {
+      Err(Errno::EFAULT) if pos > 0 => 0,
+      res => res?
+    };
+    pos += written;
+    if written < chunk.len() {
+      break;
+    }
+  }
+  Ok(pos)
+}
+
+fn readv_at(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall, offset: Option<i64>) -> Result<()> {
+  let mut fd_info = file.write().unwrap();
+  let iovecs = read_iovecs(tid, ptrace::getreg!(regs, arg1), ptrace::getreg!(regs, arg2))?;
+  syscall.validate()?;
+  let size = iovecs.iter().map(|(_, len)| len).sum();
+  let read_buf = read_at(mount, &fd_info, size, offset.unwrap_or(fd_info.offset))?;
+  let read_len = scatter(tid, &iovecs, &read_buf)?;
+  if offset.is_none() {
+    fd_info.offset += read_len as i64;
+  }
+  drop(fd_info);
+  syscall.ret(read_len as i64)?;
+  Ok(())
+}
+
+pub fn readv(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  readv_at(mount, file, tid, regs, syscall, None)
+}
+
+pub fn preadv(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let offset = ptrace::getreg!(regs, arg3) as i64;
+  if offset < 0 {
+    return Err(Errno::EINVAL.into());
+  }
+  readv_at(mount, file, tid, regs, syscall, Some(offset))
+}
+
+pub fn preadv2(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  // An offset of -1 reads from the current file position like readv
+  match ptrace::getreg!(regs, arg3) as i64 {
+    -1 => readv_at(mount, file, tid, regs, syscall, None),
+    offset if offset < 0 => Err(Errno::EINVAL.into()),
+    offset => readv_at(mount, file, tid, regs, syscall, Some(offset))
+  }
+}
@@ -0,0 +1,21 @@
+use nix::{errno::Errno, libc};
+use typed_path::Utf8UnixPath;
+use crate::{mounts::Mount, plugin};
+use super::{ptrace, Result, Syscall};
+
+pub fn rename(mount: &Mount, from: &Utf8UnixPath, to: &Utf8UnixPath, _tid: ptrace::Pid, _regs: ptrace::Regs, syscall: &impl Syscall, flags: u32) -> Result<()> {
+  if flags & !(libc::RENAME_NOREPLACE | libc::RENAME_EXCHANGE | libc::RENAME_WHITEOUT) != 0
+    || flags & plugin::RENAME_NOREPLACE != 0 && flags & plugin::RENAME_EXCHANGE != 0 {
+    return Err(Errno::EINVAL.into());
+  }
+  // Not to have flags silently ignored
+  if flags != 0 && !mount.plugin.has_capability(plugin::MOUNTBOX_CAP_RENAME_FLAGS) {
+    return Err(Errno::EINVAL.into());
+  }
+  if from == "/" || to == "/" {
+    return Err(Errno::EBUSY.into());
+  }
+  mount.plugin.rename(from.as_str(), to.as_str(), flags)?;
+  syscall.ret(0)?;
+  Ok(())
+}
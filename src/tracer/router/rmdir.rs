@@ -0,0 +1,13 @@
+use nix::errno::Errno;
+use typed_path::Utf8UnixPath;
+use crate::mounts::Mount;
+use super::{ptrace, Result, Syscall};
+
+pub fn rmdir(mount: &Mount, path: &Utf8UnixPath, _tid: ptrace::Pid, _regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  if path == "/" {
+    return Err(Errno::EBUSY.into());
+  }
+  mount.plugin.rmdir(path.as_str())?;
+  syscall.ret(0)?;
+  Ok(())
+}
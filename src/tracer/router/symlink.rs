@@ -0,0 +1,17 @@
+use nix::errno::Errno;
+use typed_path::Utf8UnixPath;
+use crate::mounts::Mount;
+use super::{ptrace, Result, Syscall};
+
+pub fn symlink(mount: &Mount, path: &Utf8UnixPath, _tid: ptrace::Pid, _regs: ptrace::Regs, syscall: &impl Syscall, target: &str) -> Result<()> {
+  syscall.validate()?;
+  if target.is_empty() {
+    return Err(Errno::ENOENT.into());
+  }
+  if path == "/" {
+    return Err(Errno::EEXIST.into());
+  }
+  mount.plugin.symlink(target, path.as_str())?;
+  syscall.ret(0)?;
+  Ok(())
+}
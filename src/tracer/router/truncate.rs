@@ -0,0 +1,26 @@
+use nix::errno::Errno;
+use typed_path::Utf8UnixPath;
+use crate::mounts::{Mount, OpenFile};
+use super::{ptrace, Result, Syscall};
+
+pub fn truncate(mount: &Mount, path: &Utf8UnixPath, _tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let size = ptrace::getreg!(regs, arg1) as i64;
+  if size < 0 {
+    return Err(Errno::EINVAL.into());
+  }
+  mount.plugin.truncate(path.as_str(), size)?;
+  syscall.ret(0)?;
+  Ok(())
+}
+
+pub fn ftruncate(mount: &Mount, file: &OpenFile, _tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let fd_info = file.read().unwrap();
+  let size = ptrace::getreg!(regs, arg1) as i64;
+  if size < 0 || fd_info.is_dir() || !fd_info.is_writable() {
+    return Err(Errno::EINVAL.into());
+  }
+  mount.plugin.truncate(fd_info.path.as_str(), size)?;
+  drop(fd_info);
+  syscall.ret(0)?;
+  Ok(())
+}
@@ -0,0 +1,23 @@
+use nix::{errno::Errno, libc};
+use typed_path::Utf8UnixPath;
+use crate::mounts::Mount;
+use super::{ptrace, Result, Syscall};
+
+pub fn unlink(mount: &Mount, path: &Utf8UnixPath, _tid: ptrace::Pid, _regs: ptrace::Regs, syscall: &impl Syscall, flags: i32) -> Result<()> {
+  if flags & !libc::AT_REMOVEDIR != 0 {
+    return Err(Errno::EINVAL.into());
+  }
+  if flags & libc::AT_REMOVEDIR != 0 {
+    if path == "/" {
+      return Err(Errno::EBUSY.into());
+    }
+    mount.plugin.rmdir(path.as_str())?;
+  } else {
+    if path == "/" {
+      return Err(Errno::EISDIR.into());
+    }
+    mount.plugin.unlink(path.as_str())?;
+  }
+  syscall.ret(0)?;
+  Ok(())
+}
@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+use nix::{errno::Errno, libc};
+use typed_path::{NativePath, NativePathBuf, UnixComponent};
+use crate::{plugin, state::State};
+use super::{locate, Result};
+
+const MAXSYMLINKS: usize = 40;
+
+fn split(path: &[u8]) -> impl Iterator<Item = Vec<u8>> + '_ {
+  NativePath::new(path).components().map(|component| match component {
+    UnixComponent::RootDir => b"/".to_vec(),
+    UnixComponent::CurDir => b".".to_vec(),
+    UnixComponent::ParentDir => b"..".to_vec(),
+    UnixComponent::Normal(name) => name.to_vec()
+  })
+}
+
+/// Walks `path` component by component, following the symlinks reported by plugins the
+/// way the kernel would. The last component is only followed when `follow_last` is set,
+/// and `resolve` takes the openat2 `RESOLVE_*` flags restricting links.
+///
+/// Returns `None` when no plugin link was met, so that host paths are left for the kernel
+/// to resolve exactly as given.
+pub fn walk(state: &State, path: &NativePath, follow_last: bool, resolve: u64) -> Result<Option<NativePathBuf>> {
+  let mut pending: VecDeque<Vec<u8>> = split(path.as_bytes()).collect();
+  let mut resolved = NativePathBuf::from("/");
+  let mut hops = 0;
+  while let Some(component) = pending.pop_front() {
+    match component.as_slice() {
+      b"/" => resolved = NativePathBuf::from("/"),
+      b"." => {},
+      b".." => { resolved.pop(); },
+      name => {
+        resolved.push(name);
+        let is_last = pending.is_empty();
+        let Some((mount, relpath)) = locate(state, &resolved)? else { continue };
+        if relpath == "/" || !mount.plugin.has_readlink() {
+          continue;
+        }
+        match mount.plugin.getattr(relpath.as_str()) {
+          Ok(stat) if stat.mode & plugin::S_IFMT == plugin::S_IFLNK => {
+            if is_last && !follow_last {
+              continue;
+            }
+            let target = match mount.plugin.readlink(relpath.as_str()) {
+              // Not a link the plugin can follow
+              Err(plugin::PluginError::Errno(Errno::ENOSYS)) => continue,
+              res => res?
+            };
+            hops += 1;
+            if hops > MAXSYMLINKS || resolve & libc::RESOLVE_NO_SYMLINKS != 0 {
+              return Err(Errno::ELOOP.into());
+            }
+            if target.is_empty() {
+              return Err(Errno::ENOENT.into());
+            }
+            if target.starts_with('/') && resolve & (libc::RESOLVE_BENEATH | libc::RESOLVE_IN_ROOT) != 0 {
+              return Err(Errno::EXDEV.into());
+            }
+            resolved.pop();
+            for component in split(target.as_bytes()).collect::<Vec<_>>().into_iter().rev() {
+              pending.push_front(component);
+            }
+          },
+          Ok(stat) if !is_last && stat.mode & plugin::S_IFMT != plugin::S_IFDIR => return Err(Errno::ENOTDIR.into()),
+          Ok(_) => {},
+          // Filesystems served in-process have readlink whether or not they report links
+          Err(plugin::PluginError::Errno(Errno::ENOSYS)) => {},
+          // A missing last component is for the handler to deal with, e.g. O_CREAT
+          Err(_) if is_last => {},
+          Err(err) => return Err(err.into())
+        }
+      }
+    }
+  }
+  Ok(if hops > 0 { Some(resolved) } else { None })
+}
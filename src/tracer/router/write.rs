@@ -0,0 +1,64 @@
+use nix::{errno::Errno, libc};
+use crate::mounts::{FileInfo, Mount, OpenFile};
+use super::{ptrace, readv::read_iovecs, Result, Syscall, MAX_RW_COUNT};
+
+pub fn write_at(mount: &Mount, fd_info: &FileInfo, buf: &[u8], offset: i64) -> Result<u64> {
+  if !fd_info.is_writable() {
+    return Err(Errno::EBADF.into());
+  }
+  // O_APPEND writes always land at the end of the file, even for pwrite
+  let offset = if fd_info.flags & libc::O_APPEND != 0 {
+    mount.plugin.getattr(fd_info.path.as_str())?.size as i64
+  } else {
+    offset
+  };
+  Ok(mount.plugin.write(fd_info.path.as_str(), buf, offset, fd_info.fh)?)
+}
+
+fn write_buf(mount: &Mount, file: &OpenFile, syscall: &impl Syscall, buf: &[u8], offset: Option<i64>) -> Result<()> {
+  syscall.validate()?;
+  let mut fd_info = file.write().unwrap();
+  let write_len = write_at(mount, &fd_info, buf, offset.unwrap_or(fd_info.offset))?;
+  if offset.is_none() {
+    fd_info.offset = if fd_info.flags & libc::O_APPEND != 0 {
+      mount.plugin.getattr(fd_info.path.as_str())?.size as i64
+    } else {
+      fd_info.offset + write_len as i64
+    };
+  }
+  drop(fd_info);
+  syscall.ret(write_len as i64)?;
+  Ok(())
+}
+
+pub fn write(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let buf = ptrace::read_partial(tid, ptrace::getreg!(regs, arg1), ptrace::getreg!(regs, arg2).min(MAX_RW_COUNT) as usize)?;
+  write_buf(mount, file, syscall, &buf, None)
+}
+
+pub fn pwrite64(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let offset = ptrace::getreg!(regs, arg3) as i64;
+  if offset < 0 {
+    return Err(Errno::EINVAL.into());
+  }
+  let buf = ptrace::read_partial(tid, ptrace::getreg!(regs, arg1), ptrace::getreg!(regs, arg2).min(MAX_RW_COUNT) as usize)?;
+  write_buf(mount, file, syscall, &buf, Some(offset))
+}
+
+pub fn writev(mount: &Mount, file: &OpenFile, tid: ptrace::Pid, regs: ptrace::Regs, syscall: &impl Syscall) -> Result<()> {
+  let iovecs = read_iovecs(tid, ptrace::getreg!(regs, arg1), ptrace::getreg!(regs, arg2))?;
+  let mut buf = vec![];
+  // Up to the first fault, which only fails the syscall if nothing precedes it
+  for (base, len) in iovecs {
+    let len = len.min(MAX_RW_COUNT - buf.len() as u64);
+    let chunk = match ptrace::read_partial(tid, base, len as usize) {
+      Err(Errno::EFAULT) if !buf.is_empty() => break,
+      res => res?
+    };
+    buf.extend(&chunk);
+    if chunk.len() < len as usize {
+      break;
+    }
+  }
+  write_buf(mount, file, syscall, &buf, None)
+}
@@ -0,0 +1,69 @@
+use std::mem::offset_of;
+use nix::{errno::Errno, libc::{self, BPF_ABS, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W}};
+use super::{ptrace, router};
+
+fn stmt(code: u32, k: u32) -> libc::sock_filter {
+  jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+  libc::sock_filter { code: code as u16, jt, jf, k }
+}
+
+// The jump over a block of two instructions per syscall must fit in a u8
+const _: () = assert!(2 * router::SYSCALLS.len() + 2 <= u8::MAX as usize);
+#[cfg(target_arch="x86_64")]
+const _: () = assert!(2 * ptrace::I386_SYSCALLS.len() + 2 <= u8::MAX as usize);
+
+/// Instructions returning `action` for the syscalls of `arch` in `syscalls`, skipped for
+/// other ABIs. Expect the arch loaded.
+fn arch_block(arch: u32, syscalls: impl Iterator<Item = u64>, action: u32) -> Vec<libc::sock_filter> {
+  let mut block = vec![stmt(BPF_LD | BPF_W | BPF_ABS, offset_of!(libc::seccomp_data, nr) as u32)];
+  for nr in syscalls {
+    block.push(jump(BPF_JMP | BPF_JEQ | BPF_K, nr as u32, 0, 1));
+    block.push(stmt(BPF_RET | BPF_K, action));
+  }
+  block.push(stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW));
+  let mut filter = vec![jump(BPF_JMP | BPF_JEQ | BPF_K, arch, 0, block.len() as u8)];
+  filter.extend(block);
+  filter
+}
+
+/// A filter returning `action` for the syscalls the router handles only.
+fn filter(action: u32) -> Vec<libc::sock_filter> {
+  let mut filter = vec![stmt(BPF_LD | BPF_W | BPF_ABS, offset_of!(libc::seccomp_data, arch) as u32)];
+  filter.extend(arch_block(ptrace::AUDIT_ARCH, router::SYSCALLS.iter().map(|nr| *nr as u64), action));
+  #[cfg(target_arch="x86_64")]
+  filter.extend(arch_block(ptrace::AUDIT_ARCH_I386, ptrace::I386_SYSCALLS.iter().map(|(nr, _)| *nr), action));
+  // Syscalls of other ABIs are not routed
+  filter.push(stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW));
+  filter
+}
+
+/// Installs the filter of `action` on the calling thread, inherited by its children and
+/// across execve. Returns what seccomp does for `flags`.
+pub fn install(action: u32, flags: libc::c_ulong) -> Result<i64, Errno> {
+  let filter = filter(action);
+  let prog = libc::sock_fprog {
+    len: filter.len() as u16,
+    filter: filter.as_ptr() as *mut libc::sock_filter
+  };
+  let install = || Errno::result(unsafe { libc::syscall(libc::SYS_seccomp, libc::SECCOMP_SET_MODE_FILTER, flags, &prog) });
+  match install() {
+    // Unprivileged processes may only install it with no_new_privs, which setuid binaries
+    // and file capabilities are ignored under
+    Err(Errno::EACCES) => {
+      Errno::result(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+      install()
+    },
+    res => res
+  }
+}
+
+/// Installs the filter having the ptrace tracer stop at routed syscalls.
+///
+/// Meant to be called by the command to trace once attached: filtered syscalls fail with
+/// ENOSYS for a process that has no tracer.
+pub fn install_filter() -> Result<(), Errno> {
+  install(libc::SECCOMP_RET_TRACE, 0).map(drop)
+}
@@ -0,0 +1,107 @@
+#![cfg(target_arch="x86_64")]
+use std::{ffi::CString, sync::Mutex};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+create_plugin!(access_should_check_mode_bits_plugin, getattr: |
+  path: *const std::os::raw::c_char,
+  stat: *mut raw::stat| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let stat = unsafe { stat.as_mut().unwrap() };
+    match path {
+      "/readonly" => stat.mode = raw::S_IFREG | 0o444,
+      "/unset" => stat.mode = raw::S_IFREG,
+      _ => return -(raw::ENOENT as i32)
+    }
+    return 0;
+});
+
+#[test]
+fn access_should_check_mode_bits() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/readonly").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(access), path.as_ptr(), libc::R_OK), 0);
+      assert_eq!(libc::syscall(syscall_nr!(faccessat), libc::AT_FDCWD, path.as_ptr(), libc::X_OK), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EACCES);
+      assert_eq!(libc::syscall(syscall_nr!(faccessat2), libc::AT_FDCWD, path.as_ptr(), 8, 0), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EINVAL);
+      let path = CString::new("/test/unset").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(faccessat2), libc::AT_FDCWD, path.as_ptr(), libc::R_OK | libc::X_OK, libc::AT_EACCESS), 0);
+      let path = CString::new("/test/missing").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(access), path.as_ptr(), libc::F_OK), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::ENOENT);
+    };
+  });
+  let state = create_state!("/test", access_should_check_mode_bits_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+static MASKS: Mutex<Vec<i32>> = Mutex::new(vec![]);
+
+create_plugin!(access_should_use_plugin_op_plugin, access: |
+  path: *const std::os::raw::c_char,
+  mask: i32| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/access");
+    MASKS.lock().unwrap().push(mask);
+    return if mask & raw::W_OK as i32 != 0 { -(raw::EPERM as i32) } else { 0 };
+});
+
+#[test]
+fn access_should_use_plugin_op() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/access").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(access), path.as_ptr(), libc::R_OK), 0);
+      assert_eq!(libc::syscall(syscall_nr!(faccessat2), libc::AT_FDCWD, path.as_ptr(), libc::W_OK, 0), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EPERM);
+    };
+  });
+  let state = create_state!("/test", access_should_use_plugin_op_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(*MASKS.lock().unwrap(), vec![libc::R_OK, libc::W_OK]);
+}
+
+create_plugin!(access_should_check_owner_class_plugin, getattr: |
+  path: *const std::os::raw::c_char,
+  stat: *mut raw::stat| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let stat = unsafe { stat.as_mut().unwrap() };
+    stat.mask = raw::MOUNTBOX_STAT_TYPE | raw::MOUNTBOX_STAT_MODE | raw::MOUNTBOX_STAT_UID | raw::MOUNTBOX_STAT_GID;
+    (stat.uid, stat.gid, stat.mode) = match path {
+      "/owner" => (1000, 0, raw::S_IFREG | 0o600),
+      "/group" => (0, 100, raw::S_IFREG | 0o040),
+      "/other" => (0, 0, raw::S_IFREG | 0o604),
+      "/none" => (0, 0, raw::S_IFREG | 0o660),
+      _ => return -(raw::ENOENT as i32)
+    };
+    return 0;
+});
+
+#[test]
+fn access_should_check_owner_class() {
+  let child = run_child!(move || {
+    unsafe {
+      // An unprivileged user, in group 100 only
+      assert_eq!(libc::setgroups(0, std::ptr::null()), 0);
+      assert_eq!(libc::setresgid(100, 100, 100), 0);
+      assert_eq!(libc::setresuid(1000, 1000, 1000), 0);
+      for (path, allowed, denied) in [("/test/owner", libc::R_OK | libc::W_OK, libc::X_OK), ("/test/group", libc::R_OK, libc::W_OK),
+        ("/test/other", libc::R_OK, libc::W_OK), ("/test/none", libc::F_OK, libc::R_OK)] {
+        let path = CString::new(path).unwrap();
+        assert_eq!(libc::syscall(syscall_nr!(access), path.as_ptr(), allowed), 0);
+        assert_eq!(libc::syscall(syscall_nr!(access), path.as_ptr(), denied), -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EACCES);
+      }
+    };
+  });
+  let state = create_state!("/test", access_should_check_owner_class_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
@@ -1,4 +1,4 @@
-use std::{ffi::CString, str::FromStr};
+use std::{ffi::{CStr, CString}, str::FromStr};
 use common::raw;
 use mountbox::{syscall_nr, tracer};
 use nix::{fcntl::readlink, libc};
@@ -14,12 +14,14 @@ fn chdir_to_mount_should_succeed() {
       let path = CString::from_str("/test/chdir").unwrap();
       let res = libc::syscall(syscall_nr!(chdir), path.as_ptr());
       assert!(res == 0);
+      let buf = [0u8; 32];
+      assert_ne!(libc::syscall(syscall_nr!(getcwd), &buf, buf.len()), -1);
+      assert_eq!(CStr::from_bytes_until_nul(&buf).unwrap().to_str().unwrap(), "/test/chdir");
     };
   });
   let state = create_state!("/test", chdir_plugin);
   let status = tracer::attach(state.clone(), child).unwrap();
   assert_eq!(status, tracer::TraceeStatus::Exited(0));
-  assert_eq!(state.cwd.read().unwrap().to_str().unwrap(), "/test/chdir");
   // assert_eq!(readlink(format!("/proc/{}/cwd", child).as_str()).unwrap().to_str().unwrap(), "/test/chdir");
 }
 
@@ -31,11 +33,13 @@ fn chdir_to_not_mount_should_succeed() {
       let path = CString::from_str("/").unwrap();
       let res = libc::syscall(syscall_nr!(chdir), path.as_ptr());
       assert!(res == 0);
+      let buf = [0u8; 32];
+      assert_ne!(libc::syscall(syscall_nr!(getcwd), &buf, buf.len()), -1);
+      assert_eq!(CStr::from_bytes_until_nul(&buf).unwrap().to_str().unwrap(), "/");
+      assert_eq!(readlink("/proc/self/cwd").unwrap().to_str().unwrap(), "/");
     };
   });
   let state = create_state!("/test", chdir_plugin);
   let status = tracer::attach(state.clone(), child).unwrap();
   assert_eq!(status, tracer::TraceeStatus::Exited(0));
-  assert_eq!(state.cwd.read().unwrap().to_str().unwrap(), "/");
-  assert_eq!(readlink(format!("/proc/{}/cwd", child).as_str()).unwrap().to_str().unwrap(), "/");
 }
\ No newline at end of file
@@ -0,0 +1,101 @@
+use std::{os::unix::process::{CommandExt, ExitStatusExt}, process::{Command, Stdio}, time::{Duration, Instant}};
+use nix::{libc, sys::{signal::{kill, Signal}, wait::{waitpid, WaitPidFlag, WaitStatus}}, unistd::Pid};
+
+fn mountbox(script: &str) -> std::process::ExitStatus {
+  Command::new(env!("CARGO_BIN_EXE_mountbox")).args(["--", "sh", "-c", script]).status().unwrap()
+}
+
+#[test]
+fn cli_should_exit_with_command_code() {
+  assert_eq!(mountbox("exit 42").code(), Some(42));
+}
+
+#[test]
+fn cli_should_wait_for_whole_tree() {
+  let dir = std::env::temp_dir().join(format!("mountbox-cli-{}", std::process::id()));
+  let status = mountbox(&format!("(sleep 0.2; touch {}) & exit 3", dir.display()));
+  assert_eq!(status.code(), Some(3));
+  assert!(dir.exists());
+  std::fs::remove_file(dir).unwrap();
+}
+
+#[test]
+fn cli_should_die_of_command_signal() {
+  let status = mountbox("kill -TERM $$");
+  assert_eq!(status.signal(), Some(libc::SIGTERM));
+}
+
+#[test]
+fn cli_notify_backend_should_exit_with_command_code() {
+  let status = Command::new(env!("CARGO_BIN_EXE_mountbox")).args(["--backend", "notify", "--", "sh", "-c", "ls / > /dev/null; exit 42"]).status().unwrap();
+  assert_eq!(status.code(), Some(42));
+}
+
+/// Checks that mountbox stops along with the command it runs, then resumes it once continued.
+fn check_stop_and_continue(backend: &str) {
+  // Its own process group, for the stop not to be discarded as a shell would have it
+  let child = Command::new(env!("CARGO_BIN_EXE_mountbox")).args(["--backend", backend, "--", "sh", "-c", "kill -TSTP $$; echo resumed"])
+    .process_group(0).stdout(Stdio::piped()).spawn().unwrap();
+  let pid = Pid::from_raw(child.id() as i32);
+  let deadline = Instant::now() + Duration::from_secs(10);
+  loop {
+    match waitpid(pid, Some(WaitPidFlag::WUNTRACED | WaitPidFlag::WNOHANG)).unwrap() {
+      WaitStatus::Stopped(_, Signal::SIGTSTP) => break,
+      WaitStatus::StillAlive if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+      status => {
+        let _ = kill(pid, Signal::SIGKILL);
+        panic!("mountbox did not stop: {status:?}")
+      }
+    }
+  }
+  kill(pid, Signal::SIGCONT).unwrap();
+  let output = child.wait_with_output().unwrap();
+  assert_eq!(output.status.code(), Some(0));
+  assert_eq!(output.stdout, b"resumed\n");
+}
+
+#[test]
+fn cli_should_stop_along_with_command() {
+  check_stop_and_continue("ptrace");
+}
+
+#[test]
+fn cli_notify_backend_should_stop_along_with_command() {
+  check_stop_and_continue("notify");
+}
+
+#[test]
+fn cli_unloadable_plugin_should_fail_before_command() {
+  let dir = std::env::temp_dir().join(format!("mountbox-cli-plugin-{}", std::process::id()));
+  let status = Command::new(env!("CARGO_BIN_EXE_mountbox"))
+    .args(["--bind", "/mnt:/nonexistent/plugin.so", "--", "touch"]).arg(&dir).status().unwrap();
+  assert_eq!(status.code(), Some(1));
+  assert!(!dir.exists());
+}
+
+#[test]
+fn cli_malformed_bind_should_fail_before_command() {
+  let dir = std::env::temp_dir().join(format!("mountbox-cli-bind-{}", std::process::id()));
+  let status = Command::new(env!("CARGO_BIN_EXE_mountbox"))
+    .args(["--bind", "/mnt:/nonexistent/plugin.so:operations,archive", "--", "touch"]).arg(&dir).status().unwrap();
+  assert_eq!(status.code(), Some(2));
+  assert!(!dir.exists());
+}
+
+#[test]
+fn cli_missing_plugin_symbol_should_fail_before_command() {
+  let dir = std::env::temp_dir().join(format!("mountbox-cli-symbol-{}", std::process::id()));
+  let status = Command::new(env!("CARGO_BIN_EXE_mountbox"))
+    .args(["--bind", "/mnt:libc.so.6:mountbox_missing_plugin", "--", "touch"]).arg(&dir).status().unwrap();
+  assert_eq!(status.code(), Some(1));
+  assert!(!dir.exists());
+}
+
+#[test]
+fn cli_unreachable_daemon_should_fail_before_command() {
+  let dir = std::env::temp_dir().join(format!("mountbox-cli-connect-{}", std::process::id()));
+  let status = Command::new(env!("CARGO_BIN_EXE_mountbox"))
+    .args(["--connect", "/mnt:/nonexistent/plugin.sock", "--", "touch"]).arg(&dir).status().unwrap();
+  assert_eq!(status.code(), Some(1));
+  assert!(!dir.exists());
+}
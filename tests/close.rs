@@ -1,36 +1,68 @@
-use std::io::{Read, Write};
+use std::{ffi::CString, sync::atomic::{AtomicUsize, Ordering}};
 use common::raw;
 use mountbox::{syscall_nr, tracer};
-use nix::{fcntl::{fcntl, FcntlArg::F_GETFD}, libc};
-use typed_path::NativePathBuf;
+use nix::libc;
 
 mod common;
 
-create_plugin!(close_should_drop_fd_plugin, close: |path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
-  let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
-  assert_eq!(path, "/close");
-  return 0;
-});
+static CLOSED: AtomicUsize = AtomicUsize::new(0);
+
+create_plugin!(close_should_drop_fd_plugin,
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    return 0;
+  },
+  close: |path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/close");
+    CLOSED.fetch_add(1, Ordering::SeqCst);
+    return 0;
+  }
+);
 
 #[test]
 fn close_should_drop_fd() {
-  let (mut r, mut w) = std::io::pipe().unwrap();
   let child = run_child!(move || {
     unsafe {
-      let fd_buf = &mut [0u8; 8];
-      r.read(fd_buf).unwrap();
-      let fd = i64::from_ne_bytes(*fd_buf);
+      let path = CString::new("/test/close").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      assert!(libc::fcntl(fd as i32, libc::F_GETFD) != -1);
       let res = libc::syscall(syscall_nr!(close), fd);
       assert!(res == 0);
+      assert_eq!(libc::fcntl(fd as i32, libc::F_GETFD), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EBADF);
     };
   });
   let state = create_state!("/test", close_should_drop_fd_plugin);
-  let mount = state.mounts.get_mount(&NativePathBuf::from("/test")).unwrap();
-  let fd = mount.allocate_fd("/close", None).unwrap();
-  w.write(&fd.to_ne_bytes()).unwrap();
-  assert!(fcntl(fd as i32, F_GETFD).unwrap() != -1);
   let status = tracer::attach(state.clone(), child).unwrap();
   assert_eq!(status, tracer::TraceeStatus::Exited(0));
-  assert!(mount.get_fd_info(fd).is_none());
-  assert_eq!(fcntl(fd as i32, F_GETFD).err(), Some(nix::errno::Errno::EBADF));
-}
\ No newline at end of file
+  // Released once, and not again when the tracee exits
+  assert_eq!(CLOSED.load(Ordering::SeqCst), 1);
+}
+
+create_plugin!(close_should_drop_fd_on_failure_plugin,
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    return 0;
+  },
+  close: |_path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
+    return -libc::EIO;
+  }
+);
+
+#[test]
+fn close_should_drop_fd_on_failure() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/close").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      assert_eq!(libc::syscall(syscall_nr!(close), fd), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EIO);
+      assert_eq!(libc::fcntl(fd as i32, libc::F_GETFD), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EBADF);
+    };
+  });
+  let state = create_state!("/test", close_should_drop_fd_on_failure_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
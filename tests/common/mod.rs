@@ -3,33 +3,63 @@ pub mod raw {
   include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
 
+#[allow(dead_code)]
 pub static LIB: std::sync::LazyLock<dlopen::symbor::Library> = std::sync::LazyLock::new(|| {dlopen::symbor::Library::open_self().unwrap()});
 
 
 impl raw::mountbox_operations {
+  #[allow(dead_code)]
   pub const fn default() -> Self {
     Self {
       open: None,
       read: None,
       close: None,
-      getattr: None
+      getattr: None,
+      opendir: None,
+      readdir: None,
+      releasedir: None,
+      write: None,
+      create: None,
+      truncate: None,
+      mkdir: None,
+      rmdir: None,
+      unlink: None,
+      rename: None,
+      symlink: None,
+      link: None,
+      readlink: None,
+      access: None,
+      init: None,
+      destroy: None
     }
   }
 }
 
 #[macro_export]
 macro_rules! create_plugin {
-  ($name:tt $(, $($op:tt: |$($k:tt: $v:ty),*| -> $ret:ty {$($body:tt)*}),*)?) => { paste::paste! {
+  ($name:tt, caps: $caps:expr $(, $($op:tt: |$($k:tt: $v:ty),*| -> $ret:ty {$($body:tt)*}),*)?) => { paste::paste! {
 
     $($(unsafe extern "C" fn [<$name _$op>]($($k:$v),*) -> $ret {$($body)*})?)*
 
-    #[unsafe(no_mangle)]
     #[allow(non_upper_case_globals)]
-    pub static mut $name: raw::mountbox_operations = raw::mountbox_operations {
+    static [<$name _operations>]: raw::mountbox_operations = raw::mountbox_operations {
       $($($op: Some([<$name _$op>])),*,)?
       ..$crate::common::raw::mountbox_operations::default()
     };
-  } }
+
+    #[unsafe(no_mangle)]
+    #[allow(non_upper_case_globals)]
+    pub static mut $name: raw::mountbox_plugin_info = raw::mountbox_plugin_info {
+      abi_version: raw::MOUNTBOX_ABI_VERSION,
+      operations_size: std::mem::size_of::<raw::mountbox_operations>() as u32,
+      capabilities: $caps as u64,
+      operations: &[<$name _operations>]
+    };
+  } };
+
+  ($name:tt $(, $($op:tt: |$($k:tt: $v:ty),*| -> $ret:ty {$($body:tt)*}),*)?) => {
+    create_plugin!($name, caps: 0 $(, $($op: |$($k: $v),*| -> $ret {$($body)*}),*)?);
+  }
 }
 
 #[macro_export]
@@ -38,6 +68,7 @@ macro_rules! run_child {
     match unsafe { nix::unistd::fork().unwrap() } {
       nix::unistd::ForkResult::Child => {
         unsafe { nix::libc::raise(nix::libc::SIGSTOP); }
+        mountbox::tracer::install_filter().unwrap();
         if let Err(_) = std::panic::catch_unwind($syscall) {
           std::process::exit(101);
         } else {
@@ -52,11 +83,32 @@ macro_rules! run_child {
   }
 }
 
+#[macro_export]
+macro_rules! run_notified_child {
+  ($syscall:expr) => {
+    match unsafe { nix::unistd::fork().unwrap() } {
+      nix::unistd::ForkResult::Child => {
+        mountbox::tracer::notify::install_filter().unwrap();
+        unsafe { nix::libc::raise(nix::libc::SIGSTOP); }
+        if let Err(_) = std::panic::catch_unwind($syscall) {
+          std::process::exit(101);
+        } else {
+          std::process::exit(0);
+        }
+      }
+
+      nix::unistd::ForkResult::Parent { child } => {
+        child
+      }
+    }
+  }
+}
+
 #[macro_export]
 macro_rules! create_state {
   ($path:expr, $plugin:expr $(, {$($k:tt$(: $v:expr)?),*})?) => {
     std::sync::Arc::new(mountbox::state::State {
-      mounts: mountbox::mounts::Mounts::new(&[(typed_path::NativePathBuf::from($path), std::sync::Arc::new(mountbox::plugin::Plugin::load(&common::LIB, Some(stringify!($plugin)))))]),
+      mounts: mountbox::mounts::Mounts::new(&[(typed_path::NativePathBuf::from($path), std::sync::Arc::new(mountbox::plugin::Plugin::load(&common::LIB, Some(stringify!($plugin))).unwrap()))]),
       $($($k$(: $v)?),*, )?
       ..Default::default()
     })
@@ -0,0 +1,57 @@
+#![cfg(target_arch="x86_64")]
+use std::{arch::asm, sync::atomic::{AtomicBool, Ordering}};
+use common::raw;
+use mountbox::tracer;
+use nix::libc;
+
+mod common;
+
+static OPENED: AtomicBool = AtomicBool::new(false);
+
+create_plugin!(compat_plugin, open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+  OPENED.store(true, Ordering::Relaxed);
+  return 0;
+});
+
+/// Makes the i386 syscall `nr`, with `path` copied where 32-bit pointers reach.
+unsafe fn i386_syscall(nr: i32, path: &str, arg1: i32) -> i32 {
+  unsafe {
+    let low = libc::mmap(std::ptr::null_mut(), 4096, libc::PROT_READ | libc::PROT_WRITE,
+      libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_32BIT, -1, 0) as *mut u8;
+    assert!(low as usize != usize::MAX && (low as usize) < u32::MAX as usize);
+    std::ptr::copy_nonoverlapping(path.as_ptr(), low, path.len());
+    let ret: i32;
+    // rbx is reserved to LLVM
+    asm!("xchg {path}, rbx", "int 0x80", "xchg {path}, rbx", path = inout(reg) low as u64 => _,
+      inlateout("eax") nr => ret, in("ecx") arg1, in("edx") 0);
+    ret
+  }
+}
+
+fn i386_open_child() {
+  unsafe {
+    // i386 open, native fstat
+    assert_eq!(i386_syscall(5, "/test/file\0", libc::O_RDONLY), -libc::ENOSYS);
+    let fd = i386_syscall(5, "/dev/null\0", libc::O_RDONLY);
+    assert!(fd >= 0);
+    assert_eq!(libc::fcntl(fd, libc::F_GETFD), 0);
+  }
+}
+
+#[test]
+fn compat_syscall_on_mount_should_cause_enosys() {
+  let child = run_child!(i386_open_child);
+  let state = create_state!("/test", compat_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert!(!OPENED.load(Ordering::Relaxed));
+}
+
+#[test]
+fn compat_notified_syscall_on_mount_should_cause_enosys() {
+  let child = run_notified_child!(i386_open_child);
+  let state = create_state!("/test", compat_plugin);
+  let status = tracer::notify::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert!(!OPENED.load(Ordering::Relaxed));
+}
@@ -0,0 +1,128 @@
+use std::{ffi::CString, sync::atomic::{AtomicUsize, Ordering}};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+static RELEASED: AtomicUsize = AtomicUsize::new(0);
+static CLOEXEC_CLOSED: AtomicUsize = AtomicUsize::new(0);
+
+create_plugin!(dup_plugin,
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    // Only there once the close-on-exec file is closed
+    if unsafe { std::ffi::CStr::from_ptr(path) } == c"/closed" && CLOEXEC_CLOSED.load(Ordering::SeqCst) == 0 {
+      return -libc::ENOENT;
+    }
+    return 0;
+  },
+  read: |
+    _path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    let data = &b"0123456789"[(offset as usize).min(10)..];
+    let len = data.len().min(size as usize).min(2);
+    buf[..len].copy_from_slice(&data[..len]);
+    return len as i32;
+  },
+  close: |path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
+    match unsafe { std::ffi::CStr::from_ptr(path) }.to_bytes() {
+      b"/released" => RELEASED.fetch_add(1, Ordering::SeqCst),
+      b"/cloexec" => CLOEXEC_CLOSED.fetch_add(1, Ordering::SeqCst),
+      _ => 0
+    };
+    return 0;
+  }
+);
+
+/// Reads two bytes of `fd`.
+unsafe fn read2(fd: i64) -> Vec<u8> {
+  let mut buf = [0u8; 2];
+  let len = unsafe { libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), buf.len()) };
+  assert!(len >= 0);
+  buf[..len as usize].to_vec()
+}
+
+#[test]
+fn dup_should_share_open_file() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/file").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      assert_eq!(read2(fd), b"01");
+      let dup = libc::syscall(syscall_nr!(dup), fd);
+      assert!(dup > fd);
+      assert_eq!(read2(dup), b"23");
+      #[cfg(target_arch="x86_64")]
+      {
+        assert_eq!(libc::syscall(syscall_nr!(dup2), fd, 100), 100);
+        assert_eq!(read2(100), b"45");
+      }
+      assert_eq!(libc::syscall(syscall_nr!(dup3), fd, 101, libc::O_CLOEXEC), 101);
+      assert_eq!(libc::fcntl(101, libc::F_GETFD), libc::FD_CLOEXEC);
+      assert_eq!(libc::syscall(syscall_nr!(dup3), fd, 102, libc::O_RDONLY | libc::O_NONBLOCK), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EINVAL);
+      let above = libc::syscall(syscall_nr!(fcntl), fd, libc::F_DUPFD_CLOEXEC, 200);
+      assert!(above >= 200);
+      assert_eq!(libc::fcntl(above as i32, libc::F_GETFD), libc::FD_CLOEXEC);
+      // Closing the original leaves the file open through its duplicates
+      assert_eq!(libc::syscall(syscall_nr!(close), fd), 0);
+      assert_eq!(libc::syscall(syscall_nr!(lseek), above, 6, libc::SEEK_SET), 6);
+      assert_eq!(read2(101), b"67");
+      assert_eq!(libc::syscall(syscall_nr!(dup), 1000), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EBADF);
+    };
+  });
+  let state = create_state!("/test", dup_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn dup_over_placeholder_should_release_file() {
+  let child = run_child!(move || {
+    unsafe {
+      let mut pipe = [0; 2];
+      assert_eq!(libc::pipe(pipe.as_mut_ptr()), 0);
+      assert_eq!(libc::write(pipe[1], b"host".as_ptr() as *const libc::c_void, 4), 4);
+      let path = CString::new("/test/released").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      assert_eq!(libc::syscall(syscall_nr!(dup3), pipe[0], fd, 0), fd);
+      // The host pipe is read, and not the file it replaced
+      assert_eq!(read2(fd), b"ho");
+    };
+  });
+  let state = create_state!("/test", dup_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(RELEASED.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn execve_should_drop_cloexec_fds() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/cloexec").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      // Out of the way of the fds the new program opens, which would close it otherwise
+      assert_eq!(libc::syscall(syscall_nr!(dup3), fd, 50, libc::O_CLOEXEC), 50);
+      assert_eq!(libc::syscall(syscall_nr!(close), fd), 0);
+      let script = CString::new(": </test/closed").unwrap();
+      let sh = CString::new("/bin/sh").unwrap();
+      let arg0 = CString::new("sh").unwrap();
+      let arg1 = CString::new("-c").unwrap();
+      let argv = [arg0.as_ptr(), arg1.as_ptr(), script.as_ptr(), std::ptr::null()];
+      libc::execv(sh.as_ptr(), argv.as_ptr());
+    };
+  });
+  let state = create_state!("/test", dup_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
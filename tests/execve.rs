@@ -8,9 +8,10 @@ mod common;
 static PIPE: OnceLock<(i32, i32)> = OnceLock::new();
 
 create_plugin!(execve_noarg_noenv_should_succeed_plugin,
-  open: |path: *const std::os::raw::c_char| -> std::os::raw::c_int {
+  open: |path: *const std::os::raw::c_char, _flags: i32, fh: *mut u64| -> std::os::raw::c_int {
     let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
     assert_eq!(path, "/execve");
+    unsafe { *fh = 7 };
     return 0;
   },
   read: |
@@ -18,10 +19,11 @@ create_plugin!(execve_noarg_noenv_should_succeed_plugin,
     buf: *mut std::os::raw::c_char,
     size: u64,
     offset: i64,
-    _fh: u64
+    fh: u64
   | -> std::os::raw::c_int {
     let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
     assert_eq!(path, "/execve");
+    assert_eq!(fh, 7);
     let buf = unsafe { std::slice::from_raw_parts_mut(buf, size as usize) };
     let cmd = format!("#!/bin/sh\necho execve_success>&{}", PIPE.get().unwrap().1.as_raw_fd());
     let bin = cmd.as_bytes();
@@ -32,9 +34,10 @@ create_plugin!(execve_noarg_noenv_should_succeed_plugin,
       return 0
     }
   },
-  close: |path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
+  close: |path: *const std::os::raw::c_char, fh: u64| -> std::os::raw::c_int {
     let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
     assert_eq!(path, "/execve");
+    assert_eq!(fh, 7);
     return 0;
   }
 );
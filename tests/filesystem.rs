@@ -0,0 +1,234 @@
+use std::{collections::HashMap, ffi::CString, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}, time::{Duration, UNIX_EPOCH}};
+use mountbox::{mounts::Mounts, plugin::{self, filesystem::Result, Filesystem, Plugin, Stat}, state::State, syscall_nr, tracer};
+use nix::{errno::Errno, libc};
+use typed_path::NativePathBuf;
+
+mod common;
+
+/// Files held in memory, all in the root of the mount.
+#[derive(Default)]
+struct MemoryFs {
+  files: Mutex<HashMap<String, Vec<u8>>>
+}
+
+impl Filesystem for MemoryFs {
+  fn open(&self, path: &str, _flags: i32) -> Result<u64> {
+    self.files.lock().unwrap().contains_key(path).then_some(0).ok_or(Errno::ENOENT)
+  }
+
+  fn read(&self, path: &str, buf: &mut [u8], offset: i64, _fh: u64) -> Result<usize> {
+    let files = self.files.lock().unwrap();
+    let data = files.get(path).ok_or(Errno::ENOENT)?;
+    let data = &data[(offset as usize).min(data.len())..];
+    let len = data.len().min(buf.len());
+    buf[..len].copy_from_slice(&data[..len]);
+    Ok(len)
+  }
+
+  fn write(&self, path: &str, buf: &[u8], offset: i64, _fh: u64) -> Result<usize> {
+    let mut files = self.files.lock().unwrap();
+    let data = files.get_mut(path).ok_or(Errno::ENOENT)?;
+    let end = offset as usize + buf.len();
+    if data.len() < end {
+      data.resize(end, 0);
+    }
+    data[offset as usize..end].copy_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn getattr(&self, path: &str) -> Result<Stat> {
+    if path == "/" {
+      return Ok(Stat { kind: plugin::S_IFDIR, perm: Some(0o755), ..Default::default() });
+    }
+    let files = self.files.lock().unwrap();
+    let size = files.get(path).ok_or(Errno::ENOENT)?.len() as u64;
+    Ok(Stat { kind: plugin::S_IFREG, perm: Some(0o444), size: Some(size), ..Default::default() })
+  }
+
+  fn create(&self, path: &str, _mode: u32) -> Result<()> {
+    self.files.lock().unwrap().insert(path.to_string(), vec![]);
+    Ok(())
+  }
+}
+
+#[test]
+fn filesystem_should_be_mounted_in_process() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/mem/file").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDWR | libc::O_CREAT, 0o644);
+      assert!(fd >= 0);
+      assert_eq!(libc::syscall(syscall_nr!(write), fd, b"content".as_ptr(), 7), 7);
+      let mut buf = [0u8; 16];
+      assert_eq!(libc::syscall(syscall_nr!(pread64), fd, buf.as_mut_ptr(), buf.len(), 3), 4);
+      assert_eq!(&buf[..4], b"tent");
+      // No close to give the handle back to, nor mkdir
+      assert_eq!(libc::syscall(syscall_nr!(close), fd), 0);
+      let dir = CString::new("/mem/dir").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(mkdirat), libc::AT_FDCWD, dir.as_ptr(), 0o755), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::ENOSYS);
+      // Without access, the mode bits apply
+      assert_eq!(libc::syscall(syscall_nr!(faccessat), libc::AT_FDCWD, path.as_ptr(), libc::R_OK, 0), 0);
+    };
+  });
+  let fs = Arc::new(MemoryFs::default());
+  let state = Arc::new(State {
+    mounts: Mounts::new(&[(NativePathBuf::from("/mem"), fs.clone())]),
+    ..Default::default()
+  });
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(fs.files.lock().unwrap().get("/file").unwrap(), b"content");
+}
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// A single file whose content comes from the options of the mount.
+struct ConfiguredFs {
+  content: String
+}
+
+impl ConfiguredFs {
+  fn new(opts: &[&str]) -> Result<ConfiguredFs> {
+    let content = opts.iter().find_map(|opt| opt.strip_prefix("content=")).ok_or(Errno::EINVAL)?;
+    Ok(ConfiguredFs { content: content.to_string() })
+  }
+}
+
+impl Drop for ConfiguredFs {
+  fn drop(&mut self) {
+    DROPPED.fetch_add(1, Ordering::Relaxed);
+  }
+}
+
+impl Filesystem for ConfiguredFs {
+  fn open(&self, path: &str, _flags: i32) -> Result<u64> {
+    if path == "/file" { Ok(0) } else { Err(Errno::ENOENT) }
+  }
+
+  fn read(&self, _path: &str, buf: &mut [u8], offset: i64, _fh: u64) -> Result<usize> {
+    let data = &self.content.as_bytes()[(offset as usize).min(self.content.len())..];
+    let len = data.len().min(buf.len());
+    buf[..len].copy_from_slice(&data[..len]);
+    Ok(len)
+  }
+}
+
+mountbox::export_filesystem!(filesystem_configured_plugin, ConfiguredFs, ConfiguredFs::new);
+
+#[test]
+fn filesystem_should_be_exported_as_plugin() {
+  let child = run_child!(move || {
+    unsafe {
+      for (path, content) in [("/a/file", b"first"), ("/b/file", b"other")] {
+        let path = CString::new(path).unwrap();
+        let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+        assert!(fd >= 0);
+        let mut buf = [0u8; 16];
+        assert_eq!(libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), buf.len()), 5);
+        assert_eq!(&buf[..5], content);
+      }
+    };
+  });
+  let mut first = Plugin::load(&common::LIB, Some("filesystem_configured_plugin")).unwrap();
+  first.init(&["content=first"]).unwrap();
+  let mut other = Plugin::load(&common::LIB, Some("filesystem_configured_plugin")).unwrap();
+  other.init(&["content=other"]).unwrap();
+  let mut bad = Plugin::load(&common::LIB, Some("filesystem_configured_plugin")).unwrap();
+  assert!(bad.init(&[]).is_err());
+  let state = Arc::new(State {
+    mounts: Mounts::new(&[(NativePathBuf::from("/a"), Arc::new(first)), (NativePathBuf::from("/b"), Arc::new(other))]),
+    ..Default::default()
+  });
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  drop(state);
+  assert_eq!(DROPPED.load(Ordering::Relaxed), 2);
+}
+
+/// A single nested file, with no getattr to tell the directory above it.
+struct NestedFs;
+
+impl Filesystem for NestedFs {
+  fn open(&self, path: &str, _flags: i32) -> Result<u64> {
+    match path {
+      "/dir/file" => Ok(0),
+      // Not a real errno, which must not pass for success
+      "/dir/broken" => Err(Errno::UnknownErrno),
+      _ => Err(Errno::ENOENT)
+    }
+  }
+
+  fn read(&self, _path: &str, buf: &mut [u8], offset: i64, _fh: u64) -> Result<usize> {
+    let data = &b"nested"[(offset as usize).min(6)..];
+    let len = data.len().min(buf.len());
+    buf[..len].copy_from_slice(&data[..len]);
+    Ok(len)
+  }
+}
+
+#[test]
+fn filesystem_without_getattr_should_serve_nested_paths() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/nested/dir/file").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let mut buf = [0u8; 16];
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), buf.len()), 6);
+      assert_eq!(&buf[..6], b"nested");
+      let broken = CString::new("/nested/dir/broken").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, broken.as_ptr(), libc::O_RDONLY), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EIO);
+    };
+  });
+  let state = Arc::new(State {
+    mounts: Mounts::new(&[(NativePathBuf::from("/nested"), Arc::new(NestedFs))]),
+    ..Default::default()
+  });
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+/// A single file with attributes of its own.
+struct AttrFs;
+
+impl Filesystem for AttrFs {
+  fn getattr(&self, path: &str) -> Result<Stat> {
+    match path {
+      "/file" => Ok(Stat {
+        kind: plugin::S_IFREG,
+        perm: Some(0o640),
+        size: Some(42),
+        uid: Some(1234),
+        mtime: Some(UNIX_EPOCH + Duration::new(1, 500)),
+        // Before the epoch
+        ctime: Some(UNIX_EPOCH - Duration::new(1, 500)),
+        ..Default::default()
+      }),
+      _ => Err(Errno::ENOENT)
+    }
+  }
+}
+
+#[test]
+fn filesystem_stat_should_be_reported() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/attr/file").unwrap();
+      let mut stat: libc::stat = std::mem::zeroed();
+      assert_eq!(libc::syscall(syscall_nr!(newfstatat), libc::AT_FDCWD, path.as_ptr(), &mut stat, 0), 0);
+      assert_eq!(stat.st_mode, libc::S_IFREG | 0o640);
+      assert_eq!(stat.st_size, 42);
+      assert_eq!(stat.st_uid, 1234);
+      assert_eq!((stat.st_mtime, stat.st_mtime_nsec), (1, 500));
+      assert_eq!((stat.st_ctime, stat.st_ctime_nsec), (-2, 999_999_500));
+    };
+  });
+  let state = Arc::new(State {
+    mounts: Mounts::new(&[(NativePathBuf::from("/attr"), Arc::new(AttrFs))]),
+    ..Default::default()
+  });
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
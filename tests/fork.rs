@@ -0,0 +1,114 @@
+use std::{ffi::{CStr, CString}, sync::atomic::{AtomicUsize, Ordering}};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+use typed_path::NativePathBuf;
+
+mod common;
+
+/// Blocks a freshly forked child until the tracer has caught up with it.
+fn wait_traced() {
+  while std::fs::read_to_string("/proc/self/status").unwrap().lines()
+    .any(|line| line.starts_with("TracerPid:") && line.split_whitespace().nth(1) == Some("0")) {
+    std::thread::yield_now();
+  }
+}
+
+fn read(fd: i64) -> [u8; 2] {
+  let buf = [0u8; 2];
+  assert_eq!(unsafe { libc::syscall(syscall_nr!(read), fd, &buf, buf.len()) }, 2);
+  buf
+}
+
+fn getcwd() -> String {
+  let buf = [0u8; 32];
+  assert_ne!(unsafe { libc::syscall(syscall_nr!(getcwd), &buf, buf.len()) }, -1);
+  CStr::from_bytes_until_nul(&buf).unwrap().to_str().unwrap().to_string()
+}
+
+static CLOSED: AtomicUsize = AtomicUsize::new(0);
+
+create_plugin!(fork_should_share_open_files_plugin,
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    return 0;
+  },
+  read: |
+    _path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    let data = &b"0123456789"[(offset as usize).min(10)..];
+    let len = data.len().min(size as usize);
+    buf[..len].copy_from_slice(&data[..len]);
+    return len as i32;
+  },
+  close: |_path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
+    CLOSED.fetch_add(1, Ordering::SeqCst);
+    return 0;
+  }
+);
+
+#[test]
+fn fork_should_share_open_files() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/file").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      assert_eq!(&read(fd), b"01");
+      match libc::fork() {
+        0 => {
+          wait_traced();
+          assert_eq!(&read(fd), b"23");
+          // The parent still holds the file
+          assert_eq!(libc::syscall(syscall_nr!(close), fd), 0);
+          libc::_exit(0);
+        },
+        pid => {
+          let mut status = 0;
+          assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+          assert_eq!(status, 0);
+          assert_eq!(&read(fd), b"45");
+          assert_eq!(libc::syscall(syscall_nr!(close), fd), 0);
+        }
+      }
+    };
+  });
+  let state = create_state!("/test", fork_should_share_open_files_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(CLOSED.load(Ordering::SeqCst), 1);
+}
+
+create_plugin!(fork_should_copy_cwd_plugin);
+
+#[test]
+fn fork_should_copy_cwd() {
+  let child = run_child!(move || {
+    unsafe {
+      match libc::fork() {
+        0 => {
+          wait_traced();
+          let path = CString::new("/test/dir").unwrap();
+          assert_eq!(libc::syscall(syscall_nr!(chdir), path.as_ptr()), 0);
+          assert_eq!(getcwd(), "/test/dir");
+          libc::_exit(0);
+        },
+        pid => {
+          let mut status = 0;
+          assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+          assert_eq!(status, 0);
+          assert_eq!(getcwd(), "/");
+        }
+      }
+    };
+  });
+  let state = create_state!("/test", fork_should_copy_cwd_plugin, {
+    cwd: std::sync::RwLock::new(NativePathBuf::from("/"))
+  });
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
@@ -0,0 +1,223 @@
+use std::ffi::{CStr, CString};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+const ENTRIES: [(&str, u16); 5] = [(".", raw::S_IFDIR), ("..", raw::S_IFDIR), ("a", raw::S_IFREG), ("b", raw::S_IFLNK), ("c", 0)];
+
+create_plugin!(getdents64_should_list_entries_plugin,
+  opendir: |path: *const std::os::raw::c_char, _fh: *mut u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/dir");
+    return 0;
+  },
+  readdir: |
+    path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_void,
+    filler: raw::mountbox_fill_dir_t,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/dir");
+    for (name, mode) in &ENTRIES[offset as usize..] {
+      let cname = CString::new(*name).unwrap();
+      let mut stat: raw::stat = unsafe { std::mem::zeroed() };
+      stat.mode = *mode;
+      let stat_ptr = if *mode == 0 { std::ptr::null() } else { &stat as *const raw::stat };
+      if unsafe { filler.unwrap()(buf, cname.as_ptr(), stat_ptr) } != 0 {
+        break;
+      }
+    }
+    return 0;
+  }
+);
+
+fn parse_dirents64(buf: &[u8]) -> Vec<(String, u8)> {
+  let mut entries = vec![];
+  let mut pos = 0;
+  while pos < buf.len() {
+    let reclen = u16::from_ne_bytes(buf[pos+16..pos+18].try_into().unwrap()) as usize;
+    let d_type = buf[pos+18];
+    let name = CStr::from_bytes_until_nul(&buf[pos+19..pos+reclen]).unwrap().to_str().unwrap().to_string();
+    entries.push((name, d_type));
+    pos += reclen;
+  }
+  entries
+}
+
+#[test]
+fn getdents64_should_list_entries() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/dir").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY);
+      assert!(fd > 0);
+      // Room for two entries at a time
+      let buf = [0u8; 56];
+      let mut entries = vec![];
+      loop {
+        let len = libc::syscall(syscall_nr!(getdents64), fd, &buf, buf.len());
+        assert!(len >= 0);
+        if len == 0 {
+          break;
+        }
+        entries.extend(parse_dirents64(&buf[..len as usize]));
+      }
+      assert_eq!(entries, vec![
+        (".".to_string(), libc::DT_DIR),
+        ("..".to_string(), libc::DT_DIR),
+        ("a".to_string(), libc::DT_REG),
+        ("b".to_string(), libc::DT_LNK),
+        ("c".to_string(), libc::DT_UNKNOWN)
+      ]);
+    };
+  });
+  let state = create_state!("/test", getdents64_should_list_entries_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn getdents64_buffer_too_small_should_cause_einval() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/dir").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY);
+      assert!(fd > 0);
+      let buf = [0u8; 8];
+      let res = libc::syscall(syscall_nr!(getdents64), fd, &buf, buf.len());
+      assert_eq!(res, -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EINVAL);
+    };
+  });
+  let state = create_state!("/test", getdents64_should_list_entries_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+create_plugin!(getdents64_ino_should_match_stat_plugin,
+  opendir: |_path: *const std::os::raw::c_char, _fh: *mut u64| -> std::os::raw::c_int {
+    return 0;
+  },
+  readdir: |
+    _path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_void,
+    filler: raw::mountbox_fill_dir_t,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    for name in &["a", "b"][offset as usize..] {
+      let cname = CString::new(*name).unwrap();
+      let mut stat: raw::stat = unsafe { std::mem::zeroed() };
+      stat.mask = raw::MOUNTBOX_STAT_TYPE | raw::MOUNTBOX_STAT_INO;
+      stat.mode = raw::S_IFREG;
+      stat.ino = 42;
+      // Only a reports its inode number
+      let stat_ptr = if *name == "a" { &stat as *const raw::stat } else { std::ptr::null() };
+      if unsafe { filler.unwrap()(buf, cname.as_ptr(), stat_ptr) } != 0 {
+        break;
+      }
+    }
+    return 0;
+  },
+  getattr: |path: *const std::os::raw::c_char, stat: *mut raw::stat| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let stat = unsafe { stat.as_mut().unwrap() };
+    match path {
+      "/dir" => stat.mode = raw::S_IFDIR,
+      "/dir/a" => {
+        stat.mask = raw::MOUNTBOX_STAT_TYPE | raw::MOUNTBOX_STAT_INO;
+        stat.mode = raw::S_IFREG;
+        stat.ino = 42;
+      },
+      "/dir/b" => stat.mode = raw::S_IFREG,
+      _ => return -(raw::ENOENT as i32)
+    }
+    return 0;
+  }
+);
+
+#[test]
+fn getdents64_ino_should_match_stat() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/dir").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY);
+      assert!(fd > 0);
+      let buf = [0u8; 256];
+      let len = libc::syscall(syscall_nr!(getdents64), fd, &buf, buf.len());
+      assert!(len > 0);
+      let mut pos = 0;
+      let mut inos = vec![];
+      while pos < len as usize {
+        let name = CStr::from_bytes_until_nul(&buf[pos+19..]).unwrap().to_str().unwrap();
+        let entry = CString::new(format!("/test/dir/{name}")).unwrap();
+        let mut stat: libc::stat = std::mem::zeroed();
+        assert_eq!(libc::syscall(syscall_nr!(newfstatat), libc::AT_FDCWD, entry.as_ptr(), &mut stat, 0), 0);
+        assert_eq!(u64::from_ne_bytes(buf[pos..pos+8].try_into().unwrap()), stat.st_ino);
+        inos.push(stat.st_ino);
+        pos += u16::from_ne_bytes(buf[pos+16..pos+18].try_into().unwrap()) as usize;
+      }
+      assert_eq!(inos.len(), 2);
+      assert_eq!(inos[0], 42);
+    };
+  });
+  let state = create_state!("/test", getdents64_ino_should_match_stat_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+static RELEASED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+create_plugin!(getdents64_without_o_directory_plugin,
+  getattr: |_path: *const std::os::raw::c_char, stat: *mut raw::stat| -> std::os::raw::c_int {
+    unsafe { stat.as_mut().unwrap() }.mode = raw::S_IFDIR;
+    return 0;
+  },
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    panic!("directory opened as a file");
+  },
+  opendir: |_path: *const std::os::raw::c_char, _fh: *mut u64| -> std::os::raw::c_int {
+    return 0;
+  },
+  readdir: |
+    _path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_void,
+    filler: raw::mountbox_fill_dir_t,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    if offset == 0 {
+      let name = CString::new("a").unwrap();
+      unsafe { filler.unwrap()(buf, name.as_ptr(), std::ptr::null()) };
+    }
+    return 0;
+  },
+  releasedir: |_path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
+    RELEASED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    return 0;
+  }
+);
+
+#[test]
+fn getdents64_without_o_directory_should_list_entries() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/dir").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd > 0);
+      let buf = [0u8; 256];
+      let len = libc::syscall(syscall_nr!(getdents64), fd, &buf, buf.len());
+      assert!(len > 0);
+      assert_eq!(parse_dirents64(&buf[..len as usize]), vec![("a".to_string(), libc::DT_UNKNOWN)]);
+      assert_eq!(libc::syscall(syscall_nr!(close), fd), 0);
+    };
+  });
+  let state = create_state!("/test", getdents64_without_o_directory_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(RELEASED.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
@@ -0,0 +1,117 @@
+use std::{ffi::{CStr, CString}, path::PathBuf, sync::{atomic::{AtomicUsize, Ordering}, Arc}};
+use common::raw;
+use dlopen::symbor::Library;
+use mountbox::{mounts::Mounts, plugin::{Libraries, Plugin, PluginError}, state::State, syscall_nr, tracer};
+use nix::libc;
+use typed_path::NativePathBuf;
+
+mod common;
+
+/// Path of the shared library `name` the test is linked with.
+fn linked_library(name: &str) -> PathBuf {
+  std::fs::read_to_string("/proc/self/maps").unwrap().lines()
+    .filter_map(|line| line.split_whitespace().nth(5))
+    .find(|path| path.contains(name))
+    .map(PathBuf::from)
+    .unwrap()
+}
+
+#[test]
+fn libraries_should_load_each_library_once() {
+  let libc = linked_library("libc.so");
+  let link = std::env::temp_dir().join(format!("mountbox-libraries-{}.so", std::process::id()));
+  std::os::unix::fs::symlink(&libc, &link).unwrap();
+  let libraries = Libraries::default();
+  let by_path = libraries.open(&libc).unwrap();
+  let by_link = libraries.open(&link).unwrap();
+  let other = libraries.open(&linked_library("libgcc_s.so")).unwrap();
+  std::fs::remove_file(&link).unwrap();
+  assert!(Arc::ptr_eq(&by_path, &by_link));
+  assert!(!Arc::ptr_eq(&by_path, &other));
+  assert!(libraries.open(&PathBuf::from("/nonexistent/plugin.so")).is_err());
+}
+
+create_plugin!(libraries_src_plugin, open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+  return 0;
+});
+
+create_plugin!(libraries_out_plugin, open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+  return -(raw::EACCES as i32);
+});
+
+#[test]
+fn libraries_plugins_should_serve_their_own_mounts() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/src/file").unwrap();
+      assert!(libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY) >= 0);
+      let path = CString::new("/tmp/out/file").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EACCES);
+    };
+  });
+  let library = Arc::new(Library::open_self().unwrap());
+  let state = Arc::new(State {
+    mounts: Mounts::new(&[
+      (NativePathBuf::from("/src"), Arc::new(Plugin::load_shared(library.clone(), Some("libraries_src_plugin")).unwrap())),
+      (NativePathBuf::from("/tmp/out"), Arc::new(Plugin::load_shared(library, Some("libraries_out_plugin")).unwrap()))
+    ]),
+    ..Default::default()
+  });
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+static DESTROYED: AtomicUsize = AtomicUsize::new(0);
+
+create_plugin!(libraries_opts_plugin,
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    return -unsafe { *(raw::mountbox_private_data() as *const i32) };
+  },
+  init: |opts: *const *const std::os::raw::c_char, private_data: *mut *mut std::os::raw::c_void| -> std::os::raw::c_int {
+    let mut errno = 0;
+    let mut opt = opts;
+    while !unsafe { *opt }.is_null() {
+      match unsafe { CStr::from_ptr(*opt) }.to_str().unwrap().split_once('=') {
+        Some(("errno", value)) => errno = value.parse().unwrap(),
+        _ => return -(raw::EINVAL as i32)
+      }
+      opt = unsafe { opt.add(1) };
+    }
+    unsafe { *private_data = Box::into_raw(Box::new(errno)) as *mut std::os::raw::c_void };
+    return 0;
+  },
+  destroy: |private_data: *mut std::os::raw::c_void| -> () {
+    drop(unsafe { Box::from_raw(private_data as *mut i32) });
+    DESTROYED.fetch_add(1, Ordering::Relaxed);
+  }
+);
+
+#[test]
+fn libraries_plugin_should_be_set_up_per_mount() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/src/file").unwrap();
+      assert!(libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY) >= 0);
+      let path = CString::new("/tmp/out/file").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EACCES);
+    };
+  });
+  let library = Arc::new(Library::open_self().unwrap());
+  let mut src = Plugin::load_shared(library.clone(), Some("libraries_opts_plugin")).unwrap();
+  src.init(&["errno=0"]).unwrap();
+  let mut out = Plugin::load_shared(library.clone(), Some("libraries_opts_plugin")).unwrap();
+  out.init(&[&format!("errno={}", libc::EACCES)]).unwrap();
+  let mut bad = Plugin::load_shared(library, Some("libraries_opts_plugin")).unwrap();
+  assert_eq!(bad.init(&["archive"]), Err(PluginError::Errno(nix::errno::Errno::EINVAL)));
+  drop(bad);
+  let state = Arc::new(State {
+    mounts: Mounts::new(&[(NativePathBuf::from("/src"), Arc::new(src)), (NativePathBuf::from("/tmp/out"), Arc::new(out))]),
+    ..Default::default()
+  });
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  drop(state);
+  assert_eq!(DESTROYED.load(Ordering::Relaxed), 2);
+}
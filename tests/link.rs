@@ -0,0 +1,40 @@
+#![cfg(target_arch="x86_64")]
+use std::{ffi::CString, sync::Mutex};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+static CALLS: Mutex<Vec<(String, String)>> = Mutex::new(vec![]);
+
+create_plugin!(link_plugin, link: |
+  from: *const std::os::raw::c_char,
+  to: *const std::os::raw::c_char| -> std::os::raw::c_int {
+    let from = unsafe { std::ffi::CStr::from_ptr(from).to_str().unwrap() };
+    let to = unsafe { std::ffi::CStr::from_ptr(to).to_str().unwrap() };
+    CALLS.lock().unwrap().push((from.to_string(), to.to_string()));
+    return 0;
+});
+
+#[test]
+fn link_within_mount_should_succeed() {
+  let child = run_child!(move || {
+    unsafe {
+      let from = CString::new("/test/a").unwrap();
+      let to = CString::new("/test/b").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(link), from.as_ptr(), to.as_ptr()), 0);
+      assert_eq!(libc::syscall(syscall_nr!(linkat), libc::AT_FDCWD, from.as_ptr(), libc::AT_FDCWD, to.as_ptr(), 0), 0);
+      let to = CString::new("/tmp/b").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(link), from.as_ptr(), to.as_ptr()), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EXDEV);
+    };
+  });
+  let state = create_state!("/test", link_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(*CALLS.lock().unwrap(), vec![
+    ("/a".to_string(), "/b".to_string()),
+    ("/a".to_string(), "/b".to_string())
+  ]);
+}
@@ -0,0 +1,61 @@
+use std::ffi::CString;
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+create_plugin!(lseek_plugin,
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    return 0;
+  },
+  read: |
+    path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    assert_eq!(path, "/lseek");
+    let data = &b"0123456789"[(offset as usize).min(10)..];
+    let len = data.len().min(size as usize);
+    buf[..len].copy_from_slice(&data[..len]);
+    return len as i32;
+  },
+  getattr: |path: *const std::os::raw::c_char, stat: *mut raw::stat| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/lseek");
+    let stat = unsafe { stat.as_mut().unwrap() };
+    stat.mode = raw::S_IFREG;
+    stat.size = 10;
+    return 0;
+  }
+);
+
+#[test]
+fn lseek_should_move_offset() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/lseek").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let buf = [0u8; 2];
+      assert_eq!(libc::syscall(syscall_nr!(lseek), fd, 4, libc::SEEK_SET), 4);
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, &buf, buf.len()), 2);
+      assert_eq!(&buf, b"45");
+      assert_eq!(libc::syscall(syscall_nr!(lseek), fd, -1i64, libc::SEEK_CUR), 5);
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, &buf, buf.len()), 2);
+      assert_eq!(&buf, b"56");
+      assert_eq!(libc::syscall(syscall_nr!(lseek), fd, -2i64, libc::SEEK_END), 8);
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, &buf, buf.len()), 2);
+      assert_eq!(&buf, b"89");
+      assert_eq!(libc::syscall(syscall_nr!(lseek), fd, -11i64, libc::SEEK_END), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EINVAL);
+    };
+  });
+  let state = create_state!("/test", lseek_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
@@ -1,3 +1,4 @@
+#![cfg(target_arch="x86_64")]
 use std::{ffi::CString, mem::MaybeUninit};
 use common::raw;
 use mountbox::{syscall_nr, tracer};
@@ -13,9 +14,9 @@ create_plugin!(lstat_should_return_stat_plugin, getattr: |
     let stat = unsafe { stat.as_mut().unwrap() };
     stat.mode = raw::S_IFREG;
     stat.size = 10;
-    stat.atime = -10;
-    stat.mtime = -10;
-    stat.ctime = -10;
+    stat.atime.sec = -10;
+    stat.mtime.sec = -10;
+    stat.ctime.sec = -10;
     return 0;
 });
 
@@ -0,0 +1,37 @@
+#![cfg(target_arch="x86_64")]
+use std::{ffi::CString, sync::Mutex};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+static CALLS: Mutex<Vec<(String, u32)>> = Mutex::new(vec![]);
+
+create_plugin!(mkdir_should_create_dir_plugin, mkdir: |path: *const std::os::raw::c_char, mode: u32| -> std::os::raw::c_int {
+  let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+  CALLS.lock().unwrap().push((path.to_string(), mode));
+  return 0;
+});
+
+#[test]
+fn mkdir_should_create_dir() {
+  let child = run_child!(move || {
+    unsafe {
+      libc::umask(0o022);
+      let path = CString::new("/test/mkdir").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(mkdir), path.as_ptr(), 0o777), 0);
+      let path = CString::new("mkdirat").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(mkdirat), libc::AT_FDCWD, path.as_ptr(), 0o750), 0);
+      let path = CString::new("/test").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(mkdir), path.as_ptr(), 0o777), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EEXIST);
+    };
+  });
+  let state = create_state!("/test", mkdir_should_create_dir_plugin, {
+    cwd: std::sync::RwLock::new(typed_path::NativePathBuf::from("/test"))
+  });
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(*CALLS.lock().unwrap(), vec![("/mkdir".to_string(), 0o755), ("/mkdirat".to_string(), 0o750)]);
+}
@@ -0,0 +1,46 @@
+use std::{ffi::CString, mem::MaybeUninit};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+create_plugin!(newfstatat_plugin,
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    return 0;
+  },
+  getattr: |
+    path: *const std::os::raw::c_char,
+    stat: *mut raw::stat
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/newfstatat");
+    let stat = unsafe { stat.as_mut().unwrap() };
+    stat.mode = raw::S_IFREG | 0o640;
+    stat.size = 10;
+    return 0;
+  }
+);
+
+#[test]
+fn newfstatat_should_return_stat() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/newfstatat").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let cstat = MaybeUninit::<libc::stat>::zeroed().assume_init();
+      assert_eq!(libc::syscall(syscall_nr!(newfstatat), libc::AT_FDCWD, path.as_ptr(), &cstat, 0), 0);
+      assert_eq!(cstat.st_mode, libc::S_IFREG | 0o640);
+      assert_eq!(cstat.st_size, 10);
+      let cstat = MaybeUninit::<libc::stat>::zeroed().assume_init();
+      let path = CString::new("").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(newfstatat), fd, path.as_ptr(), &cstat, libc::AT_EMPTY_PATH), 0);
+      assert_eq!(cstat.st_mode, libc::S_IFREG | 0o640);
+      assert_eq!(cstat.st_size, 10);
+    };
+  });
+  let state = create_state!("/test", newfstatat_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
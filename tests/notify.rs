@@ -0,0 +1,166 @@
+use std::{ffi::CString, sync::atomic::{AtomicUsize, Ordering}};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::{libc, sys::{ptrace, signal::Signal, wait::{waitpid, WaitStatus}}, unistd::{fork, ForkResult}};
+
+mod common;
+
+create_plugin!(notify_plugin,
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/file");
+    return 0;
+  },
+  read: |
+    _path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    let data = &b"0123456789"[(offset as usize).min(10)..];
+    let len = data.len().min(size as usize);
+    buf[..len].copy_from_slice(&data[..len]);
+    return len as i32;
+  }
+);
+
+static CLOEXEC_CLOSED: AtomicUsize = AtomicUsize::new(0);
+
+create_plugin!(notify_exec_plugin,
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    // Only there once the close-on-exec file is closed
+    if unsafe { std::ffi::CStr::from_ptr(path) } == c"/closed" && CLOEXEC_CLOSED.load(Ordering::SeqCst) == 0 {
+      return -libc::ENOENT;
+    }
+    return 0;
+  },
+  close: |_path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
+    CLOEXEC_CLOSED.fetch_add(1, Ordering::SeqCst);
+    return 0;
+  }
+);
+
+/// Reads `fd` from its offset on.
+unsafe fn read_all(fd: i64) -> Vec<u8> {
+  let mut buf = [0u8; 4];
+  let mut data = vec![];
+  loop {
+    let len = unsafe { libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), buf.len()) };
+    assert!(len >= 0);
+    if len == 0 {
+      return data;
+    }
+    data.extend(&buf[..len as usize]);
+  }
+}
+
+#[test]
+fn notify_should_serve_mounts() {
+  let child = run_notified_child!(move || {
+    unsafe {
+      let path = CString::new("/test/file").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC);
+      assert!(fd >= 0);
+      assert_eq!(libc::fcntl(fd as i32, libc::F_GETFD), libc::FD_CLOEXEC);
+      assert_eq!(read_all(fd), b"0123456789");
+      assert_eq!(libc::syscall(syscall_nr!(close), fd), 0);
+      assert_eq!(libc::fcntl(fd as i32, libc::F_GETFD), -1);
+    };
+  });
+  let state = create_state!("/test", notify_plugin);
+  let status = tracer::notify::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn notify_child_should_inherit_fds() {
+  let child = run_notified_child!(move || {
+    unsafe {
+      let path = CString::new("/test/file").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let mut buf = [0u8; 2];
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), buf.len()), 2);
+      match fork().unwrap() {
+        ForkResult::Child => libc::_exit(if read_all(fd) == b"23456789" { 0 } else { 1 }),
+        ForkResult::Parent { child } => assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 0))
+      }
+    };
+  });
+  let state = create_state!("/test", notify_plugin);
+  let status = tracer::notify::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn notify_tracee_should_be_traceable() {
+  let child = run_notified_child!(move || {
+    unsafe {
+      match fork().unwrap() {
+        ForkResult::Child => {
+          ptrace::traceme().unwrap();
+          libc::raise(libc::SIGSTOP);
+          let path = CString::new("/test/file").unwrap();
+          let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+          libc::_exit(if fd >= 0 && read_all(fd) == b"0123456789" { 0 } else { 1 })
+        },
+        ForkResult::Parent { child } => {
+          assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Stopped(child, Signal::SIGSTOP));
+          ptrace::cont(child, None).unwrap();
+          assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 0));
+        }
+      }
+    };
+  });
+  let state = create_state!("/test", notify_plugin);
+  let status = tracer::notify::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn notify_should_follow_dup() {
+  let child = run_notified_child!(move || {
+    unsafe {
+      let path = CString::new("/test/file").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let above = libc::syscall(syscall_nr!(fcntl), fd, libc::F_DUPFD_CLOEXEC, 200);
+      assert!(above >= 200);
+      assert_eq!(libc::fcntl(above as i32, libc::F_GETFD), libc::FD_CLOEXEC);
+      assert_eq!(libc::syscall(syscall_nr!(dup3), fd, 100, 0), 100);
+      assert_eq!(libc::fcntl(100, libc::F_GETFD), 0);
+      assert_eq!(libc::syscall(syscall_nr!(close), fd), 0);
+      assert_eq!(read_all(above), b"0123456789");
+      assert_eq!(libc::syscall(syscall_nr!(dup), 1000), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EBADF);
+    };
+  });
+  let state = create_state!("/test", notify_plugin);
+  let status = tracer::notify::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn notify_execve_should_drop_cloexec_fds() {
+  let child = run_notified_child!(move || {
+    unsafe {
+      let path = CString::new("/test/cloexec").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      // Out of the way of the fds the new program opens, which would close it otherwise
+      assert_eq!(libc::syscall(syscall_nr!(dup3), fd, 50, libc::O_CLOEXEC), 50);
+      assert_eq!(libc::syscall(syscall_nr!(close), fd), 0);
+      let script = CString::new(": </test/closed").unwrap();
+      let sh = CString::new("/bin/sh").unwrap();
+      let arg0 = CString::new("sh").unwrap();
+      let arg1 = CString::new("-c").unwrap();
+      let argv = [arg0.as_ptr(), arg1.as_ptr(), script.as_ptr(), std::ptr::null()];
+      libc::execv(sh.as_ptr(), argv.as_ptr());
+    };
+  });
+  let state = create_state!("/test", notify_exec_plugin);
+  let status = tracer::notify::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
@@ -1,37 +1,109 @@
-use std::{ffi::CString, io::{Read, Write}};
+#![cfg(target_arch="x86_64")]
+use std::{ffi::CString, sync::Mutex};
 use common::raw;
 use mountbox::{syscall_nr, tracer};
-use nix::{fcntl::{fcntl, FcntlArg::F_GETFD}, libc};
-use typed_path::NativePathBuf;
+use nix::libc;
 
 mod common;
 
-create_plugin!(open_should_allocate_fd_plugin, open: |path: *const std::os::raw::c_char| -> std::os::raw::c_int {
-  let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
-  assert_eq!(path, "/open");
-  return 0;
-});
+static OPEN_CLOSED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+create_plugin!(open_should_allocate_fd_plugin,
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/open");
+    return 0;
+  },
+  // Files left open are released when the tracee exits
+  close: |path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    OPEN_CLOSED.lock().unwrap().push(path.to_string());
+    return 0;
+  }
+);
 
 #[test]
 fn open_should_allocate_fd() {
-  let (mut r, mut w) = std::io::pipe().unwrap();
   let child = run_child!(move || {
     unsafe {
       let path = CString::new("/test/open").unwrap();
       let open_fd = libc::syscall(syscall_nr!(open), path.as_ptr());
       assert!(open_fd > 0);
-      w.write(&open_fd.to_ne_bytes()).unwrap();
+      assert!(libc::fcntl(open_fd as i32, libc::F_GETFD) != -1);
     };
   });
   let state = create_state!("/test", open_should_allocate_fd_plugin);
   let status = tracer::attach(state.clone(), child).unwrap();
   assert_eq!(status, tracer::TraceeStatus::Exited(0));
-  let mount = state.mounts.get_mount(&NativePathBuf::from("/test")).unwrap();
-  let buf = &mut [0u8; 8];
-  r.read(buf).unwrap();
-  let fd = i64::from_ne_bytes(*buf);
-  let fd_info = mount.get_fd_info(fd as u16);
-  assert!(fd_info.is_some());
-  assert_eq!(fd_info.unwrap().path, "/open");
-  assert!(fcntl(fd as i32, F_GETFD).unwrap() != -1);
-}
\ No newline at end of file
+  assert_eq!(*OPEN_CLOSED.lock().unwrap(), vec!["/open"]);
+}
+
+create_plugin!(open_should_take_lowest_free_fd_plugin, open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+  return 0;
+});
+
+#[test]
+fn open_should_take_lowest_free_fd() {
+  let child = run_child!(move || {
+    unsafe {
+      // Leave a hole below fds the tracee already owns
+      let hole = libc::dup(0);
+      let above = libc::dup(0);
+      assert!(hole >= 0 && above > hole);
+      libc::close(hole);
+      let path = CString::new("/test/lowest").unwrap();
+      let fd = libc::syscall(syscall_nr!(open), path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC);
+      assert_eq!(fd, hole as i64);
+      assert_eq!(libc::fcntl(fd as i32, libc::F_GETFD), libc::FD_CLOEXEC);
+      let link = std::fs::read_link(format!("/proc/self/fd/{fd}")).unwrap();
+      assert!(link.to_str().unwrap().starts_with("/memfd:mountbox:/test/lowest"));
+      assert_eq!(libc::fcntl(above, libc::F_GETFD), 0);
+    };
+  });
+  let state = create_state!("/test", open_should_take_lowest_free_fd_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+static HANDLES: Mutex<Vec<(&str, u64)>> = Mutex::new(vec![]);
+
+create_plugin!(open_should_pass_handle_to_fd_operations_plugin,
+  open: |path: *const std::os::raw::c_char, flags: i32, fh: *mut u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/handle");
+    assert_eq!(flags, libc::O_RDONLY | libc::O_CLOEXEC);
+    unsafe { *fh = 42 };
+    return 0;
+  },
+  read: |
+    _path: *const std::os::raw::c_char,
+    _buf: *mut std::os::raw::c_char,
+    _size: u64,
+    _offset: i64,
+    fh: u64
+  | -> std::os::raw::c_int {
+    HANDLES.lock().unwrap().push(("read", fh));
+    return 0;
+  },
+  close: |_path: *const std::os::raw::c_char, fh: u64| -> std::os::raw::c_int {
+    HANDLES.lock().unwrap().push(("close", fh));
+    return 0;
+  }
+);
+
+#[test]
+fn open_should_pass_handle_to_fd_operations() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/handle").unwrap();
+      let fd = libc::syscall(syscall_nr!(open), path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC);
+      assert!(fd > 0);
+      let buf = [0u8; 4];
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, &buf, buf.len()), 0);
+      assert_eq!(libc::syscall(syscall_nr!(close), fd), 0);
+    };
+  });
+  let state = create_state!("/test", open_should_pass_handle_to_fd_operations_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(*HANDLES.lock().unwrap(), vec![("read", 42), ("close", 42)]);
+}
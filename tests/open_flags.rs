@@ -0,0 +1,151 @@
+#![cfg(target_arch="x86_64")]
+use std::{collections::HashMap, ffi::CString, sync::{LazyLock, Mutex}};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+static FILES: LazyLock<Mutex<HashMap<String, Vec<u8>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+create_plugin!(open_flags_plugin,
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    if FILES.lock().unwrap().contains_key(path) { 0 } else { -(raw::ENOENT as i32) }
+  },
+  read: |
+    path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    let files = FILES.lock().unwrap();
+    let data = files.get(path).unwrap();
+    let data = &data[(offset as usize).min(data.len())..];
+    let len = data.len().min(size as usize);
+    buf[..len].copy_from_slice(&data[..len]);
+    return len as i32;
+  },
+  write: |
+    path: *const std::os::raw::c_char,
+    buf: *const std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let buf = unsafe { std::slice::from_raw_parts(buf as *const u8, size as usize) };
+    let mut files = FILES.lock().unwrap();
+    let data = files.get_mut(path).unwrap();
+    let end = offset as usize + buf.len();
+    if data.len() < end {
+      data.resize(end, 0);
+    }
+    data[offset as usize..end].copy_from_slice(buf);
+    return size as i32;
+  },
+  getattr: |path: *const std::os::raw::c_char, stat: *mut raw::stat| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let stat = unsafe { stat.as_mut().unwrap() };
+    match FILES.lock().unwrap().get(path) {
+      Some(data) => {
+        stat.mode = raw::S_IFREG;
+        stat.size = data.len() as u64;
+        0
+      },
+      None => -(raw::ENOENT as i32)
+    }
+  },
+  create: |path: *const std::os::raw::c_char, _mode: u32| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    FILES.lock().unwrap().insert(path.to_string(), vec![]);
+    return 0;
+  },
+  truncate: |path: *const std::os::raw::c_char, size: i64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    FILES.lock().unwrap().get_mut(path).unwrap().resize(size as usize, 0);
+    return 0;
+  }
+);
+
+#[test]
+fn open_creat_should_create_file() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/creat").unwrap();
+      let fd = libc::syscall(syscall_nr!(open), path.as_ptr(), libc::O_WRONLY | libc::O_CREAT, 0o644);
+      assert!(fd > 0);
+      let res = libc::syscall(syscall_nr!(open), path.as_ptr(), libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL, 0o644);
+      assert_eq!(res, -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EEXIST);
+    };
+  });
+  let state = create_state!("/test", open_flags_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert!(FILES.lock().unwrap().contains_key("/creat"));
+}
+
+#[test]
+fn open_without_creat_should_cause_enoent() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/missing").unwrap();
+      let res = libc::syscall(syscall_nr!(open), path.as_ptr(), libc::O_WRONLY);
+      assert_eq!(res, -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::ENOENT);
+    };
+  });
+  let state = create_state!("/test", open_flags_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert!(!FILES.lock().unwrap().contains_key("/missing"));
+}
+
+#[test]
+fn open_trunc_should_truncate_file() {
+  FILES.lock().unwrap().insert("/trunc".to_string(), b"0123456789".to_vec());
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/trunc").unwrap();
+      let fd = libc::syscall(syscall_nr!(open), path.as_ptr(), libc::O_WRONLY | libc::O_TRUNC);
+      assert!(fd > 0);
+    };
+  });
+  let state = create_state!("/test", open_flags_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(FILES.lock().unwrap().get("/trunc").unwrap(), b"");
+}
+
+create_plugin!(open_writable_dir_plugin,
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    panic!("directory opened as a file");
+  },
+  getattr: |_path: *const std::os::raw::c_char, stat: *mut raw::stat| -> std::os::raw::c_int {
+    unsafe { stat.as_mut().unwrap() }.mode = raw::S_IFDIR;
+    return 0;
+  },
+  truncate: |_path: *const std::os::raw::c_char, _size: i64| -> std::os::raw::c_int {
+    panic!("directory truncated");
+  }
+);
+
+#[test]
+fn open_writable_dir_should_cause_eisdir() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/dir").unwrap();
+      for flags in [libc::O_WRONLY, libc::O_RDWR | libc::O_TRUNC] {
+        assert_eq!(libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), flags), -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EISDIR);
+      }
+    };
+  });
+  let state = create_state!("/test", open_writable_dir_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
@@ -0,0 +1,73 @@
+use std::{ffi::CString, sync::Mutex};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+static OPENAT_CLOSED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+create_plugin!(openat_should_allocate_fd_plugin,
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/openat");
+    return 0;
+  },
+  // Files left open are released when the tracee exits
+  close: |path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    OPENAT_CLOSED.lock().unwrap().push(path.to_string());
+    return 0;
+  }
+);
+
+#[test]
+fn openat_should_allocate_fd() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/openat").unwrap();
+      let open_fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(open_fd > 0);
+    };
+  });
+  let state = create_state!("/test", openat_should_allocate_fd_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(*OPENAT_CLOSED.lock().unwrap(), vec!["/openat"]);
+}
+
+static RELATIVE_CLOSED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+create_plugin!(openat_relative_to_mount_dirfd_should_resolve_plugin,
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert!(path == "/dir" || path == "/dir/openat", "unexpected path {}", path);
+    return 0;
+  },
+  // Files left open are released when the tracee exits
+  close: |path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    RELATIVE_CLOSED.lock().unwrap().push(path.to_string());
+    return 0;
+  }
+);
+
+#[test]
+fn openat_relative_to_mount_dirfd_should_resolve() {
+  let child = run_child!(move || {
+    unsafe {
+      let dirpath = CString::new("/test/dir").unwrap();
+      let dirfd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, dirpath.as_ptr(), libc::O_RDONLY);
+      assert!(dirfd > 0);
+      let path = CString::new("openat").unwrap();
+      let open_fd = libc::syscall(syscall_nr!(openat), dirfd, path.as_ptr(), libc::O_RDONLY);
+      assert!(open_fd > 0);
+    };
+  });
+  let state = create_state!("/test", openat_relative_to_mount_dirfd_should_resolve_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  let mut closed = RELATIVE_CLOSED.lock().unwrap().clone();
+  closed.sort();
+  assert_eq!(closed, vec!["/dir", "/dir/openat"]);
+}
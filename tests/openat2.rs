@@ -0,0 +1,77 @@
+use std::{ffi::CString, sync::Mutex};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+use typed_path::NativePathBuf;
+
+mod common;
+
+macro_rules! openat2 {
+  ($dirfd:expr, $path:expr, $resolve:expr) => {{
+    let path = CString::new($path).unwrap();
+    let how: [u64; 3] = [libc::O_RDONLY as u64, 0, $resolve];
+    libc::syscall(syscall_nr!(openat2), $dirfd, path.as_ptr(), &how, std::mem::size_of_val(&how))
+  }};
+}
+
+static OPENAT2_CLOSED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+create_plugin!(openat2_should_allocate_fd_plugin,
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/openat2");
+    return 0;
+  },
+  // Files left open are released when the tracee exits
+  close: |path: *const std::os::raw::c_char, _fh: u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    OPENAT2_CLOSED.lock().unwrap().push(path.to_string());
+    return 0;
+  }
+);
+
+#[test]
+fn openat2_should_allocate_fd() {
+  let child = run_child!(move || {
+    unsafe {
+      let open_fd = openat2!(libc::AT_FDCWD, "/test/openat2", 0);
+      assert!(open_fd > 0);
+    };
+  });
+  let state = create_state!("/test", openat2_should_allocate_fd_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(*OPENAT2_CLOSED.lock().unwrap(), vec!["/openat2"]);
+}
+
+create_plugin!(openat2_resolve_flags_should_be_honored_plugin, open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+  let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+  assert!(path == "/dir" || path == "/dir/openat2", "unexpected path {}", path);
+  return 0;
+});
+
+#[test]
+fn openat2_resolve_flags_should_be_honored() {
+  let child = run_child!(move || {
+    unsafe {
+      let dirfd = openat2!(libc::AT_FDCWD, "/test/dir", 0);
+      assert!(dirfd > 0);
+      let res = openat2!(dirfd, "../openat2", libc::RESOLVE_BENEATH);
+      assert_eq!(res, -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EXDEV);
+      let res = openat2!(dirfd, "/../openat2", libc::RESOLVE_IN_ROOT);
+      assert!(res > 0);
+      let res = openat2!(libc::AT_FDCWD, "/test/dir/openat2", libc::RESOLVE_NO_XDEV);
+      assert_eq!(res, -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EXDEV);
+      let res = openat2!(dirfd, "openat2", libc::RESOLVE_CACHED);
+      assert_eq!(res, -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EAGAIN);
+    };
+  });
+  let state = create_state!("/test", openat2_resolve_flags_should_be_honored_plugin, {
+    cwd: std::sync::RwLock::new(NativePathBuf::from("/"))
+  });
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
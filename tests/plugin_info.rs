@@ -0,0 +1,100 @@
+use std::{ffi::CString, mem::offset_of, sync::Mutex};
+use common::raw;
+use mountbox::{plugin::{Plugin, PluginError}, syscall_nr, tracer};
+use nix::{errno::Errno, libc};
+
+mod common;
+
+static FLAGS: Mutex<Vec<i32>> = Mutex::new(vec![]);
+
+create_plugin!(plugin_info_trunc_plugin, caps: raw::MOUNTBOX_CAP_ATOMIC_O_TRUNC,
+  open: |_path: *const std::os::raw::c_char, flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    FLAGS.lock().unwrap().push(flags);
+    return 0;
+  },
+  getattr: |_path: *const std::os::raw::c_char, stat: *mut raw::stat| -> std::os::raw::c_int {
+    unsafe { (*stat).mode = raw::S_IFREG | 0o644 };
+    return 0;
+  },
+  truncate: |_path: *const std::os::raw::c_char, _size: i64| -> std::os::raw::c_int {
+    return -(raw::EIO as i32);
+  }
+);
+
+static OLD_OPERATIONS: raw::mountbox_operations = raw::mountbox_operations {
+  getattr: Some(plugin_info_trunc_plugin_getattr),
+  ..common::raw::mountbox_operations::default()
+};
+
+/// Built against a header that ended before getattr
+#[unsafe(no_mangle)]
+#[allow(non_upper_case_globals)]
+pub static mut plugin_info_old_plugin: raw::mountbox_plugin_info = raw::mountbox_plugin_info {
+  abi_version: raw::MOUNTBOX_ABI_VERSION,
+  operations_size: offset_of!(raw::mountbox_operations, getattr) as u32,
+  capabilities: 0,
+  operations: &OLD_OPERATIONS
+};
+
+/// Sized to end within getattr
+#[unsafe(no_mangle)]
+#[allow(non_upper_case_globals)]
+pub static mut plugin_info_torn_plugin: raw::mountbox_plugin_info = raw::mountbox_plugin_info {
+  abi_version: raw::MOUNTBOX_ABI_VERSION,
+  operations_size: offset_of!(raw::mountbox_operations, getattr) as u32 + 4,
+  capabilities: 0,
+  operations: &OLD_OPERATIONS
+};
+
+#[unsafe(no_mangle)]
+#[allow(non_upper_case_globals)]
+pub static mut plugin_info_future_plugin: raw::mountbox_plugin_info = raw::mountbox_plugin_info {
+  abi_version: raw::MOUNTBOX_ABI_VERSION + 1,
+  operations_size: size_of::<raw::mountbox_operations>() as u32,
+  capabilities: 0,
+  operations: &OLD_OPERATIONS
+};
+
+#[test]
+fn plugin_info_should_reject_incompatible_plugins() {
+  assert_eq!(Plugin::load(&common::LIB, Some("plugin_info_missing_plugin")).err(),
+    Some(PluginError::MissingPlugin("plugin_info_missing_plugin".to_string())));
+  assert_eq!(Plugin::load(&common::LIB, Some("plugin_info_future_plugin")).err(),
+    Some(PluginError::AbiVersion(raw::MOUNTBOX_ABI_VERSION + 1)));
+}
+
+#[test]
+fn plugin_info_should_leave_operations_past_size_out() {
+  let plugin = Plugin::load(&common::LIB, Some("plugin_info_old_plugin")).unwrap();
+  assert_eq!(plugin.getattr("/").err(), Some(PluginError::Errno(Errno::ENOSYS)));
+  let plugin = Plugin::load(&common::LIB, Some("plugin_info_torn_plugin")).unwrap();
+  assert_eq!(plugin.getattr("/").err(), Some(PluginError::Errno(Errno::ENOSYS)));
+}
+
+#[test]
+fn plugin_info_missing_operation_should_cause_enosys() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/dir").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(mkdirat), libc::AT_FDCWD, path.as_ptr(), 0o755), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::ENOSYS);
+    };
+  });
+  let state = create_state!("/test", plugin_info_trunc_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn plugin_info_atomic_o_trunc_should_leave_truncation_to_open() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/file").unwrap();
+      assert!(libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_WRONLY | libc::O_TRUNC) >= 0);
+    };
+  });
+  let state = create_state!("/test", plugin_info_trunc_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(*FLAGS.lock().unwrap(), vec![libc::O_WRONLY | libc::O_TRUNC]);
+}
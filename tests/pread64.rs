@@ -0,0 +1,54 @@
+use std::ffi::CString;
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+create_plugin!(pread64_plugin,
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    return 0;
+  },
+  read: |
+    path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    assert_eq!(path, "/pread64");
+    let data = &b"0123456789"[(offset as usize).min(10)..];
+    let len = data.len().min(size as usize);
+    buf[..len].copy_from_slice(&data[..len]);
+    return len as i32;
+  },
+  getattr: |path: *const std::os::raw::c_char, stat: *mut raw::stat| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/pread64");
+    let stat = unsafe { stat.as_mut().unwrap() };
+    stat.mode = raw::S_IFREG;
+    stat.size = 10;
+    return 0;
+  }
+);
+
+#[test]
+fn pread64_should_not_move_offset() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/pread64").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let buf = [0u8; 3];
+      assert_eq!(libc::syscall(syscall_nr!(pread64), fd, &buf, buf.len(), 6), 3);
+      assert_eq!(&buf, b"678");
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, &buf, buf.len()), 3);
+      assert_eq!(&buf, b"012");
+    };
+  });
+  let state = create_state!("/test", pread64_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
@@ -1,42 +1,130 @@
-use std::io::{Read, Write};
+use std::ffi::CString;
 use common::raw;
 use mountbox::{syscall_nr, tracer};
 use nix::libc;
-use typed_path::NativePathBuf;
 
 mod common;
 
-create_plugin!(read_should_return_data_plugin, read: |
-  path: *const std::os::raw::c_char,
-  buf: *mut std::os::raw::c_char,
-  size: u64,
-  _offset: i64,
-  _fh: u64| -> std::os::raw::c_int {
+create_plugin!(read_should_return_data_plugin,
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    return 0;
+  },
+  read: |
+    path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    _offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
     let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
     let buf = unsafe { std::slice::from_raw_parts_mut(buf, size as usize) };
     assert_eq!(path, "/read");
     assert_eq!(size, 10);
     buf.fill(10);
     return 10;
-});
+  }
+);
 
 #[test]
 fn read_should_return_data() {
-  let (mut r, mut w) = std::io::pipe().unwrap();
   let child = run_child!(move || {
     unsafe {
-      let fd_buf = &mut [0u8; 8];
-      r.read(fd_buf).unwrap();
-      let fd = i64::from_ne_bytes(*fd_buf);
-      let buf = &[0u8; 10];
+      let path = CString::new("/test/read").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let buf = &mut [0u8; 10];
       let len = libc::syscall(syscall_nr!(read), fd, buf, 10);
       assert!(len == 10);
     };
   });
   let state = create_state!("/test", read_should_return_data_plugin);
-  let mount = state.mounts.get_mount(&NativePathBuf::from("/test")).unwrap();
-  let fd = mount.allocate_fd("/read", None).unwrap();
-  w.write(&fd.to_ne_bytes()).unwrap();
   let status = tracer::attach(state.clone(), child).unwrap();
   assert_eq!(status, tracer::TraceeStatus::Exited(0));
-}
\ No newline at end of file
+}
+create_plugin!(read_should_advance_offset_plugin,
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    return 0;
+  },
+  read: |
+    path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    assert_eq!(path, "/read");
+    let data = &b"0123456789"[(offset as usize).min(10)..];
+    let len = data.len().min(size as usize);
+    buf[..len].copy_from_slice(&data[..len]);
+    return len as i32;
+  }
+);
+
+#[test]
+fn read_should_advance_offset() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/read").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let buf = [0u8; 4];
+      let mut data: Vec<u8> = vec![];
+      loop {
+        let len = libc::syscall(syscall_nr!(read), fd, &buf, buf.len());
+        assert!(len >= 0);
+        if len == 0 {
+          break;
+        }
+        data.extend(&buf[..len as usize]);
+      }
+      assert_eq!(data, b"0123456789");
+    };
+  });
+  let state = create_state!("/test", read_should_advance_offset_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn read_across_unmapped_page_should_be_partial() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/read").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      // The last 4 bytes of a page followed by an unmapped one
+      let page = libc::mmap(std::ptr::null_mut(), 8192, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0) as *mut u8;
+      libc::munmap(page.add(4096) as *mut libc::c_void, 4096);
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, page.add(4092), 10), 4);
+      assert_eq!(std::slice::from_raw_parts(page.add(4092), 4), b"0123");
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, page.add(4096), 10), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EFAULT);
+      // Only the bytes that reached the buffer were consumed
+      let mut buf = [0u8; 10];
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), 10), 6);
+      assert_eq!(&buf[..6], b"456789");
+    };
+  });
+  let state = create_state!("/test", read_should_advance_offset_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn read_with_huge_count_should_be_clamped() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/read").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let mut buf = [0u8; 10];
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), 1usize << 40), 10);
+      assert_eq!(&buf, b"0123456789");
+    };
+  });
+  let state = create_state!("/test", read_should_advance_offset_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
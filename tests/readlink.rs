@@ -0,0 +1,130 @@
+#![cfg(target_arch="x86_64")]
+use std::{ffi::CString, mem::MaybeUninit, sync::Arc};
+use common::raw;
+use mountbox::{mounts::Mounts, plugin::Plugin, state::State, syscall_nr, tracer};
+use nix::libc;
+use typed_path::NativePathBuf;
+
+mod common;
+
+const LINKS: &[(&str, &str)] = &[
+  ("/link", "file"),
+  ("/dir/up", "../link"),
+  ("/loop", "loop"),
+  ("/host", "/tmp"),
+  ("/other", "/test2/file")
+];
+
+create_plugin!(links_plugin,
+  getattr: |path: *const std::os::raw::c_char, stat: *mut raw::stat| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let stat = unsafe { stat.as_mut().unwrap() };
+    if LINKS.iter().any(|(link, _)| *link == path) {
+      stat.mode = raw::S_IFLNK;
+    } else if path == "/file" {
+      stat.mode = raw::S_IFREG;
+      stat.size = 3;
+    } else if path == "/" || path == "/dir" {
+      stat.mode = raw::S_IFDIR;
+    } else {
+      return -(raw::ENOENT as i32);
+    }
+    return 0;
+  },
+  readlink: |path: *const std::os::raw::c_char, buf: *mut std::os::raw::c_char, size: u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    let (_, target) = LINKS.iter().find(|(link, _)| *link == path).unwrap();
+    buf[..target.len()].copy_from_slice(target.as_bytes());
+    return target.len() as i32;
+  }
+);
+
+fn create_state() -> Arc<State> {
+  let plugin = Arc::new(Plugin::load(&common::LIB, Some("links_plugin")).unwrap());
+  Arc::new(State {
+    mounts: Mounts::new(&[(NativePathBuf::from("/test"), plugin.clone()), (NativePathBuf::from("/test2"), plugin)]),
+    ..Default::default()
+  })
+}
+
+#[test]
+fn readlink_should_return_target() {
+  let child = run_child!(move || {
+    unsafe {
+      let buf = [0u8; 64];
+      let path = CString::new("/test/link").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(readlink), path.as_ptr(), &buf, buf.len()), 4);
+      assert_eq!(&buf[..5], b"file\0");
+      let path = CString::new("/test/dir/up").unwrap();
+      let buf = [0u8; 3];
+      assert_eq!(libc::syscall(syscall_nr!(readlinkat), libc::AT_FDCWD, path.as_ptr(), &buf, buf.len()), 3);
+      assert_eq!(&buf, b"../");
+      let path = CString::new("/test/file").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(readlink), path.as_ptr(), &buf, buf.len()), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EINVAL);
+    };
+  });
+  let status = tracer::attach(create_state(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn path_walk_should_follow_links() {
+  let child = run_child!(move || {
+    unsafe {
+      let cstat = MaybeUninit::<libc::stat>::zeroed().assume_init();
+      for path in ["/test/link", "/test/dir/up", "/test/other"] {
+        let path = CString::new(path).unwrap();
+        assert_eq!(libc::syscall(syscall_nr!(stat), path.as_ptr(), &cstat), 0);
+        assert_eq!(cstat.st_mode & libc::S_IFMT, libc::S_IFREG);
+        assert_eq!(cstat.st_size, 3);
+      }
+      let path = CString::new("/test/dir/up").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(lstat), path.as_ptr(), &cstat), 0);
+      assert_eq!(cstat.st_mode & libc::S_IFMT, libc::S_IFLNK);
+      // Links leading out of the mount are handed to the kernel
+      let path = CString::new("/test/host").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(stat), path.as_ptr(), &cstat), 0);
+      assert_eq!(cstat.st_mode & libc::S_IFMT, libc::S_IFDIR);
+      let path = CString::new("/test/loop").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(stat), path.as_ptr(), &cstat), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::ELOOP);
+    };
+  });
+  let status = tracer::attach(create_state(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn nofollow_should_not_follow_last_link() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/link").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(open), path.as_ptr(), libc::O_RDONLY | libc::O_NOFOLLOW), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::ELOOP);
+      let cstatx = MaybeUninit::<libc::statx>::zeroed().assume_init();
+      assert_eq!(libc::syscall(syscall_nr!(statx), libc::AT_FDCWD, path.as_ptr(), libc::AT_SYMLINK_NOFOLLOW, libc::STATX_BASIC_STATS, &cstatx), 0);
+      assert_eq!(cstatx.stx_mode as u32 & libc::S_IFMT, libc::S_IFLNK);
+      assert_eq!(libc::syscall(syscall_nr!(statx), libc::AT_FDCWD, path.as_ptr(), 0, libc::STATX_BASIC_STATS, &cstatx), 0);
+      assert_eq!(cstatx.stx_mode as u32 & libc::S_IFMT, libc::S_IFREG);
+    };
+  });
+  let status = tracer::attach(create_state(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn chdir_should_follow_links_to_host() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/host").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(chdir), path.as_ptr()), 0);
+      let buf = [0u8; 64];
+      assert_ne!(libc::syscall(syscall_nr!(getcwd), &buf, buf.len()), -1);
+      assert_eq!(&buf[..5], b"/tmp\0");
+    };
+  });
+  let status = tracer::attach(create_state(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
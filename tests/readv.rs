@@ -0,0 +1,63 @@
+use std::ffi::CString;
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+create_plugin!(readv_plugin,
+  open: |_path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    return 0;
+  },
+  read: |
+    path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    assert_eq!(path, "/readv");
+    let data = &b"0123456789"[(offset as usize).min(10)..];
+    let len = data.len().min(size as usize);
+    buf[..len].copy_from_slice(&data[..len]);
+    return len as i32;
+  },
+  getattr: |path: *const std::os::raw::c_char, stat: *mut raw::stat| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/readv");
+    let stat = unsafe { stat.as_mut().unwrap() };
+    stat.mode = raw::S_IFREG;
+    stat.size = 10;
+    return 0;
+  }
+);
+
+#[test]
+fn readv_should_scatter_data() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/readv").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let a = [0u8; 3];
+      let b = [0u8; 4];
+      let iov = [
+        libc::iovec { iov_base: a.as_ptr() as *mut libc::c_void, iov_len: a.len() },
+        libc::iovec { iov_base: b.as_ptr() as *mut libc::c_void, iov_len: b.len() }
+      ];
+      assert_eq!(libc::syscall(syscall_nr!(readv), fd, iov.as_ptr(), iov.len()), 7);
+      assert_eq!(&a, b"012");
+      assert_eq!(&b, b"3456");
+      assert_eq!(libc::syscall(syscall_nr!(preadv), fd, iov.as_ptr(), iov.len(), 1), 7);
+      assert_eq!(&a, b"123");
+      assert_eq!(&b, b"4567");
+      assert_eq!(libc::syscall(syscall_nr!(preadv2), fd, iov.as_ptr(), iov.len(), -1i64, 0, 0), 3);
+      assert_eq!(&a, b"789");
+    };
+  });
+  let state = create_state!("/test", readv_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
@@ -0,0 +1,150 @@
+use std::{ffi::CString, os::unix::net::UnixListener, path::PathBuf, sync::Arc, time::Duration};
+use mountbox::{mounts::Mounts, plugin::{filesystem::Result, remote::{self, Remote}, Filesystem}, state::State, syscall_nr, tracer};
+use nix::{errno::Errno, libc};
+use typed_path::NativePathBuf;
+
+mod common;
+
+/// A file whose content comes from the options of the mount, along with files misbehaving
+/// as a broken daemon would.
+struct DaemonFs {
+  content: String
+}
+
+impl DaemonFs {
+  fn new(opts: &[&str]) -> Result<DaemonFs> {
+    let content = opts.iter().find_map(|opt| opt.strip_prefix("content=")).ok_or(Errno::EINVAL)?;
+    Ok(DaemonFs { content: content.to_string() })
+  }
+}
+
+impl Filesystem for DaemonFs {
+  fn open(&self, path: &str, _flags: i32) -> Result<u64> {
+    match path {
+      "/file" | "/slow" => Ok(0),
+      "/crash" => panic!("daemon crashed"),
+      "/unknown" => Err(Errno::UnknownErrno),
+      _ => Err(Errno::ENOENT)
+    }
+  }
+
+  fn read(&self, path: &str, buf: &mut [u8], offset: i64, _fh: u64) -> Result<usize> {
+    if path == "/slow" {
+      std::thread::sleep(Duration::from_secs(1));
+    }
+    let data = &self.content.as_bytes()[(offset as usize).min(self.content.len())..];
+    let len = data.len().min(buf.len());
+    buf[..len].copy_from_slice(&data[..len]);
+    Ok(len)
+  }
+}
+
+/// Starts a daemon serving `DaemonFs` on a socket of its own.
+fn daemon(name: &str) -> PathBuf {
+  let path = std::env::temp_dir().join(format!("mountbox-remote-{name}-{}.sock", std::process::id()));
+  let _ = std::fs::remove_file(&path);
+  let listener = UnixListener::bind(&path).unwrap();
+  std::thread::spawn(move || remote::serve(listener, DaemonFs::new));
+  path
+}
+
+fn state(remote: Remote) -> Arc<State> {
+  Arc::new(State {
+    mounts: Mounts::new(&[(NativePathBuf::from("/remote"), Arc::new(remote))]),
+    ..Default::default()
+  })
+}
+
+#[test]
+fn remote_should_serve_mount_from_daemon() {
+  let path = daemon("serve");
+  assert_eq!(Remote::connect(&path, &[], remote::DEFAULT_TIMEOUT).err().and_then(|err| err.raw_os_error()), Some(libc::EINVAL));
+  let remote = Remote::connect(&path, &["content=served"], remote::DEFAULT_TIMEOUT).unwrap();
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/remote/file").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let mut buf = [0u8; 16];
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), buf.len()), 6);
+      assert_eq!(&buf[..6], b"served");
+      let missing = CString::new("/remote/missing").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, missing.as_ptr(), libc::O_RDONLY), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::ENOENT);
+      let unknown = CString::new("/remote/unknown").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, unknown.as_ptr(), libc::O_RDONLY), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EIO);
+    };
+  });
+  let status = tracer::attach(state(remote), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn remote_should_cause_eio_on_daemon_death_then_reconnect() {
+  let path = daemon("death");
+  let remote = Remote::connect(&path, &["content=again"], remote::DEFAULT_TIMEOUT).unwrap();
+  let child = run_child!(move || {
+    unsafe {
+      let crash = CString::new("/remote/crash").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, crash.as_ptr(), libc::O_RDONLY), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EIO);
+      let path = CString::new("/remote/file").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let mut buf = [0u8; 16];
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), buf.len()), 5);
+      assert_eq!(&buf[..5], b"again");
+    };
+  });
+  let status = tracer::attach(state(remote), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn remote_should_cause_ebadf_on_handle_from_lost_connection() {
+  let path = daemon("stale");
+  let remote = Remote::connect(&path, &["content=stale"], remote::DEFAULT_TIMEOUT).unwrap();
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/remote/file").unwrap();
+      let stale = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(stale >= 0);
+      let crash = CString::new("/remote/crash").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, crash.as_ptr(), libc::O_RDONLY), -1);
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let mut buf = [0u8; 16];
+      assert_eq!(libc::syscall(syscall_nr!(read), stale, buf.as_mut_ptr(), buf.len()), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EBADF);
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), buf.len()), 5);
+      assert_eq!(&buf[..5], b"stale");
+    };
+  });
+  let status = tracer::attach(state(remote), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+#[test]
+fn remote_should_cause_eio_on_timeout() {
+  let path = daemon("timeout");
+  let remote = Remote::connect(&path, &["content=late"], Duration::from_millis(100)).unwrap();
+  let child = run_child!(move || {
+    unsafe {
+      let slow = CString::new("/remote/slow").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, slow.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      let mut buf = [0u8; 16];
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), buf.len()), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EIO);
+      // Answered on a new connection, the late response being left behind
+      let path = CString::new("/remote/file").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, buf.as_mut_ptr(), buf.len()), 4);
+      assert_eq!(&buf[..4], b"late");
+    };
+  });
+  let status = tracer::attach(state(remote), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
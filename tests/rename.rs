@@ -0,0 +1,88 @@
+#![cfg(target_arch="x86_64")]
+use std::{ffi::CString, sync::{Arc, Mutex}};
+use common::raw;
+use mountbox::{mounts::Mounts, plugin::Plugin, state::State, syscall_nr, tracer};
+use nix::libc;
+use typed_path::NativePathBuf;
+
+mod common;
+
+static CALLS: Mutex<Vec<(String, String, u32)>> = Mutex::new(vec![]);
+
+create_plugin!(rename_plugin, caps: raw::MOUNTBOX_CAP_RENAME_FLAGS, rename: |
+  from: *const std::os::raw::c_char,
+  to: *const std::os::raw::c_char,
+  flags: u32| -> std::os::raw::c_int {
+    let from = unsafe { std::ffi::CStr::from_ptr(from).to_str().unwrap() };
+    let to = unsafe { std::ffi::CStr::from_ptr(to).to_str().unwrap() };
+    CALLS.lock().unwrap().push((from.to_string(), to.to_string(), flags));
+    return 0;
+});
+
+#[test]
+fn rename_within_mount_should_succeed() {
+  let child = run_child!(move || {
+    unsafe {
+      let from = CString::new("/test/a").unwrap();
+      let to = CString::new("/test/b").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(rename), from.as_ptr(), to.as_ptr()), 0);
+      assert_eq!(libc::syscall(syscall_nr!(renameat2), libc::AT_FDCWD, from.as_ptr(), libc::AT_FDCWD, to.as_ptr(), libc::RENAME_NOREPLACE), 0);
+      assert_eq!(libc::syscall(syscall_nr!(renameat2), libc::AT_FDCWD, from.as_ptr(), libc::AT_FDCWD, to.as_ptr(), libc::RENAME_NOREPLACE | libc::RENAME_EXCHANGE), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EINVAL);
+    };
+  });
+  let state = create_state!("/test", rename_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(*CALLS.lock().unwrap(), vec![
+    ("/a".to_string(), "/b".to_string(), 0),
+    ("/a".to_string(), "/b".to_string(), libc::RENAME_NOREPLACE)
+  ]);
+}
+
+#[test]
+fn rename_across_mounts_should_cause_exdev() {
+  let child = run_child!(move || {
+    unsafe {
+      let from = CString::new("/test/c").unwrap();
+      for to in ["/other/c", "/tmp/c"] {
+        let to = CString::new(to).unwrap();
+        assert_eq!(libc::syscall(syscall_nr!(rename), from.as_ptr(), to.as_ptr()), -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EXDEV);
+        assert_eq!(libc::syscall(syscall_nr!(rename), to.as_ptr(), from.as_ptr()), -1);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EXDEV);
+      }
+    };
+  });
+  let plugin = Arc::new(Plugin::load(&common::LIB, Some("rename_plugin")).unwrap());
+  let state = Arc::new(State {
+    mounts: Mounts::new(&[(NativePathBuf::from("/test"), plugin.clone()), (NativePathBuf::from("/other"), plugin)]),
+    ..Default::default()
+  });
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert!(!CALLS.lock().unwrap().iter().any(|(from, _, _)| from == "/c"));
+}
+
+create_plugin!(rename_flagless_plugin, rename: |
+  _from: *const std::os::raw::c_char,
+  _to: *const std::os::raw::c_char,
+  _flags: u32| -> std::os::raw::c_int {
+    return 0;
+});
+
+#[test]
+fn rename_with_flags_should_cause_einval_without_capability() {
+  let child = run_child!(move || {
+    unsafe {
+      let from = CString::new("/test/a").unwrap();
+      let to = CString::new("/test/b").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(renameat2), libc::AT_FDCWD, from.as_ptr(), libc::AT_FDCWD, to.as_ptr(), 0), 0);
+      assert_eq!(libc::syscall(syscall_nr!(renameat2), libc::AT_FDCWD, from.as_ptr(), libc::AT_FDCWD, to.as_ptr(), libc::RENAME_NOREPLACE), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EINVAL);
+    };
+  });
+  let state = create_state!("/test", rename_flagless_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
@@ -1,3 +1,4 @@
+#![cfg(target_arch="x86_64")]
 use std::{ffi::CString, mem::MaybeUninit};
 use common::raw;
 use mountbox::{syscall_nr, tracer};
@@ -13,9 +14,9 @@ create_plugin!(stat_should_return_stat_plugin, getattr: |
     let stat = unsafe { stat.as_mut().unwrap() };
     stat.mode = raw::S_IFREG;
     stat.size = 10;
-    stat.atime = -10;
-    stat.mtime = -10;
-    stat.ctime = -10;
+    stat.atime.sec = -10;
+    stat.mtime.sec = -10;
+    stat.ctime.sec = -10;
     return 0;
 });
 
@@ -37,4 +38,58 @@ fn stat_should_return_stat() {
   let state = create_state!("/test", stat_should_return_stat_plugin);
   let status = tracer::attach(state.clone(), child).unwrap();
   assert_eq!(status, tracer::TraceeStatus::Exited(0));
-}
\ No newline at end of file
+}
+create_plugin!(stat_should_return_full_attributes_plugin, getattr: |
+  path: *const std::os::raw::c_char,
+  stat: *mut raw::stat| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let stat = unsafe { stat.as_mut().unwrap() };
+    assert_eq!(stat.version, raw::MOUNTBOX_STAT_VERSION);
+    if path == "/defaults" {
+      stat.mode = raw::S_IFDIR;
+      return 0;
+    }
+    assert_eq!(path, "/full");
+    stat.mask = raw::MOUNTBOX_STAT_TYPE | raw::MOUNTBOX_STAT_MODE | raw::MOUNTBOX_STAT_UID | raw::MOUNTBOX_STAT_GID
+      | raw::MOUNTBOX_STAT_INO | raw::MOUNTBOX_STAT_NLINK | raw::MOUNTBOX_STAT_SIZE | raw::MOUNTBOX_STAT_MTIME;
+    stat.mode = raw::S_IFREG | 0o750;
+    stat.uid = 1234;
+    stat.gid = 5678;
+    stat.ino = 42;
+    stat.nlink = 3;
+    stat.size = 1000;
+    stat.mtime = raw::mountbox_timespec { sec: 1700000000, nsec: 123456789 };
+    return 0;
+});
+
+#[test]
+fn stat_should_return_full_attributes() {
+  let child = run_child!(move || {
+    unsafe {
+      let full = MaybeUninit::<libc::stat>::zeroed().assume_init();
+      let path = CString::new("/test/full").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(stat), path.as_ptr(), &full), 0);
+      assert_eq!(full.st_mode, libc::S_IFREG | 0o750);
+      assert_eq!((full.st_uid, full.st_gid), (1234, 5678));
+      assert_eq!((full.st_ino, full.st_nlink), (42, 3));
+      assert_eq!((full.st_size, full.st_blocks, full.st_blksize), (1000, 2, 4096));
+      assert_eq!((full.st_mtime, full.st_mtime_nsec), (1700000000, 123456789));
+      let defaults = MaybeUninit::<libc::stat>::zeroed().assume_init();
+      let path = CString::new("/test/defaults").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(stat), path.as_ptr(), &defaults), 0);
+      assert_eq!(defaults.st_mode, libc::S_IFDIR | 0o777);
+      assert_eq!((defaults.st_uid, defaults.st_gid), (libc::geteuid(), libc::getegid()));
+      assert_eq!(defaults.st_nlink, 2);
+      assert_ne!(defaults.st_ino, 0);
+      assert_ne!(defaults.st_ino, full.st_ino);
+      assert_eq!(defaults.st_dev, full.st_dev);
+      let host = MaybeUninit::<libc::stat>::zeroed().assume_init();
+      let path = CString::new("/").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(stat), path.as_ptr(), &host), 0);
+      assert_ne!(host.st_dev, full.st_dev);
+    };
+  });
+  let state = create_state!("/test", stat_should_return_full_attributes_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
@@ -13,9 +13,9 @@ create_plugin!(statx_should_return_stat_plugin, getattr: |
     let stat = unsafe { stat.as_mut().unwrap() };
     stat.mode = raw::S_IFREG;
     stat.size = 10;
-    stat.atime = -10;
-    stat.mtime = -10;
-    stat.ctime = -10;
+    stat.atime.sec = -10;
+    stat.mtime.sec = -10;
+    stat.ctime.sec = -10;
     return 0;
 });
 
@@ -25,7 +25,7 @@ fn statx_should_return_stat() {
     unsafe {
       let cpath = CString::new("/test/statx").unwrap();
       let cstatx = MaybeUninit::<nix::libc::statx>::zeroed().assume_init();
-      let res = libc::syscall(syscall_nr!(statx), libc::AT_FDCWD, cpath.as_ptr(), &cstatx);
+      let res = libc::syscall(syscall_nr!(statx), libc::AT_FDCWD, cpath.as_ptr(), 0, libc::STATX_BASIC_STATS, &cstatx);
       assert_eq!(res, 0);
       assert_eq!(cstatx.stx_mode as u32 & libc::S_IFMT, libc::S_IFREG);
       assert_eq!(cstatx.stx_size, 10);
@@ -37,4 +37,35 @@ fn statx_should_return_stat() {
   let state = create_state!("/test", statx_should_return_stat_plugin);
   let status = tracer::attach(state.clone(), child).unwrap();
   assert_eq!(status, tracer::TraceeStatus::Exited(0));
-}
\ No newline at end of file
+}
+create_plugin!(statx_should_report_provided_mask_plugin, getattr: |
+  _path: *const std::os::raw::c_char,
+  stat: *mut raw::stat| -> std::os::raw::c_int {
+    let stat = unsafe { stat.as_mut().unwrap() };
+    stat.mask = raw::MOUNTBOX_STAT_TYPE | raw::MOUNTBOX_STAT_MODE | raw::MOUNTBOX_STAT_INO | raw::MOUNTBOX_STAT_MTIME | raw::MOUNTBOX_STAT_BLKSIZE;
+    stat.mode = raw::S_IFREG | 0o600;
+    stat.ino = 7;
+    stat.blksize = 512;
+    stat.mtime = raw::mountbox_timespec { sec: 10, nsec: 20 };
+    return 0;
+});
+
+#[test]
+fn statx_should_report_provided_mask() {
+  let child = run_child!(move || {
+    unsafe {
+      let cpath = CString::new("/test/statx").unwrap();
+      let cstatx = MaybeUninit::<nix::libc::statx>::zeroed().assume_init();
+      let res = libc::syscall(syscall_nr!(statx), libc::AT_FDCWD, cpath.as_ptr(), 0, libc::STATX_ALL, &cstatx);
+      assert_eq!(res, 0);
+      assert_eq!(cstatx.stx_mask, libc::STATX_TYPE | libc::STATX_MODE | libc::STATX_INO | libc::STATX_MTIME);
+      assert_eq!(cstatx.stx_mode as u32, libc::S_IFREG | 0o600);
+      assert_eq!(cstatx.stx_ino, 7);
+      assert_eq!(cstatx.stx_blksize, 512);
+      assert_eq!((cstatx.stx_mtime.tv_sec, cstatx.stx_mtime.tv_nsec), (10, 20));
+    };
+  });
+  let state = create_state!("/test", statx_should_report_provided_mask_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
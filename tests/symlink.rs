@@ -0,0 +1,37 @@
+#![cfg(target_arch="x86_64")]
+use std::{ffi::CString, sync::Mutex};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+static CALLS: Mutex<Vec<(String, String)>> = Mutex::new(vec![]);
+
+create_plugin!(symlink_should_create_link_plugin, symlink: |
+  target: *const std::os::raw::c_char,
+  path: *const std::os::raw::c_char| -> std::os::raw::c_int {
+    let target = unsafe { std::ffi::CStr::from_ptr(target).to_str().unwrap() };
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    CALLS.lock().unwrap().push((target.to_string(), path.to_string()));
+    return 0;
+});
+
+#[test]
+fn symlink_should_create_link() {
+  let child = run_child!(move || {
+    unsafe {
+      let target = CString::new("../target").unwrap();
+      let path = CString::new("/test/link").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(symlink), target.as_ptr(), path.as_ptr()), 0);
+      assert_eq!(libc::syscall(syscall_nr!(symlinkat), target.as_ptr(), libc::AT_FDCWD, path.as_ptr()), 0);
+    };
+  });
+  let state = create_state!("/test", symlink_should_create_link_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(*CALLS.lock().unwrap(), vec![
+    ("../target".to_string(), "/link".to_string()),
+    ("../target".to_string(), "/link".to_string())
+  ]);
+}
@@ -6,12 +6,16 @@ use nix::{libc, sys::signal, unistd::Pid};
 mod common;
 
 create_plugin!(tracer_plugin_error_should_cause_syscall_errno_plugin,
-  open: |path: *const std::os::raw::c_char| -> std::os::raw::c_int {
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
     let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
     return match &path[1..] {
       "UNKNOWN" => i32::MIN,
       "EPERM" => -(raw::EPERM as i32),
       "ENOENT" => -(raw::ENOENT as i32),
+      "EACCES" => -(raw::EACCES as i32),
+      "EIO" => -(raw::EIO as i32),
+      "ENOSPC" => -(raw::ENOSPC as i32),
+      "EOUTOFRANGE" => -4096,
       _ => unreachable!()
     }
   }
@@ -24,15 +28,19 @@ fn tracer_plugin_error_should_cause_syscall_errno() {
       macro_rules! test_err {
         ($perr:tt, $serr:tt) => {
           let path = CString::new(format!("/test/{}", stringify!($perr))).unwrap();
-          let code = libc::syscall(syscall_nr!(open), path.as_ptr());
+          let code = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
           let errno = std::io::Error::last_os_error().raw_os_error().unwrap();
           assert_eq!(code, -1);
           assert_eq!(errno, nix::libc::$serr.into(), "unexpected errno {} for {} plugin error", errno, stringify!($perr));
         };
       }
-      test_err!(UNKNOWN, EPERM);
+      test_err!(UNKNOWN, EIO);
       test_err!(EPERM, EPERM);
       test_err!(ENOENT, ENOENT);
+      test_err!(EACCES, EACCES);
+      test_err!(EIO, EIO);
+      test_err!(ENOSPC, ENOSPC);
+      test_err!(EOUTOFRANGE, EIO);
     };
   });
   
@@ -47,7 +55,7 @@ create_plugin!(tracer_syscall_invalid_addr_should_cause_syscall_efault_plugin);
 fn tracer_syscall_invalid_addr_should_cause_syscall_efault() {
   let child = run_child!(|| {
     unsafe {
-      let code = libc::syscall(syscall_nr!(open), 0);
+      let code = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, 0, libc::O_RDONLY);
       let errno = std::io::Error::last_os_error().raw_os_error().unwrap();
       assert_eq!(code, -1);
       assert_eq!(errno, nix::libc::EFAULT);
@@ -66,7 +74,7 @@ create_plugin!(tracer_syscall_invalid_arg_should_cause_syscall_einval_plugin);
 fn tracer_syscall_invalid_arg_should_cause_syscall_einval() {
   let child = run_child!(|| {
     unsafe {
-      let code = libc::syscall(syscall_nr!(open), &[0xFF]); // Invalid utf8 path
+      let code = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, &[0xFF], libc::O_RDONLY); // Invalid utf8 path
       let errno = std::io::Error::last_os_error().raw_os_error().unwrap();
       assert_eq!(code, -1);
       assert_eq!(errno, nix::libc::EINVAL);
@@ -86,7 +94,7 @@ fn tracer_syscall_path_too_long_should_cause_syscall_enametoolong() {
   let child = run_child!(|| {
     unsafe {
       let very_long_str = CString::new("a".repeat(nix::libc::PATH_MAX as usize)).unwrap();
-      let code = libc::syscall(syscall_nr!(open), very_long_str.as_ptr()); // Invalid utf8 path
+      let code = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, very_long_str.as_ptr(), libc::O_RDONLY); // Invalid utf8 path
       let errno = std::io::Error::last_os_error().raw_os_error().unwrap();
       assert_eq!(code, -1);
       assert_eq!(errno, nix::libc::ENAMETOOLONG);
@@ -127,12 +135,18 @@ fn tracer_child_killed_should_return_signal() {
 }
 
 static PID_PIPE: OnceLock<(Mutex<PipeReader>, Mutex<PipeWriter>)> = OnceLock::new();
-create_plugin!(tracer_child_killed_in_syscall_should_return_signal_plugin, open: |_: *const std::os::raw::c_char| -> std::os::raw::c_int {
-  let mut buf = [0u8; 4];
-  PID_PIPE.get().unwrap().0.lock().unwrap().read(&mut buf).unwrap();
-  let pid = i32::from_ne_bytes(buf);
-  unsafe { libc::kill(pid, libc::SIGKILL) }
-});
+create_plugin!(tracer_child_killed_in_syscall_should_return_signal_plugin,
+  open: |_: *const std::os::raw::c_char, _: i32, _: *mut u64| -> std::os::raw::c_int {
+    let mut buf = [0u8; 4];
+    PID_PIPE.get().unwrap().0.lock().unwrap().read(&mut buf).unwrap();
+    let pid = i32::from_ne_bytes(buf);
+    unsafe { libc::kill(pid, libc::SIGKILL) }
+  },
+  // The handle is given back once the tracee is gone
+  close: |_: *const std::os::raw::c_char, _: u64| -> std::os::raw::c_int {
+    return 0;
+  }
+);
 
 #[test]
 fn tracer_child_killed_in_syscall_should_return_signal() {
@@ -142,10 +156,138 @@ fn tracer_child_killed_in_syscall_should_return_signal() {
   });
   let child = run_child!(|| {
     let path = CString::new(format!("/test/{}", stringify!($perr))).unwrap();
-    unsafe { libc::syscall(syscall_nr!(open), path.as_ptr()); }
+    unsafe { libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY); }
   });
   PID_PIPE.get().unwrap().1.lock().unwrap().write(&child.as_raw().to_ne_bytes()).unwrap();
   let state = create_state!("/test", tracer_child_killed_in_syscall_should_return_signal_plugin);
   let status = tracer::attach(state.clone(), child).unwrap();
   assert_eq!(status, tracer::TraceeStatus::Killed(signal::SIGKILL));
-}
\ No newline at end of file
+}
+create_plugin!(tracer_threads_should_be_traced_plugin,
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    assert_eq!(path, "/thread");
+    return 0;
+  },
+  read: |
+    _path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    _offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    buf.fill(b'x');
+    return size as i32;
+  }
+);
+
+#[test]
+fn tracer_threads_should_be_traced() {
+  let child = run_child!(|| {
+    // The main thread blocks in the kernel while the other one is served
+    let fd = std::thread::spawn(|| unsafe {
+      let path = CString::new("/test/thread").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd >= 0);
+      fd
+    }).join().unwrap();
+    // Threads share their fds
+    let buf = [0u8; 4];
+    assert_eq!(unsafe { libc::syscall(syscall_nr!(read), fd, &buf, buf.len()) }, 4);
+    assert_eq!(&buf, b"xxxx");
+  });
+  let state = create_state!("/test", tracer_threads_should_be_traced_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+create_plugin!(tracer_exit_group_from_thread_should_return_code_plugin);
+
+#[test]
+fn tracer_exit_group_from_thread_should_return_code() {
+  #[allow(irrefutable_let_patterns)]
+  let child = run_child!(|| {
+    std::thread::spawn(|| unsafe {
+      libc::syscall(syscall_nr!(exit_group), 7);
+    });
+    loop {
+      std::thread::park();
+    }
+  });
+  let state = create_state!("/test", tracer_exit_group_from_thread_should_return_code_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(7));
+}
+
+static HANDLED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+extern "C" fn handle_sigusr1(_: libc::c_int) {
+  HANDLED.store(true, std::sync::atomic::Ordering::SeqCst);
+}
+
+create_plugin!(tracer_signal_should_be_delivered_plugin);
+
+#[test]
+fn tracer_signal_should_be_delivered() {
+  let child = run_child!(|| {
+    unsafe { signal::signal(signal::SIGUSR1, signal::SigHandler::Handler(handle_sigusr1)) }.unwrap();
+    signal::raise(signal::SIGUSR1).unwrap();
+    assert!(HANDLED.load(std::sync::atomic::Ordering::SeqCst));
+  });
+  let state = create_state!("/test", tracer_signal_should_be_delivered_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+create_plugin!(tracer_fatal_signal_should_return_signal_plugin);
+
+#[test]
+fn tracer_fatal_signal_should_return_signal() {
+  let child = run_child!(|| {
+    signal::raise(signal::SIGTERM).unwrap();
+  });
+  let state = create_state!("/test", tracer_fatal_signal_should_return_signal_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Killed(signal::SIGTERM));
+}
+
+create_plugin!(tracer_stopped_child_should_be_continued_plugin);
+
+#[test]
+fn tracer_stopped_child_should_be_continued() {
+  let child = run_child!(|| {
+    unsafe {
+      match libc::fork() {
+        0 => {
+          signal::raise(signal::SIGSTOP).unwrap();
+          libc::_exit(3);
+        },
+        pid => {
+          let mut status = 0;
+          assert_eq!(libc::waitpid(pid, &mut status, libc::WUNTRACED), pid);
+          assert!(libc::WIFSTOPPED(status) && libc::WSTOPSIG(status) == libc::SIGSTOP);
+          signal::kill(Pid::from_raw(pid), signal::SIGCONT).unwrap();
+          assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+          assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 3);
+        }
+      }
+    }
+  });
+  let state = create_state!("/test", tracer_stopped_child_should_be_continued_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
+
+create_plugin!(tracer_privileged_child_should_gain_privileges_plugin);
+
+#[test]
+fn tracer_privileged_child_should_gain_privileges() {
+  let child = run_child!(|| {
+    // Left unset for setuid binaries to work, the tests running with CAP_SYS_ADMIN
+    assert_eq!(unsafe { libc::prctl(libc::PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) }, 0);
+  });
+  let state = create_state!("/test", tracer_privileged_child_should_gain_privileges_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+}
@@ -0,0 +1,43 @@
+#![cfg(target_arch="x86_64")]
+use std::{ffi::CString, sync::Mutex};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+static CALLS: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+create_plugin!(unlink_should_remove_entries_plugin,
+  unlink: |path: *const std::os::raw::c_char| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    CALLS.lock().unwrap().push(format!("unlink {}", path));
+    return 0;
+  },
+  rmdir: |path: *const std::os::raw::c_char| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    CALLS.lock().unwrap().push(format!("rmdir {}", path));
+    return 0;
+  }
+);
+
+#[test]
+fn unlink_should_remove_entries() {
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/file").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(unlink), path.as_ptr()), 0);
+      assert_eq!(libc::syscall(syscall_nr!(unlinkat), libc::AT_FDCWD, path.as_ptr(), 0), 0);
+      let path = CString::new("/test/dir").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(rmdir), path.as_ptr()), 0);
+      assert_eq!(libc::syscall(syscall_nr!(unlinkat), libc::AT_FDCWD, path.as_ptr(), libc::AT_REMOVEDIR), 0);
+      let path = CString::new("/test").unwrap();
+      assert_eq!(libc::syscall(syscall_nr!(rmdir), path.as_ptr()), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EBUSY);
+    };
+  });
+  let state = create_state!("/test", unlink_should_remove_entries_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(*CALLS.lock().unwrap(), vec!["unlink /file", "unlink /file", "rmdir /dir", "rmdir /dir"]);
+}
@@ -0,0 +1,174 @@
+use std::{collections::HashMap, ffi::CString, sync::{LazyLock, Mutex}};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+static FILES: LazyLock<Mutex<HashMap<String, Vec<u8>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+create_plugin!(write_plugin,
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    if FILES.lock().unwrap().contains_key(path) { 0 } else { -(raw::ENOENT as i32) }
+  },
+  read: |
+    path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    let files = FILES.lock().unwrap();
+    let data = files.get(path).unwrap();
+    let data = &data[(offset as usize).min(data.len())..];
+    let len = data.len().min(size as usize);
+    buf[..len].copy_from_slice(&data[..len]);
+    return len as i32;
+  },
+  write: |
+    path: *const std::os::raw::c_char,
+    buf: *const std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let buf = unsafe { std::slice::from_raw_parts(buf as *const u8, size as usize) };
+    let mut files = FILES.lock().unwrap();
+    let data = files.get_mut(path).unwrap();
+    let end = offset as usize + buf.len();
+    if data.len() < end {
+      data.resize(end, 0);
+    }
+    data[offset as usize..end].copy_from_slice(buf);
+    return size as i32;
+  },
+  getattr: |path: *const std::os::raw::c_char, stat: *mut raw::stat| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let stat = unsafe { stat.as_mut().unwrap() };
+    match FILES.lock().unwrap().get(path) {
+      Some(data) => {
+        stat.mode = raw::S_IFREG;
+        stat.size = data.len() as u64;
+        0
+      },
+      None => -(raw::ENOENT as i32)
+    }
+  },
+  create: |path: *const std::os::raw::c_char, _mode: u32| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    FILES.lock().unwrap().insert(path.to_string(), vec![]);
+    return 0;
+  },
+  truncate: |path: *const std::os::raw::c_char, size: i64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    FILES.lock().unwrap().get_mut(path).unwrap().resize(size as usize, 0);
+    return 0;
+  }
+);
+
+#[test]
+fn write_should_advance_offset() {
+  FILES.lock().unwrap().insert("/write".to_string(), vec![]);
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/write").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_WRONLY);
+      assert!(fd > 0);
+      assert_eq!(libc::syscall(syscall_nr!(write), fd, b"abc".as_ptr(), 3), 3);
+      assert_eq!(libc::syscall(syscall_nr!(write), fd, b"def".as_ptr(), 3), 3);
+      let buf = [0u8; 3];
+      assert_eq!(libc::syscall(syscall_nr!(read), fd, &buf, buf.len()), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EBADF);
+    };
+  });
+  let state = create_state!("/test", write_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(FILES.lock().unwrap().get("/write").unwrap(), b"abcdef");
+}
+
+#[test]
+fn write_on_read_only_fd_should_cause_ebadf() {
+  FILES.lock().unwrap().insert("/write_ro".to_string(), vec![]);
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/write_ro").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_RDONLY);
+      assert!(fd > 0);
+      assert_eq!(libc::syscall(syscall_nr!(write), fd, b"abc".as_ptr(), 3), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EBADF);
+    };
+  });
+  let state = create_state!("/test", write_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(FILES.lock().unwrap().get("/write_ro").unwrap(), b"");
+}
+
+#[test]
+fn write_append_should_write_at_end() {
+  FILES.lock().unwrap().insert("/write_append".to_string(), b"abc".to_vec());
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/write_append").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_WRONLY | libc::O_APPEND);
+      assert!(fd > 0);
+      assert_eq!(libc::syscall(syscall_nr!(write), fd, b"def".as_ptr(), 3), 3);
+      assert_eq!(libc::syscall(syscall_nr!(pwrite64), fd, b"ghi".as_ptr(), 3, 0), 3);
+    };
+  });
+  let state = create_state!("/test", write_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(FILES.lock().unwrap().get("/write_append").unwrap(), b"abcdefghi");
+}
+
+#[test]
+fn write_across_unmapped_page_should_be_partial() {
+  FILES.lock().unwrap().insert("/write_partial".to_string(), vec![]);
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/write_partial").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_WRONLY);
+      assert!(fd > 0);
+      // The last 3 bytes of a page followed by an unmapped one
+      let page = libc::mmap(std::ptr::null_mut(), 8192, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0) as *mut u8;
+      libc::munmap(page.add(4096) as *mut libc::c_void, 4096);
+      let buf = page.add(4093);
+      std::ptr::copy_nonoverlapping(b"abc".as_ptr(), buf, 3);
+      assert_eq!(libc::syscall(syscall_nr!(write), fd, buf, 10), 3);
+      assert_eq!(libc::syscall(syscall_nr!(write), fd, page.add(4096), 10), -1);
+      assert_eq!(std::io::Error::last_os_error().raw_os_error().unwrap(), libc::EFAULT);
+    };
+  });
+  let state = create_state!("/test", write_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(FILES.lock().unwrap().get("/write_partial").unwrap(), b"abc");
+}
+
+#[test]
+fn write_with_huge_count_should_be_clamped() {
+  FILES.lock().unwrap().insert("/write_huge".to_string(), vec![]);
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/write_huge").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_WRONLY);
+      assert!(fd > 0);
+      let page = libc::mmap(std::ptr::null_mut(), 8192, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0) as *mut u8;
+      libc::munmap(page.add(4096) as *mut libc::c_void, 4096);
+      let buf = page.add(4093);
+      std::ptr::copy_nonoverlapping(b"abc".as_ptr(), buf, 3);
+      assert_eq!(libc::syscall(syscall_nr!(write), fd, buf, 1usize << 40), 3);
+      assert_eq!(libc::syscall(syscall_nr!(pwrite64), fd, buf, 1usize << 40, 3), 3);
+    };
+  });
+  let state = create_state!("/test", write_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(FILES.lock().unwrap().get("/write_huge").unwrap(), b"abcabc");
+}
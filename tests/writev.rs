@@ -0,0 +1,92 @@
+use std::{collections::HashMap, ffi::CString, sync::{LazyLock, Mutex}};
+use common::raw;
+use mountbox::{syscall_nr, tracer};
+use nix::libc;
+
+mod common;
+
+static FILES: LazyLock<Mutex<HashMap<String, Vec<u8>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+create_plugin!(writev_plugin,
+  open: |path: *const std::os::raw::c_char, _flags: i32, _fh: *mut u64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    if FILES.lock().unwrap().contains_key(path) { 0 } else { -(raw::ENOENT as i32) }
+  },
+  read: |
+    path: *const std::os::raw::c_char,
+    buf: *mut std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    let files = FILES.lock().unwrap();
+    let data = files.get(path).unwrap();
+    let data = &data[(offset as usize).min(data.len())..];
+    let len = data.len().min(size as usize);
+    buf[..len].copy_from_slice(&data[..len]);
+    return len as i32;
+  },
+  write: |
+    path: *const std::os::raw::c_char,
+    buf: *const std::os::raw::c_char,
+    size: u64,
+    offset: i64,
+    _fh: u64
+  | -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let buf = unsafe { std::slice::from_raw_parts(buf as *const u8, size as usize) };
+    let mut files = FILES.lock().unwrap();
+    let data = files.get_mut(path).unwrap();
+    let end = offset as usize + buf.len();
+    if data.len() < end {
+      data.resize(end, 0);
+    }
+    data[offset as usize..end].copy_from_slice(buf);
+    return size as i32;
+  },
+  getattr: |path: *const std::os::raw::c_char, stat: *mut raw::stat| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    let stat = unsafe { stat.as_mut().unwrap() };
+    match FILES.lock().unwrap().get(path) {
+      Some(data) => {
+        stat.mode = raw::S_IFREG;
+        stat.size = data.len() as u64;
+        0
+      },
+      None => -(raw::ENOENT as i32)
+    }
+  },
+  create: |path: *const std::os::raw::c_char, _mode: u32| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    FILES.lock().unwrap().insert(path.to_string(), vec![]);
+    return 0;
+  },
+  truncate: |path: *const std::os::raw::c_char, size: i64| -> std::os::raw::c_int {
+    let path = unsafe { std::ffi::CStr::from_ptr(path).to_str().unwrap() };
+    FILES.lock().unwrap().get_mut(path).unwrap().resize(size as usize, 0);
+    return 0;
+  }
+);
+
+#[test]
+fn writev_should_gather_data() {
+  FILES.lock().unwrap().insert("/writev".to_string(), vec![]);
+  let child = run_child!(move || {
+    unsafe {
+      let path = CString::new("/test/writev").unwrap();
+      let fd = libc::syscall(syscall_nr!(openat), libc::AT_FDCWD, path.as_ptr(), libc::O_WRONLY);
+      assert!(fd > 0);
+      let iov = [
+        libc::iovec { iov_base: b"abc".as_ptr() as *mut libc::c_void, iov_len: 3 },
+        libc::iovec { iov_base: b"defg".as_ptr() as *mut libc::c_void, iov_len: 4 }
+      ];
+      assert_eq!(libc::syscall(syscall_nr!(writev), fd, iov.as_ptr(), iov.len()), 7);
+    };
+  });
+  let state = create_state!("/test", writev_plugin);
+  let status = tracer::attach(state.clone(), child).unwrap();
+  assert_eq!(status, tracer::TraceeStatus::Exited(0));
+  assert_eq!(FILES.lock().unwrap().get("/writev").unwrap(), b"abcdefg");
+}